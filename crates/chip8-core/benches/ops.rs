@@ -0,0 +1,32 @@
+// The instructions turbo mode and batch runs spend their time in. Run with
+// `cargo bench -p chip8-core` before and after touching them.
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use chip8_core::chip8::Chip8;
+use chip8_core::fontset;
+use chip8_core::quirks::Quirks;
+
+// Instructions per measured frame, about turbo speed.
+const CYCLES: usize = 10_000;
+
+// I = glyph 0, then draw it 15 rows tall, move right and repeat forever.
+const DRAW_LORES: [u8; 8] = [0xA0, 0x00, 0xD0, 0x1F, 0x70, 0x03, 0x12, 0x02];
+// The same after switching to 128x64.
+const DRAW_HIRES: [u8; 10] = [0x00, 0xFF, 0xA0, 0x00, 0xD0, 0x1F, 0x70, 0x03, 0x12, 0x04];
+// I = 0x300, then store V0 as decimal, count V0 up and repeat forever.
+const BCD: [u8; 8] = [0xA3, 0x00, 0xF0, 0x33, 0x70, 0x01, 0x12, 0x02];
+
+fn bench_rom(c: &mut Criterion, name: &str, rom: &[u8]) {
+    let mut machine = Chip8::new(&fontset::FONT_SET, Quirks::default());
+    machine.load_rom(rom);
+    c.bench_function(name, |b| b.iter(|| machine.run_frame(CYCLES)));
+}
+
+fn ops(c: &mut Criterion) {
+    bench_rom(c, "dxyn lores", &DRAW_LORES);
+    bench_rom(c, "dxyn hires", &DRAW_HIRES);
+    bench_rom(c, "fx33", &BCD);
+}
+
+criterion_group!(benches, ops);
+criterion_main!(benches);
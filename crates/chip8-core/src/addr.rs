@@ -0,0 +1,74 @@
+use std::fmt;
+
+// Number of return addresses the stack holds.
+pub const STACK_DEPTH: usize = 16;
+
+// An address in the machine's 16-bit address space. Arithmetic on it stays
+// within 16 bits; Memory decides what an address beyond the end of RAM means.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Addr(u16);
+
+impl Addr {
+    pub const fn new(value: u16) -> Self {
+        Addr(value)
+    }
+
+    // The address as an index into RAM.
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    pub fn wrapping_add(self, offset: u16) -> Addr {
+        Addr(self.0.wrapping_add(offset))
+    }
+
+    pub fn wrapping_sub(self, offset: u16) -> Addr {
+        Addr(self.0.wrapping_sub(offset))
+    }
+}
+
+impl fmt::LowerHex for Addr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::LowerHex::fmt(&self.0, f)
+    }
+}
+
+// The stack pointer: the number of return addresses on the stack, which is
+// also the slot the next call stores its return address in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct StackIdx(u8);
+
+impl StackIdx {
+    pub fn depth(self) -> usize {
+        self.0 as usize
+    }
+
+    // The pointer with `depth` addresses on the stack, or None if it can't hold that many.
+    pub fn from_depth(depth: usize) -> Option<StackIdx> {
+        if depth <= STACK_DEPTH {
+            Some(StackIdx(depth as u8))
+        } else {
+            None
+        }
+    }
+
+    // The pointer after a call, or None if the stack is full.
+    pub fn push(self) -> Option<StackIdx> {
+        if self.depth() < STACK_DEPTH {
+            Some(StackIdx(self.0 + 1))
+        } else {
+            None
+        }
+    }
+
+    // The pointer after a return, or None if the stack is empty.
+    pub fn pop(self) -> Option<StackIdx> {
+        self.0.checked_sub(1).map(StackIdx)
+    }
+}
+
+impl fmt::Display for StackIdx {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
@@ -0,0 +1,1225 @@
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeSet, VecDeque},
+    fmt,
+    hash::{Hash, Hasher},
+    time::Instant,
+};
+
+use crate::addr::{Addr, StackIdx, STACK_DEPTH};
+use crate::framebuffer::Framebuffer;
+use crate::keypad::Keypad;
+use crate::memory::{Layout, Memory};
+use crate::opstats::OpcodeStats;
+use crate::ports::HostPorts;
+use crate::profile::Profile;
+use crate::quirks::{KeyWait, LoadStore, Quirks};
+use crate::rng::Rng;
+use crate::savestate::SaveState;
+
+// vram always has room for SCHIP hires mode, on a single plane. In lores
+// mode every pixel covers a 2x2 block of it.
+pub const WIDTH: usize = 128;
+pub const HEIGHT: usize = 64;
+pub const LORES_WIDTH: usize = 64;
+pub const LORES_HEIGHT: usize = 32;
+// Screen height of two-page hi-res CHIP-8, which keeps the 64 pixel width.
+pub const TWO_PAGE_HEIGHT: usize = 64;
+
+// Two-page hi-res CHIP-8 programs start with a jump to 0x260 into the
+// interpreter patch, which the original loaded along with them. Their own
+// code starts at 0x2C0.
+const TWO_PAGE_SIGNATURE: [u8; 2] = [0x12, 0x60];
+const TWO_PAGE_START: Addr = Addr::new(0x2C0);
+
+// Number of RPL user flags FX75/FX85 can reach.
+pub const RPL_FLAGS: usize = 16;
+
+// Number of recently executed instructions kept for crash reports.
+const TRACE_LEN: usize = 100;
+
+// What the machine did during one cycle.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Status {
+    Running,
+    // FX0A is waiting for a key and will be repeated.
+    WaitingForKey,
+    // DXYN is waiting for the next vertical blank (display_wait quirk) and
+    // will be repeated.
+    WaitingForVblank,
+    // The program counter ran off the end of RAM.
+    Halted,
+    // A 1NNN jumped to its own address, the usual way for a program (and
+    // most test ROMs) to end: from here on only the timers change.
+    Finished { pc: Addr },
+    // 00FD asked to leave the interpreter. The program counter stays on it,
+    // so running on only reports this again.
+    Exited,
+    // An opcode no supported interpreter knows was skipped, reported the
+    // first time each one turns up under UnknownOpcodes::Warn.
+    UnknownOpcode { pc: Addr, op: u16 },
+}
+
+// What to do about opcodes the machine doesn't implement. Seeing them
+// usually means the ROM was written for another platform, like SCHIP.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnknownOpcodes {
+    // Skip them silently.
+    Ignore,
+    // Skip them, reporting each distinct one once.
+    Warn,
+    // Stop with Fault::UnknownOpcode.
+    Halt,
+}
+
+impl UnknownOpcodes {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "ignore" => Some(UnknownOpcodes::Ignore),
+            "warn" => Some(UnknownOpcodes::Warn),
+            "halt" => Some(UnknownOpcodes::Halt),
+            _ => None,
+        }
+    }
+}
+
+// Errors raised by the running program.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fault {
+    StackOverflow { pc: Addr },
+    StackUnderflow { pc: Addr },
+    UnknownOpcode { pc: Addr, op: u16 },
+    // A run of unknown opcodes long enough that the program counter has
+    // most likely wandered into data, starting at `pc`. `jump` is the last
+    // jump, call or return before it, where things probably went wrong.
+    Runaway { pc: Addr, jump: Option<Addr> },
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Fault::StackOverflow { pc } => write!(f, "stack overflow at {:#05x}", pc),
+            Fault::StackUnderflow { pc } => write!(f, "stack underflow at {:#05x}", pc),
+            Fault::UnknownOpcode { pc, op } => {
+                write!(f, "unknown opcode {:#06x} at {:#05x}", op, pc)
+            }
+            Fault::Runaway {
+                pc,
+                jump: Some(jump),
+            } => write!(
+                f,
+                "ran into data at {:#05x} after the jump at {:#05x}",
+                pc, jump
+            ),
+            Fault::Runaway { pc, jump: None } => write!(f, "ran into data at {:#05x}", pc),
+        }
+    }
+}
+
+// A consistent copy of the CPU registers, taken between instructions.
+#[derive(Clone, Copy, Debug, PartialEq, Hash)]
+pub struct CpuState {
+    pub pc: Addr,
+    pub op: u16,
+    pub ir: Addr,
+    pub sp: StackIdx,
+    pub registers: [u8; 16],
+    pub stack: [Addr; STACK_DEPTH],
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+}
+
+impl fmt::Display for CpuState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "pc={:#05x} op={:#06x} I={:#05x} sp={} DT={} ST={} V=",
+            self.pc, self.op, self.ir, self.sp, self.delay_timer, self.sound_timer
+        )?;
+        for v in self.registers.iter() {
+            write!(f, "{:02x}", v)?;
+        }
+        Ok(())
+    }
+}
+
+// Counters collected while running, for the post-run summary.
+#[derive(Clone, Debug, Default)]
+pub struct Stats {
+    pub instructions: u64,
+    pub unknown_opcodes: u64,
+    // distinct unknown opcodes seen
+    pub unknown_opcode_set: BTreeSet<u16>,
+    pub peak_stack_depth: usize,
+}
+
+// Cloning a machine snapshots its whole state, to branch off alternative runs.
+#[derive(Clone)]
+pub struct Chip8 {
+    pc: Addr,            // program counter
+    op: u16,             // current opcode (two bytes)
+    ir: Addr,            // index register
+    sp: StackIdx,        // stack pointer
+    delay_timer: u8,     // timer registers that count at 60 hz
+    sound_timer: u8,     // ^
+    registers: [u8; 16], // 15 general-purpose registers + carry
+    keypad: Keypad,      // current state of each key pressed
+    memory: Memory,
+    // RAM as it was right after the program was loaded, for reset()
+    loaded_memory: Memory,
+    vram: Framebuffer,
+    hires: bool,
+    // whether the program is for two-page hi-res CHIP-8 (64x64)
+    two_page: bool,
+    stack: [Addr; STACK_DEPTH],
+    // the HP48's RPL user flags, which FX75/FX85 save and restore registers
+    // to; they outlive resets like the calculator's did
+    rpl_flags: [u8; RPL_FLAGS],
+    // bumped every time vram changes
+    frame_version: u64,
+    waiting_for_key: bool,
+    // an FX0A is repeating until a key comes
+    key_wait_started: bool,
+    // whether a timer tick (the vertical blank) has come since the last
+    // DXYN under the display_wait quirk
+    vblank: bool,
+    waiting_for_vblank: bool,
+    unknown_opcodes: UnknownOpcodes,
+    // an unknown opcode to report from this cycle under UnknownOpcodes::Warn
+    new_unknown_opcode: Option<(Addr, u16)>,
+    // unknown opcodes in a row, and how many make a Fault::Runaway
+    unknown_streak: usize,
+    runaway_streak: Option<usize>,
+    fault: Option<Fault>,
+    quirks: Quirks,
+    rng: Rng,
+    stats: Stats,
+    // (address, opcode) of the last TRACE_LEN instructions, oldest first
+    trace: VecDeque<(Addr, u16)>,
+    // counters for the profiler overlay, while it is on
+    profile: Option<Profile>,
+    // counts and time per instruction pattern, while collected
+    opcode_stats: Option<OpcodeStats>,
+    // for each vram pixel, the DXYN number (counting from 1) that last drew
+    // to it since the tags were taken, while draw tagging is on
+    draw_tags: Option<Vec<u16>>,
+    draws: u16,
+    // the nonstandard host time and random ports, while enabled
+    ports: Option<HostPorts>,
+}
+
+impl Chip8 {
+    pub fn new(fontset: &[u8], quirks: Quirks) -> Self {
+        let memory = Memory::new(Layout::default(), fontset);
+
+        Self {
+            pc: memory.layout().start_pc,
+            op: 0x0,
+            ir: Addr::default(),
+            sp: StackIdx::default(),
+            loaded_memory: memory.clone(),
+            memory,
+            vram: Framebuffer::new(WIDTH, HEIGHT, 1),
+            hires: false,
+            two_page: false,
+            registers: [0; 16],
+            keypad: Keypad::default(),
+            delay_timer: 0,
+            sound_timer: 0,
+            stack: [Addr::default(); STACK_DEPTH],
+            rpl_flags: [0; RPL_FLAGS],
+            frame_version: 0,
+            waiting_for_key: false,
+            key_wait_started: false,
+            vblank: false,
+            waiting_for_vblank: false,
+            unknown_opcodes: UnknownOpcodes::Ignore,
+            new_unknown_opcode: None,
+            unknown_streak: 0,
+            runaway_streak: None,
+            fault: None,
+            quirks,
+            rng: Rng::from_entropy(),
+            stats: Stats::default(),
+            trace: VecDeque::with_capacity(TRACE_LEN),
+            profile: None,
+            opcode_stats: None,
+            draw_tags: None,
+            draws: 0,
+            ports: None,
+        }
+    }
+
+    // Load a program into RAM as it was at power-on, replacing any earlier
+    // one. Two-page hi-res programs are recognized by their first jump and
+    // run at 64x64 from their own start address. Returns how many bytes at
+    // the end didn't fit in RAM and were left out.
+    pub fn load_rom(&mut self, data: &[u8]) -> usize {
+        self.memory = self.loaded_memory.clone();
+        let dropped = self.memory.load_program(data);
+        self.loaded_memory = self.memory.clone();
+        self.two_page = data.starts_with(&TWO_PAGE_SIGNATURE);
+        self.pc = self.start_pc();
+        dropped
+    }
+
+    // Give the machine `size` bytes of RAM from the next load_rom on: 4 KB
+    // like the original interpreters, up to 64 KB for XO-CHIP programs.
+    pub fn set_ram_size(&mut self, size: usize) {
+        self.memory.resize(size);
+        self.loaded_memory.resize(size);
+    }
+
+    // Load programs at `addr` from the next load_rom on, for interpreters
+    // like the ETI-660 that don't use 0x200.
+    pub fn set_load_address(&mut self, addr: Addr) {
+        self.memory.set_start_pc(addr);
+        self.loaded_memory.set_start_pc(addr);
+    }
+
+    fn start_pc(&self) -> Addr {
+        if self.two_page {
+            TWO_PAGE_START
+        } else {
+            self.memory.layout().start_pc
+        }
+    }
+
+    // Size of the screen in the current mode, in the program's pixels.
+    pub fn screen_size(&self) -> (usize, usize) {
+        if self.hires {
+            (WIDTH, HEIGHT)
+        } else if self.two_page {
+            (LORES_WIDTH, TWO_PAGE_HEIGHT)
+        } else {
+            (LORES_WIDTH, LORES_HEIGHT)
+        }
+    }
+
+    // Size of one of the program's pixels in vram pixels, across and down.
+    pub fn pixel_size(&self) -> (usize, usize) {
+        (WIDTH / self.screen_size().0, HEIGHT / self.screen_size().1)
+    }
+
+    // Flip the program's pixel at `x`, `y` on the first plane, for
+    // experimenting from a debugger. Drawing code sees it like any other.
+    pub fn toggle_pixel(&mut self, x: usize, y: usize) {
+        let (bw, bh) = self.pixel_size();
+        for row in y * bh..(y + 1) * bh {
+            for col in x * bw..(x + 1) * bw {
+                self.vram.xor(0, col, row, 1);
+            }
+        }
+        self.frame_version = self.frame_version.wrapping_add(1);
+    }
+
+    // The RPL user flags, for keeping them between sessions.
+    pub fn rpl_flags(&self) -> [u8; RPL_FLAGS] {
+        self.rpl_flags
+    }
+
+    pub fn set_rpl_flags(&mut self, flags: [u8; RPL_FLAGS]) {
+        self.rpl_flags = flags;
+    }
+
+    // Whether the loaded program runs in two-page hi-res mode (64x64).
+    pub fn two_page(&self) -> bool {
+        self.two_page
+    }
+
+    // Read a byte of RAM, wrapping past the end like the opcodes do.
+    pub fn peek(&self, addr: Addr) -> u8 {
+        self.memory.read(addr)
+    }
+
+    // All of RAM, for snapshots and debugger views.
+    pub fn ram(&self) -> &[u8] {
+        self.memory.bytes()
+    }
+
+    // Write a byte of RAM while the machine runs, for cheats and trainers.
+    pub fn poke(&mut self, addr: Addr, value: u8) {
+        self.memory.write(addr, value);
+    }
+
+    // Overwrite RAM from `addr` on with `bytes`. Instructions are decoded
+    // from RAM every time they run, so a patched program takes effect from
+    // its next instruction, even when the patch covers the current one.
+    pub fn patch(&mut self, addr: Addr, bytes: &[u8]) {
+        for (offset, &byte) in bytes.iter().enumerate() {
+            self.memory.write(addr.wrapping_add(offset as u16), byte);
+        }
+    }
+
+    // Put the machine back into its power-on state, keeping the loaded
+    // program. RAM goes back to how it was loaded too, so a program that
+    // modified itself or the font starts out clean again.
+    pub fn reset(&mut self) {
+        self.memory = self.loaded_memory.clone();
+        self.pc = self.start_pc();
+        self.op = 0x0;
+        self.ir = Addr::default();
+        self.sp = StackIdx::default();
+        self.vram.clear();
+        self.hires = false;
+        self.registers = [0; 16];
+        self.keypad = Keypad::default();
+        self.delay_timer = 0;
+        self.sound_timer = 0;
+        self.stack = [Addr::default(); STACK_DEPTH];
+        self.frame_version = self.frame_version.wrapping_add(1);
+        self.waiting_for_key = false;
+        self.key_wait_started = false;
+        self.vblank = false;
+        self.waiting_for_vblank = false;
+        self.new_unknown_opcode = None;
+        self.unknown_streak = 0;
+        self.fault = None;
+        self.trace.clear();
+        if self.ports.is_some() {
+            self.ports = Some(HostPorts::new());
+        }
+    }
+
+    // Recently executed instructions as (address, opcode), oldest first.
+    pub fn trace(&self) -> impl Iterator<Item = &(Addr, u16)> {
+        self.trace.iter()
+    }
+
+    // Start or stop collecting profiler counters. Starting again begins from zero.
+    pub fn set_profiling(&mut self, on: bool) {
+        self.profile = if on { Some(Profile::default()) } else { None };
+    }
+
+    pub fn profile_mut(&mut self) -> Option<&mut Profile> {
+        self.profile.as_mut()
+    }
+
+    // Start or stop counting and timing instructions by pattern.
+    pub fn set_opcode_stats(&mut self, on: bool) {
+        self.opcode_stats = if on {
+            Some(OpcodeStats::default())
+        } else {
+            None
+        };
+    }
+
+    pub fn opcode_stats(&self) -> Option<&OpcodeStats> {
+        self.opcode_stats.as_ref()
+    }
+
+    // Start or stop tagging vram pixels with the sprite draw that last touched them.
+    pub fn set_draw_tagging(&mut self, on: bool) {
+        self.draw_tags = if on {
+            Some(vec![0; WIDTH * HEIGHT])
+        } else {
+            None
+        };
+        self.draws = 0;
+    }
+
+    // The draw tags collected since the last call, row by row, after which
+    // counting starts again from the first draw.
+    pub fn take_draw_tags(&mut self) -> Option<Vec<u16>> {
+        let tags = self.draw_tags.as_mut()?;
+        let taken = std::mem::replace(tags, vec![0; WIDTH * HEIGHT]);
+        self.draws = 0;
+        Some(taken)
+    }
+
+    // Map the host time and random ports into RAM. See ports.rs.
+    pub fn set_host_ports(&mut self, on: bool) {
+        self.ports = if on { Some(HostPorts::new()) } else { None };
+    }
+
+    pub fn set_unknown_opcodes(&mut self, policy: UnknownOpcodes) {
+        self.unknown_opcodes = policy;
+    }
+
+    // Fault with Fault::Runaway after `streak` unknown opcodes in a row, or
+    // never with None.
+    pub fn set_runaway_streak(&mut self, streak: Option<usize>) {
+        self.runaway_streak = streak;
+    }
+
+    // Make CXKK produce the same sequence on every run.
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = Rng::from_seed(seed);
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    // Quirks are looked up on every instruction, so changes apply immediately.
+    pub fn quirks_mut(&mut self) -> &mut Quirks {
+        &mut self.quirks
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    pub fn cpu_state(&self) -> CpuState {
+        CpuState {
+            pc: self.pc,
+            op: self.op,
+            ir: self.ir,
+            sp: self.sp,
+            registers: self.registers,
+            stack: self.stack,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+        }
+    }
+
+    // The machine's state, to save to disk and load back later.
+    pub fn save_state(&self) -> SaveState {
+        SaveState {
+            cpu: self.cpu_state(),
+            hires: self.hires,
+            ram: self.memory.bytes().to_vec(),
+            vram_size: (self.vram.width(), self.vram.height()),
+            vram: self.vram.pixels().to_vec(),
+        }
+    }
+
+    // Put the machine back into a saved state. The state must come from a
+    // machine of the same size; quirks and the rest of the settings stay.
+    pub fn load_state(&mut self, state: &SaveState) -> Result<(), String> {
+        if state.ram.len() != self.ram().len() {
+            return Err(format!(
+                "Save state has {} bytes of RAM, not {}",
+                state.ram.len(),
+                self.ram().len()
+            ));
+        }
+        if state.vram_size != (self.vram.width(), self.vram.height()) {
+            return Err("Save state has a different screen size".to_string());
+        }
+        let cpu = &state.cpu;
+        self.pc = cpu.pc;
+        self.op = cpu.op;
+        self.ir = cpu.ir;
+        self.sp = cpu.sp;
+        self.registers = cpu.registers;
+        self.stack = cpu.stack;
+        self.delay_timer = cpu.delay_timer;
+        self.sound_timer = cpu.sound_timer;
+        self.hires = state.hires;
+        self.memory.set_bytes(&state.ram);
+        self.vram.set_pixels(&state.vram);
+        self.frame_version = self.frame_version.wrapping_add(1);
+        self.waiting_for_key = false;
+        self.key_wait_started = false;
+        self.waiting_for_vblank = false;
+        self.fault = None;
+        self.trace.clear();
+        Ok(())
+    }
+
+    pub fn set_keypad(&mut self, state: [bool; 16]) {
+        self.keypad.update(state);
+    }
+
+    pub fn vram(&self) -> &Framebuffer {
+        &self.vram
+    }
+
+    // Whether the SCHIP 128x64 mode is active.
+    pub fn hires(&self) -> bool {
+        self.hires
+    }
+
+    // A number that changes whenever vram does. Frontends compare it with the
+    // version they last drew to tell whether there is anything new to show,
+    // however many changes happened in between.
+    pub fn frame_version(&self) -> u64 {
+        self.frame_version
+    }
+
+    // Count both timers down by one 60 Hz tick. The tick doubles as the
+    // vertical blank that DXYN waits for under the display_wait quirk.
+    pub fn tick_timers(&mut self) {
+        self.vblank = true;
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+        if let Some(ports) = &mut self.ports {
+            ports.tick();
+        }
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn sound_active(&self) -> bool {
+        self.sound_timer > 0
+    }
+
+    // Hash of everything that determines how the machine runs from here on.
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.cpu_state().hash(&mut hasher);
+        self.memory.hash(&mut hasher);
+        self.vram.hash(&mut hasher);
+        self.hires.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    // Run one 60 Hz frame without a frontend: `cycles` instructions, then a
+    // timer tick. A DXYN waiting for the vertical blank or a finished program
+    // ends the frame early, since either would only repeat until the tick.
+    pub fn run_frame(&mut self, cycles: usize) -> Result<Status, Fault> {
+        let mut status = Status::Running;
+        for _ in 0..cycles {
+            status = self.cycle()?;
+            if matches!(
+                status,
+                Status::Halted
+                    | Status::WaitingForVblank
+                    | Status::Finished { .. }
+                    | Status::Exited
+            ) {
+                break;
+            }
+        }
+        self.tick_timers();
+        Ok(status)
+    }
+
+    // Execute a single instruction.
+    pub fn cycle(&mut self) -> Result<Status, Fault> {
+        if self.pc.index() + 1 >= self.ram().len() {
+            return Ok(Status::Halted);
+        }
+
+        if let Some(ports) = &mut self.ports {
+            ports.refresh(&mut self.memory);
+        }
+        let pc = self.pc;
+        let unknown_opcodes = self.stats.unknown_opcodes;
+        self.fetch();
+        // timing every instruction costs, so only while asked to
+        if self.opcode_stats.is_some() {
+            let start = Instant::now();
+            self.decode_execute();
+            let time = start.elapsed();
+            if let Some(stats) = &mut self.opcode_stats {
+                stats.record(self.op, time);
+            }
+        } else {
+            self.decode_execute();
+        }
+        self.stats.instructions += 1;
+        if self.stats.unknown_opcodes == unknown_opcodes {
+            self.unknown_streak = 0;
+        }
+
+        if let Some(fault) = self.fault.take() {
+            return Err(fault);
+        }
+        if std::mem::replace(&mut self.waiting_for_key, false) {
+            return Ok(Status::WaitingForKey);
+        }
+        if std::mem::replace(&mut self.waiting_for_vblank, false) {
+            return Ok(Status::WaitingForVblank);
+        }
+        if let Some((pc, op)) = self.new_unknown_opcode.take() {
+            return Ok(Status::UnknownOpcode { pc, op });
+        }
+        if self.op & 0xF000 == 0x1000 && self.pc == pc {
+            return Ok(Status::Finished { pc: self.pc });
+        }
+        if self.op == 0x00FD {
+            return Ok(Status::Exited);
+        }
+        Ok(Status::Running)
+    }
+
+    fn fetch(&mut self) {
+        self.op = (self.memory.read(self.pc) as u16) << 8
+            | self.memory.read(self.pc.wrapping_add(1)) as u16;
+        if self.trace.len() == TRACE_LEN {
+            self.trace.pop_front();
+        }
+        self.trace.push_back((self.pc, self.op));
+        if let Some(profile) = &mut self.profile {
+            profile.record_pc(self.pc);
+        }
+        self.pc = self.pc.wrapping_add(2);
+    }
+
+    fn decode_execute(&mut self) {
+        let hex = (
+            ((self.op & 0xF000) >> 12) as u8,
+            ((self.op & 0x0F00) >> 8) as u8,
+            ((self.op & 0x00F0) >> 4) as u8,
+            (self.op & 0x000F) as u8,
+        );
+
+        let nnn = self.op & 0x0FFF;
+        let lower_byte = (self.op & 0xFF) as u8;
+        let x = hex.1 as usize;
+        let y = hex.2 as usize;
+        let n = hex.3 as usize;
+
+        match hex {
+            // CLS
+            (0x00, 0x00, 0x0e, 0x00) => self.op_00e0(),
+            // CLS in the two-page hi-res interpreter
+            (0x00, 0x02, 0x03, 0x00) if self.two_page => self.op_00e0(),
+            // RET
+            (0x00, 0x00, 0x0e, 0x0e) => self.op_00ee(),
+            // SCD nibble (SCHIP)
+            (0x00, 0x00, 0x0c, _) => self.op_00cn(n),
+            // SCR (SCHIP)
+            (0x00, 0x00, 0x0f, 0x0b) => self.op_00fb(),
+            // SCL (SCHIP)
+            (0x00, 0x00, 0x0f, 0x0c) => self.op_00fc(),
+            // EXIT (SCHIP)
+            (0x00, 0x00, 0x0f, 0x0d) => self.op_00fd(),
+            // LOW (SCHIP)
+            (0x00, 0x00, 0x0f, 0x0e) => self.op_00fe(),
+            // HIGH (SCHIP)
+            (0x00, 0x00, 0x0f, 0x0f) => self.op_00ff(),
+            // JP addr
+            (0x01, _, _, _) => self.op_1nnn(nnn),
+            // CALL addr
+            (0x02, _, _, _) => self.op_2nnn(nnn),
+            // SE Vx, byte
+            (0x03, _, _, _) => self.op_3xkk(x, lower_byte),
+            // SNE Vx, byte
+            (0x04, _, _, _) => self.op_4xkk(x, lower_byte),
+            // SE Vx, Vy
+            (0x05, _, _, 0x00) => self.op_5xy0(x, y),
+            // LD Vx, byte
+            (0x06, _, _, _) => self.op_6xkk(x, lower_byte),
+            // ADD Vx, byte
+            (0x07, _, _, _) => self.op_7xkk(x, lower_byte),
+            // LD Vx, Vy
+            (0x08, _, _, 0x00) => self.op_8xy0(x, y),
+            // OR Vx, Vy
+            (0x08, _, _, 0x01) => self.op_8xy1(x, y),
+            // AND Vx, Vy
+            (0x08, _, _, 0x02) => self.op_8xy2(x, y),
+            // XOR Vx, Vy
+            (0x08, _, _, 0x03) => self.op_8xy3(x, y),
+            // ADD Vx, Vy
+            (0x08, _, _, 0x04) => self.op_8xy4(x, y),
+            // SUB Vx, Vy
+            (0x08, _, _, 0x05) => self.op_8xy5(x, y),
+            // SHR Vx {, Vy}
+            (0x08, _, _, 0x06) => self.op_8xy6(x, y),
+            // SUBN Vx, Vy
+            (0x08, _, _, 0x07) => self.op_8xy7(x, y),
+            // SHL Vx {, Vy}
+            (0x08, _, _, 0x0e) => self.op_8xye(x, y),
+            // SNE Vx, Vy
+            (0x09, _, _, 0x00) => self.op_9xy0(x, y),
+            // LD I, addr
+            (0x0a, _, _, _) => self.op_annn(nnn),
+            // JP V0, addr
+            (0x0b, _, _, _) => self.op_bnnn(nnn),
+            // RND Vx, byte
+            (0x0c, _, _, _) => self.op_cxkk(x, lower_byte),
+            // DRW Vx, Vy, nibble
+            (0x0d, _, _, _) => self.op_dxyn(x, y, n),
+            // SKP Vx
+            (0x0e, _, 0x09, 0x0e) => self.op_ex9e(x),
+            // SKNP Vx
+            (0x0e, _, 0x0a, 0x01) => self.op_exa1(x),
+            // LD Vx, DT
+            (0x0f, _, 0x00, 0x07) => self.op_fx07(x),
+            // LD Vx, K
+            (0x0f, _, 0x00, 0x0a) => self.op_fx0a(x),
+            // LD DT, Vx
+            (0x0f, _, 0x01, 0x05) => self.op_fx15(x),
+            // LD ST, Vx
+            (0x0f, _, 0x01, 0x08) => self.op_fx18(x),
+            // ADD I, Vx
+            (0x0f, _, 0x01, 0x0e) => self.op_fx1e(x),
+            // LD F, Vx
+            (0x0f, _, 0x02, 0x09) => self.op_fx29(x),
+            // LD HF, Vx (SCHIP)
+            (0x0f, _, 0x03, 0x00) => self.op_fx30(x),
+            // LD B, Vx
+            (0x0f, _, 0x03, 0x03) => self.op_fx33(x),
+            // LD [I], Vx
+            (0x0f, _, 0x05, 0x05) => self.op_fx55(x),
+            // LD Vx, [I]
+            (0x0f, _, 0x06, 0x05) => self.op_fx65(x),
+            // LD R, Vx (SCHIP)
+            (0x0f, _, 0x07, 0x05) => self.op_fx75(x),
+            // LD Vx, R (SCHIP)
+            (0x0f, _, 0x08, 0x05) => self.op_fx85(x),
+            // NOP
+            _ => self.op_unknown(),
+        }
+    }
+
+    // Count an opcode nothing implements and deal with it as the
+    // UnknownOpcodes policy says.
+    fn op_unknown(&mut self) {
+        self.stats.unknown_opcodes += 1;
+        let first_time = self.stats.unknown_opcode_set.insert(self.op);
+        let pc = self.pc.wrapping_sub(2);
+        match self.unknown_opcodes {
+            UnknownOpcodes::Ignore => (),
+            UnknownOpcodes::Warn if first_time => {
+                self.new_unknown_opcode = Some((pc, self.op));
+            }
+            UnknownOpcodes::Warn => (),
+            UnknownOpcodes::Halt => {
+                self.fault = Some(Fault::UnknownOpcode { pc, op: self.op });
+            }
+        }
+
+        self.unknown_streak += 1;
+        if self.runaway_streak == Some(self.unknown_streak) {
+            self.fault = Some(self.runaway());
+        }
+    }
+
+    // The Fault::Runaway for the current streak of unknown opcodes, which
+    // ends with the newest trace entry.
+    fn runaway(&self) -> Fault {
+        let before = self.trace.len().saturating_sub(self.unknown_streak);
+        let pc = self.trace.get(before).map_or(self.pc, |&(pc, _)| pc);
+        let jump = self
+            .trace
+            .iter()
+            .take(before)
+            .rev()
+            .find(|&&(_, op)| matches!(op & 0xF000, 0x1000 | 0x2000 | 0xB000) || op == 0x00EE)
+            .map(|&(pc, _)| pc);
+        Fault::Runaway { pc, jump }
+    }
+
+    // thanks cowgod!!! http://devernay.free.fr/hacks/chip8/C8TECH10.HTM
+    // and https://github.com/starrhorne/chip8-rust/
+
+    // Clear the display.
+    fn op_00e0(&mut self) {
+        self.vram.clear();
+        self.frame_version = self.frame_version.wrapping_add(1);
+    }
+
+    // Scroll the display down n pixels. Like modern interpreters, and unlike
+    // SCHIP 1.1, lores mode scrolls by its own pixels rather than by half.
+    fn op_00cn(&mut self, n: usize) {
+        self.scroll(0, n as isize);
+    }
+
+    // Scroll the display right 4 pixels.
+    fn op_00fb(&mut self) {
+        self.scroll(4, 0);
+    }
+
+    // Scroll the display left 4 pixels.
+    fn op_00fc(&mut self) {
+        self.scroll(-4, 0);
+    }
+
+    // Scroll by `dx`, `dy` of the program's pixels.
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let (bw, bh) = self.pixel_size();
+        self.vram.scroll(dx * bw as isize, dy * bh as isize);
+        self.frame_version = self.frame_version.wrapping_add(1);
+    }
+
+    // Switch to 64x32 lores mode.
+    fn op_00fe(&mut self) {
+        self.set_hires(false);
+    }
+
+    // Exit the interpreter: stay on this instruction for cycle() to report.
+    fn op_00fd(&mut self) {
+        self.pc = self.pc.wrapping_sub(2);
+    }
+
+    // Switch to 128x64 hires mode.
+    fn op_00ff(&mut self) {
+        self.set_hires(true);
+    }
+
+    fn set_hires(&mut self, hires: bool) {
+        if hires != self.hires && self.quirks.mode_switch_clears {
+            self.vram.clear();
+        }
+        self.hires = hires;
+        self.frame_version = self.frame_version.wrapping_add(1);
+    }
+
+    // Return from a subroutine.
+    fn op_00ee(&mut self) {
+        match self.sp.pop() {
+            Some(sp) => {
+                self.sp = sp;
+                self.pc = self.stack[sp.depth()];
+            }
+            None => {
+                self.fault = Some(Fault::StackUnderflow {
+                    pc: self.pc.wrapping_sub(2),
+                })
+            }
+        }
+    }
+
+    // Jump to location nnn.
+    fn op_1nnn(&mut self, nnn: u16) {
+        self.pc = Addr::new(nnn);
+    }
+
+    // Call subroutine at nnn.
+    fn op_2nnn(&mut self, nnn: u16) {
+        match self.sp.push() {
+            Some(sp) => {
+                self.stack[self.sp.depth()] = self.pc;
+                self.sp = sp;
+                self.stats.peak_stack_depth = self.stats.peak_stack_depth.max(sp.depth());
+                self.pc = Addr::new(nnn);
+            }
+            None => {
+                self.fault = Some(Fault::StackOverflow {
+                    pc: self.pc.wrapping_sub(2),
+                })
+            }
+        }
+    }
+
+    // Skip next instruction if Vx = kk.
+    fn op_3xkk(&mut self, x: usize, kk: u8) {
+        if self.registers[x] == kk {
+            self.pc = self.pc.wrapping_add(2);
+        }
+    }
+
+    // Skip next instruction if Vx != kk.
+    fn op_4xkk(&mut self, x: usize, kk: u8) {
+        if self.registers[x] != kk {
+            self.pc = self.pc.wrapping_add(2);
+        }
+    }
+
+    // Skip next instruction if Vx = Vy.
+    fn op_5xy0(&mut self, x: usize, y: usize) {
+        if self.registers[x] == self.registers[y] {
+            self.pc = self.pc.wrapping_add(2);
+        }
+    }
+
+    // Set Vx = kk.
+    fn op_6xkk(&mut self, x: usize, kk: u8) {
+        self.registers[x] = kk;
+    }
+
+    // Set Vx = Vx + kk.
+    fn op_7xkk(&mut self, x: usize, kk: u8) {
+        self.registers[x] = self.registers[x].wrapping_add(kk);
+    }
+
+    // Set Vx = Vy.
+    fn op_8xy0(&mut self, x: usize, y: usize) {
+        self.registers[x] = self.registers[y];
+    }
+
+    // Set Vx = Vx OR Vy.
+    fn op_8xy1(&mut self, x: usize, y: usize) {
+        self.registers[x] |= self.registers[y];
+        self.reset_vf_after_logic();
+    }
+
+    // Set Vx = Vx AND Vy.
+    fn op_8xy2(&mut self, x: usize, y: usize) {
+        self.registers[x] &= self.registers[y];
+        self.reset_vf_after_logic();
+    }
+
+    // Set Vx = Vx XOR Vy.
+    fn op_8xy3(&mut self, x: usize, y: usize) {
+        self.registers[x] ^= self.registers[y];
+        self.reset_vf_after_logic();
+    }
+
+    // The VIP computes logical ops in a routine that leaves VF cleared.
+    fn reset_vf_after_logic(&mut self) {
+        if self.quirks.vf_reset {
+            self.registers[15] = 0;
+        }
+    }
+
+    // Set Vx = Vx + Vy, set VF = carry.
+    fn op_8xy4(&mut self, x: usize, y: usize) {
+        let sum = self.registers[x] as usize + self.registers[y] as usize;
+        self.registers[x] = (sum & 0xFF) as u8;
+        self.registers[15] = if sum > 0xFF { 1 } else { 0 };
+    }
+
+    // If Vx > Vy, then VF is set to 1, otherwise 0. Then Vy is subtracted from Vx, and the results stored in Vx.
+    fn op_8xy5(&mut self, x: usize, y: usize) {
+        self.registers[15] = (self.registers[x] > self.registers[y]) as u8;
+        self.registers[x] = self.registers[x].wrapping_sub(self.registers[y]);
+    }
+
+    // If the least-significant bit of Vx is 1, then VF is set to 1, otherwise 0. Then Vx is divided by 2.
+    // With the shift_vy quirk, Vy is shifted instead and the result stored in Vx.
+    fn op_8xy6(&mut self, x: usize, y: usize) {
+        let value = self.shift_source(x, y);
+        self.registers[x] = value >> 1;
+        self.registers[15] = value & 0b1;
+    }
+
+    // If Vy > Vx, then VF is set to 1, otherwise 0. Then Vx is subtracted from Vy, and the results stored in Vx.
+    fn op_8xy7(&mut self, x: usize, y: usize) {
+        self.registers[15] = (self.registers[x] < self.registers[y]) as u8;
+        self.registers[x] = self.registers[y].wrapping_sub(self.registers[x]);
+    }
+
+    // If the most-significant bit of Vx is 1, then VF is set to 1, otherwise to 0. Then Vx is multiplied by 2.
+    // With the shift_vy quirk, Vy is shifted instead and the result stored in Vx.
+    fn op_8xye(&mut self, x: usize, y: usize) {
+        let value = self.shift_source(x, y);
+        self.registers[x] = value << 1;
+        self.registers[15] = (value & 0b10000000) >> 7;
+    }
+
+    fn shift_source(&self, x: usize, y: usize) -> u8 {
+        if self.quirks.shift_vy {
+            self.registers[y]
+        } else {
+            self.registers[x]
+        }
+    }
+
+    // Skip next instruction if Vx != Vy.
+    fn op_9xy0(&mut self, x: usize, y: usize) {
+        if self.registers[x] != self.registers[y] {
+            self.pc = self.pc.wrapping_add(2);
+        }
+    }
+
+    // Set ir = nnn.
+    fn op_annn(&mut self, nnn: u16) {
+        self.ir = Addr::new(nnn);
+    }
+
+    // Jump to location nnn + V0.
+    fn op_bnnn(&mut self, nnn: u16) {
+        self.pc = Addr::new(nnn).wrapping_add(self.registers[0] as u16);
+    }
+
+    // Set Vx = random byte AND kk.
+    fn op_cxkk(&mut self, x: usize, kk: u8) {
+        let n = self.rng.byte();
+        self.registers[x] = n & kk;
+    }
+
+    // The interpreter reads n bytes from memory, starting at the address stored in IR.
+    // These bytes are then displayed as sprites on screen at coordinates (Vx, Vy).
+    // Sprites are XORed onto the existing screen.
+    // If this causes any pixels to be erased, VF is set to 1, otherwise it is set to 0.
+    // If the sprite is positioned so part of it is outside the coordinates of the display,
+    // it wraps around to the opposite side of the screen, or with the clip_sprites quirk
+    // is cut off there.
+    // In lores mode each sprite pixel is drawn as a 2x2 block of vram.
+    // With the collision_count quirk, hires draws set VF to the number of rows
+    // that collided or ran past the bottom edge instead.
+    // With the display_wait quirk the draw first waits for the next vertical
+    // blank, as the COSMAC VIP interpreter does, which limits a program to
+    // one sprite per frame.
+    // DXY0 draws a 16x16 sprite of 32 bytes, two per row, as SCHIP does in
+    // hires mode; like modern interpreters it does so in lores mode too.
+    fn op_dxyn(&mut self, x: usize, y: usize, n: usize) {
+        if self.quirks.display_wait && !std::mem::replace(&mut self.vblank, false) {
+            self.pc = self.pc.wrapping_sub(2);
+            self.waiting_for_vblank = true;
+            return;
+        }
+
+        let (width, screen_height) = self.screen_size();
+        let (bw, bh) = self.pixel_size();
+        let (left, top) = (
+            self.registers[x] as usize % width,
+            self.registers[y] as usize % screen_height,
+        );
+
+        let (sprite_width, height) = if n == 0 { (16, 16) } else { (8, n) };
+
+        let mut collision = false;
+        let mut hit_rows = 0;
+        self.draws = self.draws.saturating_add(1);
+        let clip = self.quirks.clip_sprites;
+        for i in 0..height {
+            let y = (top + i) % screen_height;
+            let mut row_collided = false;
+            // The row's bits, leftmost in the top bit. Only lit bits change
+            // anything, so visit just those, leftmost first. Clipping drops
+            // the bits past the right edge up front.
+            let mut bits = if sprite_width == 16 {
+                let row = self.ir.wrapping_add(2 * i as u16);
+                (self.memory.read(row) as u16) << 8 | self.memory.read(row.wrapping_add(1)) as u16
+            } else {
+                (self.memory.read(self.ir.wrapping_add(i as u16)) as u16) << 8
+            };
+            if clip {
+                if top + i >= screen_height {
+                    bits = 0;
+                } else if left + sprite_width > width {
+                    bits &= 0xFFFF << (16 - (width - left));
+                }
+            }
+            while bits != 0 {
+                let j = bits.leading_zeros() as usize;
+                bits &= !(0x8000 >> j);
+                let x = (left + j) % width;
+                row_collided |= self.vram.get(x * bw, y * bh) & 1 == 1;
+                if let Some(profile) = &mut self.profile {
+                    profile.record_draw(x * bw, y * bh, bw, bh);
+                }
+                if let Some(tags) = &mut self.draw_tags {
+                    for row in y * bh..(y + 1) * bh {
+                        let start = row * WIDTH + x * bw;
+                        tags[start..start + bw].fill(self.draws);
+                    }
+                }
+                for row in y * bh..(y + 1) * bh {
+                    for col in x * bw..(x + 1) * bw {
+                        self.vram.xor(0, col, row, 1);
+                    }
+                }
+            }
+            collision |= row_collided;
+            if row_collided || top + i >= screen_height {
+                hit_rows += 1;
+            }
+        }
+
+        self.registers[15] = if self.hires && self.quirks.collision_count {
+            hit_rows
+        } else {
+            collision as u8
+        };
+        self.frame_version = self.frame_version.wrapping_add(1);
+    }
+
+    // Skip next instruction if key with the value of Vx is pressed.
+    fn op_ex9e(&mut self, x: usize) {
+        if self.keypad.is_down(self.registers[x] as usize) {
+            self.pc = self.pc.wrapping_add(2);
+        }
+    }
+
+    // Skip next instruction if key with the value of Vx is not pressed.
+    fn op_exa1(&mut self, x: usize) {
+        if !self.keypad.is_down(self.registers[x] as usize) {
+            self.pc = self.pc.wrapping_add(2);
+        }
+    }
+
+    // Set Vx = delay timer value.
+    fn op_fx07(&mut self, x: usize) {
+        self.registers[x] = self.delay_timer;
+    }
+
+    // Wait for a key press, store the value of the key in Vx.
+    // Only a fresh press or release counts, so a key held since an earlier
+    // prompt isn't consumed again. While waiting, the instruction repeats.
+    fn op_fx0a(&mut self, x: usize) {
+        if !self.key_wait_started {
+            self.keypad.clear_edges();
+            self.key_wait_started = true;
+        }
+        let key = match self.quirks.key_wait {
+            KeyWait::Release => self.keypad.take_released(),
+            KeyWait::Press => self.keypad.take_pressed(),
+        };
+
+        match key {
+            Some(key) => {
+                self.registers[x] = key as u8;
+                self.key_wait_started = false;
+            }
+            None => {
+                self.pc = self.pc.wrapping_sub(2);
+                self.waiting_for_key = true;
+            }
+        }
+    }
+
+    // Set delay timer = Vx.
+    fn op_fx15(&mut self, x: usize) {
+        self.delay_timer = self.registers[x];
+    }
+
+    // Set sound timer = Vx.
+    fn op_fx18(&mut self, x: usize) {
+        self.sound_timer = self.registers[x];
+    }
+
+    // Set IR = IR + Vx. With the fx1e_carry quirk VF tells whether that
+    // went past the 12-bit address space.
+    fn op_fx1e(&mut self, x: usize) {
+        let sum = self.ir.index() + self.registers[x] as usize;
+        self.ir = self.ir.wrapping_add(self.registers[x] as u16);
+        if self.quirks.fx1e_carry {
+            self.registers[15] = (sum > 0xFFF) as u8;
+        }
+    }
+
+    // Set I = location of sprite for digit Vx.
+    fn op_fx29(&mut self, x: usize) {
+        self.ir = self.memory.font_char_addr(self.registers[x]);
+    }
+
+    // Set I = location of the big (8x10) sprite for digit Vx.
+    fn op_fx30(&mut self, x: usize) {
+        self.ir = self.memory.big_font_char_addr(self.registers[x]);
+    }
+
+    // The interpreter takes the decimal value of Vx,
+    // and places the hundreds digit in memory at location in IR,
+    // the tens digit at location IR+1, and the ones digit at location IR+2.
+    fn op_fx33(&mut self, x: usize) {
+        let n = self.registers[x];
+
+        self.memory.write(self.ir, n / 100);
+        self.memory.write(self.ir.wrapping_add(1), (n / 10) % 10);
+        self.memory.write(self.ir.wrapping_add(2), n % 10);
+    }
+
+    // Store registers V0 through Vx in memory starting at location I.
+    fn op_fx55(&mut self, x: usize) {
+        for i in 0..=x {
+            self.memory
+                .write(self.ir.wrapping_add(i as u16), self.registers[i]);
+        }
+        self.advance_ir_after_load_store(x);
+    }
+
+    // Read registers V0 through Vx from memory starting at location I.
+    fn op_fx65(&mut self, x: usize) {
+        for i in 0..=x {
+            self.registers[i] = self.memory.read(self.ir.wrapping_add(i as u16));
+        }
+        self.advance_ir_after_load_store(x);
+    }
+
+    // Save registers V0 through Vx to the RPL user flags. SCHIP 1.1 only
+    // has 8 flags; XO-CHIP programs use all 16.
+    fn op_fx75(&mut self, x: usize) {
+        self.rpl_flags[..=x].copy_from_slice(&self.registers[..=x]);
+    }
+
+    // Restore registers V0 through Vx from the RPL user flags.
+    fn op_fx85(&mut self, x: usize) {
+        self.registers[..=x].copy_from_slice(&self.rpl_flags[..=x]);
+    }
+
+    fn advance_ir_after_load_store(&mut self, x: usize) {
+        self.ir = match self.quirks.load_store {
+            LoadStore::Unchanged => self.ir,
+            LoadStore::X => self.ir.wrapping_add(x as u16),
+            LoadStore::XPlusOne => self.ir.wrapping_add(x as u16 + 1),
+        };
+    }
+}
@@ -0,0 +1,130 @@
+// The machine's display memory. Each pixel is a byte whose bit n is set when
+// the pixel is lit on plane n, so machines with several bit planes (XO-CHIP)
+// fit the same type as the single-plane CHIP-8. Sizes are set at runtime,
+// and drawing outside them is ignored rather than a panic.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    planes: usize,
+    pixels: Vec<u8>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize, planes: usize) -> Self {
+        assert!(
+            (1..=8).contains(&planes),
+            "a pixel byte holds 1 to 8 planes"
+        );
+        Self {
+            width,
+            height,
+            planes,
+            pixels: vec![0; width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn planes(&self) -> usize {
+        self.planes
+    }
+
+    pub fn clear(&mut self) {
+        self.pixels.fill(0);
+    }
+
+    // Plane bits of the pixel at `x`, `y`; 0 outside the buffer.
+    pub fn get(&self, x: usize, y: usize) -> u8 {
+        if x < self.width && y < self.height {
+            self.pixels[y * self.width + x]
+        } else {
+            0
+        }
+    }
+
+    // Flip the pixel at `x`, `y` on `plane` if `bit` is 1. Returns whether
+    // that turned a lit pixel off. Pixels outside the buffer are left alone.
+    pub fn xor(&mut self, plane: usize, x: usize, y: usize, bit: u8) -> bool {
+        if x >= self.width || y >= self.height || plane >= self.planes || bit == 0 {
+            return false;
+        }
+        let pixel = &mut self.pixels[y * self.width + x];
+        let mask = 1 << plane;
+        let erased = *pixel & mask != 0;
+        *pixel ^= mask;
+        erased
+    }
+
+    // All pixels, row by row, for saving.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    // Replace all pixels with `pixels`, which must be the same size.
+    pub fn set_pixels(&mut self, pixels: &[u8]) {
+        self.pixels.copy_from_slice(pixels);
+    }
+
+    // The pixels row by row, top to bottom.
+    pub fn rows(&self) -> impl Iterator<Item = &[u8]> {
+        self.pixels.chunks(self.width)
+    }
+
+    // The pixels as 8-bit RGBA, four bytes per pixel in rows from the top,
+    // each pixel blown up to a `scale` x `scale` block, for frontends to
+    // show as they are. `palette` has a color per pixel value: unlit first,
+    // then lit on plane 0, on plane 1, on both and so on. Values past its
+    // end take its last color.
+    pub fn render_rgba(&self, palette: &[[u8; 4]], scale: usize) -> Vec<u8> {
+        let mut rgba = Vec::with_capacity(self.pixels.len() * scale * scale * 4);
+        for row in self.rows() {
+            let start = rgba.len();
+            for &pixel in row.iter() {
+                let color = palette
+                    .get(pixel as usize)
+                    .or(palette.last())
+                    .copied()
+                    .unwrap_or([0, 0, 0, 0xFF]);
+                for _ in 0..scale {
+                    rgba.extend_from_slice(&color);
+                }
+            }
+            let end = rgba.len();
+            for _ in 1..scale {
+                rgba.extend_from_within(start..end);
+            }
+        }
+        rgba
+    }
+
+    // Move every pixel `dx` columns right and `dy` rows down, negative to go
+    // the other way. Pixels moved off an edge are lost and the ones
+    // uncovered on the other side are cleared.
+    pub fn scroll(&mut self, dx: isize, dy: isize) {
+        let (width, height) = (self.width as isize, self.height as isize);
+        let mut scrolled = vec![0; self.pixels.len()];
+        for y in 0.max(dy)..height.min(height + dy) {
+            for x in 0.max(dx)..width.min(width + dx) {
+                scrolled[(y * width + x) as usize] =
+                    self.pixels[((y - dy) * width + x - dx) as usize];
+            }
+        }
+        self.pixels = scrolled;
+    }
+
+    // Number of pixels that differ from `other`, which should be the same size.
+    pub fn count_differences(&self, other: &Framebuffer) -> usize {
+        self.pixels
+            .iter()
+            .zip(other.pixels.iter())
+            .filter(|(a, b)| a != b)
+            .count()
+    }
+}
@@ -0,0 +1,63 @@
+use std::{collections::VecDeque, time::Instant};
+
+// Most key changes held back at once. A machine that stops consuming them
+// (paused, halted) can't grow the queue without end; past this the oldest
+// changes are applied straight away.
+pub const INPUT_QUEUE_LEN: usize = 256;
+
+// A hex key going down or up, stamped with when the host saw it.
+#[derive(Clone, Copy, Debug)]
+struct KeyChange {
+    at: Instant,
+    key: usize,
+    down: bool,
+}
+
+// Key changes waiting to be handed to the machine at the frame they
+// happened in, so a hiccup in the host loop doesn't move input to a later
+// frame or merge several frames' input into one.
+#[derive(Default)]
+pub struct InputQueue {
+    changes: VecDeque<KeyChange>,
+    // the keypad after every change taken so far
+    keypad: [bool; 16],
+    // most changes waiting at once since the last peak() call
+    peak: usize,
+}
+
+impl InputQueue {
+    pub fn push(&mut self, at: Instant, key: usize, down: bool) {
+        if self.changes.len() == INPUT_QUEUE_LEN {
+            if let Some(oldest) = self.changes.pop_front() {
+                self.keypad[oldest.key] = oldest.down;
+            }
+        }
+        self.changes.push_back(KeyChange { at, key, down });
+        self.peak = self.peak.max(self.changes.len());
+    }
+
+    // The keypad for a frame ending at `until`, taking every change up to
+    // then. A key that went down during the frame reads as pressed even if
+    // it came back up, so short taps are never lost.
+    pub fn take_until(&mut self, until: Instant) -> [bool; 16] {
+        let mut tapped = [false; 16];
+        while let Some(change) = self.changes.front() {
+            if change.at > until {
+                break;
+            }
+            self.keypad[change.key] = change.down;
+            tapped[change.key] |= change.down;
+            self.changes.pop_front();
+        }
+        let mut keypad = self.keypad;
+        for (key, tap) in keypad.iter_mut().zip(tapped.iter()) {
+            *key |= tap;
+        }
+        keypad
+    }
+
+    // The most changes that were waiting at once since the last call.
+    pub fn peak(&mut self) -> usize {
+        std::mem::replace(&mut self.peak, self.changes.len())
+    }
+}
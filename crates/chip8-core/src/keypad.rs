@@ -0,0 +1,46 @@
+// Hex keypad state with edge tracking, so opcodes can tell a fresh press or
+// release apart from a key that is simply being held down.
+#[derive(Clone, Copy, Default)]
+pub struct Keypad {
+    down: [bool; 16],
+    pressed: [bool; 16],  // went down since last taken or cleared
+    released: [bool; 16], // went up since last taken or cleared
+}
+
+impl Keypad {
+    // Edges stay set until taken, so updates coming faster than the program
+    // looks at the keys don't lose them.
+    pub fn update(&mut self, state: [bool; 16]) {
+        for (key, &down) in state.iter().enumerate() {
+            self.pressed[key] |= down && !self.down[key];
+            self.released[key] |= !down && self.down[key];
+        }
+        self.down = state;
+    }
+
+    // Forget the edges so far, for a wait that only counts new ones.
+    pub fn clear_edges(&mut self) {
+        self.pressed = [false; 16];
+        self.released = [false; 16];
+    }
+
+    // Only the low nibble selects a key, as on the COSMAC VIP, so EX9E/EXA1
+    // with Vx above 0xF can't index past the keypad.
+    pub fn is_down(&self, key: usize) -> bool {
+        self.down[key & 0xF]
+    }
+
+    // Take the lowest key that was pressed and not yet taken, if any.
+    pub fn take_pressed(&mut self) -> Option<usize> {
+        let key = self.pressed.iter().position(|&p| p)?;
+        self.pressed[key] = false;
+        Some(key)
+    }
+
+    // Take the lowest key that was released and not yet taken, if any.
+    pub fn take_released(&mut self) -> Option<usize> {
+        let key = self.released.iter().position(|&r| r)?;
+        self.released[key] = false;
+        Some(key)
+    }
+}
@@ -0,0 +1,16 @@
+// The CHIP-8 machine itself, without any frontend. Frontends feed it key
+// states, run it with Chip8::cycle() or Chip8::run_frame() and draw vram().
+pub mod addr;
+pub mod chip8;
+pub mod fontset;
+pub mod framebuffer;
+pub mod input;
+pub mod keypad;
+pub mod memory;
+pub mod opstats;
+pub mod ports;
+pub mod profile;
+pub mod quirks;
+pub mod rng;
+pub mod savestate;
+pub mod timers;
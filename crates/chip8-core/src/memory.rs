@@ -0,0 +1,152 @@
+use crate::addr::Addr;
+use crate::fontset;
+
+// RAM of the original interpreters, and the most 16-bit addresses reach,
+// which XO-CHIP programs get.
+pub const RAM_SIZE: usize = 4096;
+pub const MAX_RAM_SIZE: usize = 0x10000;
+
+// Height in bytes of one glyph of the built-in font.
+const FONT_CHAR_SIZE: u16 = 5;
+// Height in bytes of one glyph of the big font.
+const BIG_FONT_CHAR_SIZE: u16 = 10;
+
+// Where the interpreter keeps things in RAM.
+#[derive(Clone, Copy, Debug, Hash)]
+pub struct Layout {
+    // Start of the hex digit font.
+    pub font_addr: Addr,
+    // Start of the big hex digit font.
+    pub big_font_addr: Addr,
+    // Where programs are loaded and execution starts.
+    pub start_pc: Addr,
+}
+
+// Where `platform` loads programs: 0x600 on the ETI-660, whose monitor
+// takes the page below, and the usual 0x200 everywhere else.
+pub fn platform_start_pc(platform: &str) -> Addr {
+    match platform {
+        "eti660" => Addr::new(0x600),
+        _ => Layout::default().start_pc,
+    }
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            font_addr: Addr::new(0x000),
+            big_font_addr: Addr::new(0x050), // right after the small font
+            start_pc: Addr::new(0x200), // lower bytes are reserved for the interpreter and font data
+        }
+    }
+}
+
+impl Layout {
+    // The first address past both fonts, the lowest a program can be
+    // loaded at without overwriting them.
+    pub fn fonts_end(&self) -> usize {
+        let small = self.font_addr.index() + fontset::FONT_SET.len();
+        let big = self.big_font_addr.index() + fontset::BIG_FONT_SET.len();
+        small.max(big)
+    }
+}
+
+// The machine's RAM, laid out according to a Layout.
+#[derive(Clone, Hash)]
+pub struct Memory {
+    layout: Layout,
+    ram: Vec<u8>,
+}
+
+impl Memory {
+    pub fn new(layout: Layout, fontset: &[u8]) -> Self {
+        let mut ram = vec![0; RAM_SIZE];
+        let font = layout.font_addr.index();
+        ram[font..font + fontset.len()].copy_from_slice(fontset);
+        let big_font = layout.big_font_addr.index();
+        ram[big_font..big_font + fontset::BIG_FONT_SET.len()]
+            .copy_from_slice(&fontset::BIG_FONT_SET);
+
+        Self { layout, ram }
+    }
+
+    pub fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    // Move the program area to start at `addr`, clearing any program loaded
+    // at the old one.
+    pub fn set_start_pc(&mut self, addr: Addr) {
+        self.load_program(&[]);
+        self.layout.start_pc = addr;
+    }
+
+    // Grow or shrink RAM to `size` bytes, at most MAX_RAM_SIZE. Added bytes
+    // are zero.
+    pub fn resize(&mut self, size: usize) {
+        self.ram.resize(size.min(MAX_RAM_SIZE), 0);
+    }
+
+    // Replace the program area with `data`. Anything that doesn't fit is
+    // dropped; returns how many bytes that was.
+    pub fn load_program(&mut self, data: &[u8]) -> usize {
+        let start = self.layout.start_pc.index().min(self.ram.len());
+        let program_area = &mut self.ram[start..];
+        program_area.fill(0);
+
+        let len = data.len().min(program_area.len());
+        program_area[..len].copy_from_slice(&data[..len]);
+        data.len() - len
+    }
+
+    // Address of the font sprite for hex digit `digit`.
+    pub fn font_char_addr(&self, digit: u8) -> Addr {
+        self.layout
+            .font_addr
+            .wrapping_add((digit & 0xF) as u16 * FONT_CHAR_SIZE)
+    }
+
+    // Address of the big font sprite for hex digit `digit`.
+    pub fn big_font_char_addr(&self, digit: u8) -> Addr {
+        self.layout
+            .big_font_addr
+            .wrapping_add((digit & 0xF) as u16 * BIG_FONT_CHAR_SIZE)
+    }
+
+    // Addresses past the end of RAM wrap around to the start, so a program
+    // that runs I off the end reads and writes low memory instead of
+    // crashing the emulator.
+    pub fn read(&self, addr: Addr) -> u8 {
+        self.ram[addr.index() % self.ram.len()]
+    }
+
+    pub fn write(&mut self, addr: Addr, value: u8) {
+        let len = self.ram.len();
+        self.ram[addr.index() % len] = value;
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.ram
+    }
+
+    // Replace all of RAM with `bytes`, which must be as long as it.
+    pub fn set_bytes(&mut self, bytes: &[u8]) {
+        self.ram.copy_from_slice(bytes);
+    }
+}
+
+// Runs of consecutive addresses whose bytes differ between two RAM
+// snapshots, as (first, last) inclusive, in address order.
+pub fn changed_ranges(before: &[u8], after: &[u8]) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for (addr, (a, b)) in before.iter().zip(after.iter()).enumerate() {
+        if a == b {
+            continue;
+        }
+        match ranges.last_mut() {
+            Some((_, last)) if *last + 1 == addr => *last = addr,
+            _ => ranges.push((addr, addr)),
+        }
+    }
+    ranges
+}
@@ -0,0 +1,95 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+// How often each kind of instruction ran and how long it took in total,
+// for deciding which handlers are worth optimizing and which quirks real
+// ROMs exercise. Instructions are grouped by pattern, like 8XY4 or DXYN.
+#[derive(Clone, Default)]
+pub struct OpcodeStats {
+    counts: BTreeMap<&'static str, (u64, Duration)>,
+}
+
+impl OpcodeStats {
+    pub fn record(&mut self, op: u16, time: Duration) {
+        let (count, total) = self.counts.entry(pattern(op)).or_default();
+        *count += 1;
+        *total += time;
+    }
+
+    // One line per pattern: the pattern, how many times it ran and the
+    // nanoseconds it took in all, most run first, under a header line.
+    pub fn to_csv(&self) -> String {
+        let mut rows: Vec<_> = self.counts.iter().collect();
+        rows.sort_by(|a, b| b.1 .0.cmp(&a.1 .0).then(a.0.cmp(b.0)));
+        let mut csv = "opcode,count,total_ns\n".to_string();
+        for (pattern, (count, total)) in rows {
+            csv.push_str(&format!("{},{},{}\n", pattern, count, total.as_nanos()));
+        }
+        csv
+    }
+}
+
+// The instruction pattern `op` belongs to, or "unknown".
+pub fn pattern(op: u16) -> &'static str {
+    let (kk, n) = (op & 0xFF, op & 0xF);
+    match op >> 12 {
+        0x0 => match op {
+            0x00E0 => "00E0",
+            0x00EE => "00EE",
+            0x00FB => "00FB",
+            0x00FC => "00FC",
+            0x00FD => "00FD",
+            0x00FE => "00FE",
+            0x00FF => "00FF",
+            0x0230 => "0230",
+            _ if op & 0xFFF0 == 0x00C0 => "00CN",
+            _ => "unknown",
+        },
+        0x1 => "1NNN",
+        0x2 => "2NNN",
+        0x3 => "3XKK",
+        0x4 => "4XKK",
+        0x5 if n == 0 => "5XY0",
+        0x6 => "6XKK",
+        0x7 => "7XKK",
+        0x8 => match n {
+            0x0 => "8XY0",
+            0x1 => "8XY1",
+            0x2 => "8XY2",
+            0x3 => "8XY3",
+            0x4 => "8XY4",
+            0x5 => "8XY5",
+            0x6 => "8XY6",
+            0x7 => "8XY7",
+            0xE => "8XYE",
+            _ => "unknown",
+        },
+        0x9 if n == 0 => "9XY0",
+        0xA => "ANNN",
+        0xB => "BNNN",
+        0xC => "CXKK",
+        0xD if n == 0 => "DXY0",
+        0xD => "DXYN",
+        0xE => match kk {
+            0x9E => "EX9E",
+            0xA1 => "EXA1",
+            _ => "unknown",
+        },
+        0xF => match kk {
+            0x07 => "FX07",
+            0x0A => "FX0A",
+            0x15 => "FX15",
+            0x18 => "FX18",
+            0x1E => "FX1E",
+            0x29 => "FX29",
+            0x30 => "FX30",
+            0x33 => "FX33",
+            0x55 => "FX55",
+            0x65 => "FX65",
+            0x75 => "FX75",
+            0x85 => "FX85",
+            _ => "unknown",
+        },
+        _ => "unknown",
+    }
+}
@@ -0,0 +1,59 @@
+use std::time::Instant;
+
+use crate::addr::Addr;
+use crate::memory::Memory;
+use crate::rng::Rng;
+
+// Nonstandard extension, off unless a frontend turns it on: a few bytes in
+// the interpreter area just below the program that the host keeps filled,
+// so homebrew written for this emulator can read a clock or real entropy.
+// ROMs written for other interpreters must not rely on them.
+//
+// 0x1F0-0x1F1  whole seconds since the machine started, big endian, wrapping
+// 0x1F2-0x1F3  60 Hz frames since the machine started, big endian, wrapping
+// 0x1F4        a fresh random byte from the host before every instruction
+pub const SECONDS_ADDR: Addr = Addr::new(0x1F0);
+pub const FRAMES_ADDR: Addr = Addr::new(0x1F2);
+pub const RANDOM_ADDR: Addr = Addr::new(0x1F4);
+
+#[derive(Clone)]
+pub struct HostPorts {
+    started: Instant,
+    frames: u16,
+    rng: Rng,
+}
+
+impl HostPorts {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            frames: 0,
+            rng: Rng::from_entropy(),
+        }
+    }
+
+    // Count one 60 Hz frame.
+    pub fn tick(&mut self) {
+        self.frames = self.frames.wrapping_add(1);
+    }
+
+    // Write the current values into their addresses.
+    pub fn refresh(&mut self, memory: &mut Memory) {
+        let seconds = self.started.elapsed().as_secs() as u16;
+        write_u16(memory, SECONDS_ADDR, seconds);
+        write_u16(memory, FRAMES_ADDR, self.frames);
+        memory.write(RANDOM_ADDR, self.rng.byte());
+    }
+}
+
+impl Default for HostPorts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn write_u16(memory: &mut Memory, addr: Addr, value: u16) {
+    let [high, low] = value.to_be_bytes();
+    memory.write(addr, high);
+    memory.write(addr.wrapping_add(1), low);
+}
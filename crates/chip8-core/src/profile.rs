@@ -0,0 +1,68 @@
+use crate::addr::Addr;
+use crate::chip8::{HEIGHT, WIDTH};
+use crate::memory::MAX_RAM_SIZE;
+
+// Execution and drawing counters for the profiler overlay. Counts are halved
+// by decay(), so they show where the time is going now rather than over the
+// whole session.
+#[derive(Clone)]
+pub struct Profile {
+    // instructions executed at each address
+    pc_hits: Vec<u32>,
+    // times each vram pixel was drawn to
+    draw_hits: Vec<u32>,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            pc_hits: vec![0; MAX_RAM_SIZE],
+            draw_hits: vec![0; WIDTH * HEIGHT],
+        }
+    }
+}
+
+impl Profile {
+    pub fn record_pc(&mut self, pc: Addr) {
+        if let Some(hits) = self.pc_hits.get_mut(pc.index()) {
+            *hits = hits.saturating_add(1);
+        }
+    }
+
+    // A sprite pixel was drawn over the `width` x `height` block of vram at (x, y).
+    pub fn record_draw(&mut self, x: usize, y: usize, width: usize, height: usize) {
+        for row in y..y + height {
+            for col in x..x + width {
+                let hits = &mut self.draw_hits[row * WIDTH + col];
+                *hits = hits.saturating_add(1);
+            }
+        }
+    }
+
+    pub fn decay(&mut self) {
+        for hits in self.pc_hits.iter_mut().chain(self.draw_hits.iter_mut()) {
+            *hits /= 2;
+        }
+    }
+
+    // The `count` most executed addresses, hottest first.
+    pub fn hottest(&self, count: usize) -> Vec<(Addr, u32)> {
+        let mut hot: Vec<(Addr, u32)> = self
+            .pc_hits
+            .iter()
+            .enumerate()
+            .filter(|&(_, &hits)| hits > 0)
+            .map(|(pc, &hits)| (Addr::new(pc as u16), hits))
+            .collect();
+        hot.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        hot.truncate(count);
+        hot
+    }
+
+    // How often each vram pixel was drawn to, row by row, scaled so the
+    // busiest pixel is 1.0.
+    pub fn heatmap(&self) -> Vec<f32> {
+        let max = self.draw_hits.iter().copied().max().unwrap_or(0).max(1) as f32;
+        self.draw_hits.iter().map(|&h| h as f32 / max).collect()
+    }
+}
@@ -0,0 +1,209 @@
+// Behaviours that differ between CHIP-8 interpreters.
+#[derive(Clone, Copy, Debug)]
+pub struct Quirks {
+    // When FX0A registers the key it is waiting for.
+    pub key_wait: KeyWait,
+    // Whether switching between lores and hires (00FE/00FF) clears the
+    // screen, as modern SCHIP interpreters do. SCHIP 1.1 on the HP48 kept
+    // the old contents, which then show up at the other resolution.
+    pub mode_switch_clears: bool,
+    // Whether DXYN in hires mode sets VF to the number of sprite rows that
+    // collided or ran off the bottom of the screen, as SCHIP 1.1 does,
+    // rather than to 1 for any collision.
+    pub collision_count: bool,
+    // Whether 8XY6/8XYE shift VY and store the result in VX, as on the
+    // COSMAC VIP, rather than shifting VX in place as SCHIP does.
+    pub shift_vy: bool,
+    // Where FX55/FX65 leave I.
+    pub load_store: LoadStore,
+    // Whether 8XY1/8XY2/8XY3 reset VF to 0, as the COSMAC VIP does.
+    pub vf_reset: bool,
+    // Whether DXYN cuts sprites off at the screen edges instead of wrapping
+    // them around. The start position wraps either way.
+    pub clip_sprites: bool,
+    // Whether DXYN waits for the next vertical blank (the 60 Hz timer tick)
+    // before drawing, as the COSMAC VIP does.
+    pub display_wait: bool,
+    // Whether FX1E sets VF to 1 when I + VX goes past 0xFFF and to 0
+    // otherwise, as the Amiga interpreter did. Spacefight 2091! relies on it.
+    pub fx1e_carry: bool,
+}
+
+// Quirk names as used with --quirk, in the order toggle() numbers them.
+pub const NAMES: [&str; 9] = [
+    "key-wait",
+    "mode-switch-clears",
+    "collision-count",
+    "shift-vy",
+    "load-store-increment",
+    "vf-reset",
+    "clip-sprites",
+    "display-wait",
+    "fx1e-carry",
+];
+
+// Platforms with a quirk preset, as given with --platform or in a ROM's settings block.
+pub const PLATFORMS: [&str; 4] = ["chip8", "vip", "schip", "eti660"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyWait {
+    // On release of the key, like the COSMAC VIP.
+    Release,
+    // As soon as a new key goes down.
+    Press,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoadStore {
+    // I is left where it was, as SCHIP 1.1 does.
+    Unchanged,
+    // I = I + x, as CHIP-48 does.
+    X,
+    // I = I + x + 1, past the last register, like the COSMAC VIP.
+    XPlusOne,
+}
+
+impl Quirks {
+    // The quirks of a platform, or None for an unknown platform.
+    pub fn preset(platform: &str) -> Option<Self> {
+        match platform {
+            // the ETI-660 differs in where programs load, not in its quirks
+            "chip8" | "eti660" => Some(Self::default()),
+            // the original interpreter on the COSMAC VIP
+            "vip" => Some(Self {
+                shift_vy: true,
+                load_store: LoadStore::XPlusOne,
+                vf_reset: true,
+                clip_sprites: true,
+                display_wait: true,
+                ..Self::default()
+            }),
+            // SCHIP 1.1 on the HP48
+            "schip" => Some(Self {
+                mode_switch_clears: false,
+                collision_count: true,
+                clip_sprites: true,
+                ..Self::default()
+            }),
+            _ => None,
+        }
+    }
+
+    // Flip quirk number `n` to its next setting while the machine runs.
+    // Returns a description of the new setting, or None for an unknown quirk.
+    pub fn toggle(&mut self, n: usize) -> Option<String> {
+        match n {
+            0 => {
+                self.key_wait = match self.key_wait {
+                    KeyWait::Release => KeyWait::Press,
+                    KeyWait::Press => KeyWait::Release,
+                };
+                Some(format!("FX0A key wait: {:?}", self.key_wait))
+            }
+            1 => {
+                self.mode_switch_clears = !self.mode_switch_clears;
+                Some(format!("Mode switch clears: {}", self.mode_switch_clears))
+            }
+            2 => {
+                self.collision_count = !self.collision_count;
+                Some(format!("Collision count: {}", self.collision_count))
+            }
+            3 => {
+                self.shift_vy = !self.shift_vy;
+                Some(format!("Shift VY: {}", self.shift_vy))
+            }
+            4 => {
+                self.load_store = match self.load_store {
+                    LoadStore::Unchanged => LoadStore::X,
+                    LoadStore::X => LoadStore::XPlusOne,
+                    LoadStore::XPlusOne => LoadStore::Unchanged,
+                };
+                Some(format!("FX55/FX65 increment: {:?}", self.load_store))
+            }
+            5 => {
+                self.vf_reset = !self.vf_reset;
+                Some(format!("VF reset: {}", self.vf_reset))
+            }
+            6 => {
+                self.clip_sprites = !self.clip_sprites;
+                Some(format!("Clip sprites: {}", self.clip_sprites))
+            }
+            7 => {
+                self.display_wait = !self.display_wait;
+                Some(format!("Display wait: {}", self.display_wait))
+            }
+            8 => {
+                self.fx1e_carry = !self.fx1e_carry;
+                Some(format!("FX1E carry: {}", self.fx1e_carry))
+            }
+            _ => None,
+        }
+    }
+
+    // Set a quirk by name, as given on the command line with --quirk name=value.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let invalid = || format!("Invalid value '{}' for quirk {}", value, name);
+        match name {
+            "key-wait" => self.key_wait = KeyWait::parse(value).ok_or_else(invalid)?,
+            "mode-switch-clears" => {
+                self.mode_switch_clears = parse_bool(value).ok_or_else(invalid)?
+            }
+            "collision-count" => self.collision_count = parse_bool(value).ok_or_else(invalid)?,
+            "shift-vy" => self.shift_vy = parse_bool(value).ok_or_else(invalid)?,
+            "vf-reset" => self.vf_reset = parse_bool(value).ok_or_else(invalid)?,
+            "clip-sprites" => self.clip_sprites = parse_bool(value).ok_or_else(invalid)?,
+            "display-wait" => self.display_wait = parse_bool(value).ok_or_else(invalid)?,
+            "fx1e-carry" => self.fx1e_carry = parse_bool(value).ok_or_else(invalid)?,
+            "load-store-increment" => {
+                self.load_store = LoadStore::parse(value).ok_or_else(invalid)?
+            }
+            _ => return Err(format!("Unknown quirk '{}'", name)),
+        }
+        Ok(())
+    }
+}
+
+impl KeyWait {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "release" => Some(KeyWait::Release),
+            "press" => Some(KeyWait::Press),
+            _ => None,
+        }
+    }
+}
+
+impl LoadStore {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(LoadStore::Unchanged),
+            "x" => Some(LoadStore::X),
+            "x+1" => Some(LoadStore::XPlusOne),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self {
+            key_wait: KeyWait::Release,
+            mode_switch_clears: true,
+            collision_count: false,
+            shift_vy: false,
+            load_store: LoadStore::Unchanged,
+            vf_reset: false,
+            clip_sprites: false,
+            display_wait: false,
+            fx1e_carry: false,
+        }
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "on" | "true" | "1" => Some(true),
+        "off" | "false" | "0" => Some(false),
+        _ => None,
+    }
+}
@@ -0,0 +1,61 @@
+// The random number generator behind CXKK and the random host port:
+// xorshift64*. It is built in rather than taken from the rand crate so the
+// same seed gives the same numbers in every build, whichever features are on,
+// and movies and audits replay the same everywhere.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    // never 0, which xorshift can't leave
+    state: u64,
+}
+
+impl Rng {
+    pub fn from_seed(seed: u64) -> Self {
+        // splitmix64, so nearby seeds start far apart and 0 is fine
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        Self { state: z.max(1) }
+    }
+
+    // A generator seeded differently on every call.
+    pub fn from_entropy() -> Self {
+        Self::from_seed(entropy())
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    pub fn byte(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+
+    // A number from 0 up to but not including `n`, which must not be 0.
+    pub fn below(&mut self, n: u64) -> u64 {
+        ((self.next_u64() as u128 * n as u128) >> 64) as u64
+    }
+}
+
+// A seed nobody can predict: from the OS through rand with the std-rng
+// feature, otherwise from std's randomly keyed hasher and the clock.
+#[cfg(feature = "std-rng")]
+fn entropy() -> u64 {
+    rand::random()
+}
+
+#[cfg(not(feature = "std-rng"))]
+fn entropy() -> u64 {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+    use std::time::SystemTime;
+
+    let mut hasher = RandomState::new().build_hasher();
+    if let Ok(time) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        hasher.write_u128(time.as_nanos());
+    }
+    hasher.finish()
+}
@@ -0,0 +1,119 @@
+use crate::addr::{Addr, StackIdx, STACK_DEPTH};
+use crate::chip8::CpuState;
+
+// Everything needed to put a machine back where it was: registers, timers,
+// stack, RAM and the screen. Quirks and the random number generator are
+// settings rather than state and stay as they are. Stored as bytes (.c8s):
+//
+//   "C8SS" and a format version byte
+//   pc, op, I: 16-bit big-endian each
+//   sp, V0..VF, DT, ST, hires: one byte each
+//   the stack: STACK_DEPTH 16-bit big-endian addresses
+//   RAM length (16-bit big-endian, 0 for 64 KB) and RAM
+//   vram width, height (16-bit big-endian) and one byte per pixel
+#[derive(Clone, Debug, PartialEq)]
+pub struct SaveState {
+    pub cpu: CpuState,
+    pub hires: bool,
+    pub ram: Vec<u8>,
+    pub vram_size: (usize, usize),
+    pub vram: Vec<u8>,
+}
+
+const MAGIC: &[u8; 4] = b"C8SS";
+const VERSION: u8 = 1;
+
+impl SaveState {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        let cpu = &self.cpu;
+        for word in [cpu.pc.index() as u16, cpu.op, cpu.ir.index() as u16] {
+            bytes.extend_from_slice(&word.to_be_bytes());
+        }
+        bytes.push(cpu.sp.depth() as u8);
+        bytes.extend_from_slice(&cpu.registers);
+        bytes.extend_from_slice(&[cpu.delay_timer, cpu.sound_timer, self.hires as u8]);
+        for addr in cpu.stack.iter() {
+            bytes.extend_from_slice(&(addr.index() as u16).to_be_bytes());
+        }
+        bytes.extend_from_slice(&(self.ram.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(&self.ram);
+        let (width, height) = self.vram_size;
+        bytes.extend_from_slice(&(width as u16).to_be_bytes());
+        bytes.extend_from_slice(&(height as u16).to_be_bytes());
+        bytes.extend_from_slice(&self.vram);
+        bytes
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader { bytes, at: 0 };
+        if reader.take(4)? != MAGIC {
+            return Err("Not a chip8-rs save state".to_string());
+        }
+        let version = reader.byte()?;
+        if version != VERSION {
+            return Err(format!("Unsupported save state version {}", version));
+        }
+
+        let (pc, op, ir) = (reader.word()?, reader.word()?, reader.word()?);
+        let sp = reader.byte()? as usize;
+        let mut registers = [0; 16];
+        registers.copy_from_slice(reader.take(16)?);
+        let (delay_timer, sound_timer) = (reader.byte()?, reader.byte()?);
+        let hires = reader.byte()? != 0;
+        let mut stack = [Addr::default(); STACK_DEPTH];
+        for addr in stack.iter_mut() {
+            *addr = Addr::new(reader.word()?);
+        }
+        let ram_len = match reader.word()? {
+            0 => 0x10000,
+            len => len as usize,
+        };
+        let ram = reader.take(ram_len)?.to_vec();
+        let vram_size = (reader.word()? as usize, reader.word()? as usize);
+        let vram = reader.take(vram_size.0 * vram_size.1)?.to_vec();
+
+        Ok(Self {
+            cpu: CpuState {
+                pc: Addr::new(pc),
+                op,
+                ir: Addr::new(ir),
+                sp: StackIdx::from_depth(sp).ok_or("Save state stack pointer out of range")?,
+                registers,
+                stack,
+                delay_timer,
+                sound_timer,
+            },
+            hires,
+            ram,
+            vram_size,
+            vram,
+        })
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .bytes
+            .get(self.at..self.at + len)
+            .ok_or("Save state is cut short")?;
+        self.at += len;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn word(&mut self) -> Result<u16, String> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+}
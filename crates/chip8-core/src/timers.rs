@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+// What single-stepping a paused machine does to the delay and sound timers.
+// While paused the timers never move; while running they follow wall-clock
+// time at the timer rate, whatever the instruction rate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StepTimers {
+    // Steps leave the timers alone, so they read the same between steps.
+    Frozen,
+    // Each step advances them by the time one instruction takes at the
+    // current speed, so stepping through a delay loop eventually ends it.
+    Proportional,
+}
+
+impl StepTimers {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "frozen" => Some(StepTimers::Frozen),
+            "proportional" => Some(StepTimers::Proportional),
+            _ => None,
+        }
+    }
+}
+
+// Turns elapsed time into timer ticks, carrying the remainder over so no
+// time is lost between calls, including across a pause.
+#[derive(Clone, Debug)]
+pub struct TimerClock {
+    hz: u32,
+    // time owed, in nanoseconds times hz, so a tick is exactly NANOS of it
+    debt: u128,
+}
+
+const NANOS: u128 = 1_000_000_000;
+
+impl TimerClock {
+    pub fn new(hz: u32) -> Self {
+        Self { hz, debt: 0 }
+    }
+
+    // Timer ticks due after `elapsed` more time.
+    pub fn advance(&mut self, elapsed: Duration) -> u32 {
+        self.debt += elapsed.as_nanos() * self.hz as u128;
+        self.take_ticks()
+    }
+
+    // Timer ticks due after one instruction at `ips` instructions per second.
+    pub fn advance_step(&mut self, ips: u32) -> u32 {
+        self.debt += NANOS * self.hz as u128 / ips.max(1) as u128;
+        self.take_ticks()
+    }
+
+    // Time until the next timer tick is due, at least a nanosecond.
+    pub fn until_next_tick(&self) -> Duration {
+        let hz = self.hz.max(1) as u128;
+        let nanos = (NANOS - self.debt).div_ceil(hz);
+        Duration::from_nanos(nanos.max(1) as u64)
+    }
+
+    fn take_ticks(&mut self) -> u32 {
+        let ticks = self.debt / NANOS;
+        self.debt %= NANOS;
+        ticks as u32
+    }
+}
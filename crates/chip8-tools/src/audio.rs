@@ -0,0 +1,72 @@
+// The buzzer rendered offline, sample-exact and independent of any audio
+// device, for headless capture. It matches the tone the frontend plays.
+pub const SAMPLE_RATE: u32 = 44100;
+const TONE_HZ: f32 = 240.0;
+const VOLUME: f32 = 0.25;
+
+pub struct Buzzer {
+    frame_rate: u32,
+    frames: u64,
+    phase: f32,
+    samples: Vec<i16>,
+}
+
+impl Buzzer {
+    // A buzzer advanced one `frame_rate` Hz frame at a time.
+    pub fn new(frame_rate: u32) -> Self {
+        Self {
+            frame_rate,
+            frames: 0,
+            phase: 0.0,
+            samples: Vec::new(),
+        }
+    }
+
+    // Add one frame of sound, on or off. Frames get whole samples, spread
+    // so that they add up to exactly SAMPLE_RATE per second.
+    pub fn add_frame(&mut self, on: bool) {
+        let rate = SAMPLE_RATE as u64;
+        let fps = self.frame_rate as u64;
+        let count = (self.frames + 1) * rate / fps - self.frames * rate / fps;
+        self.frames += 1;
+
+        for _ in 0..count {
+            let level = if !on {
+                0.0
+            } else if self.phase <= 0.5 {
+                VOLUME
+            } else {
+                -VOLUME
+            };
+            self.samples.push((level * i16::MAX as f32) as i16);
+            self.phase = (self.phase + TONE_HZ / SAMPLE_RATE as f32) % 1.0;
+        }
+    }
+
+    // The sound so far, SAMPLE_RATE samples a second; silence is 0.
+    pub fn samples(&self) -> &[i16] {
+        &self.samples
+    }
+
+    // The sound so far as a 16-bit mono WAV file.
+    pub fn wav(&self) -> Vec<u8> {
+        let data_len = self.samples.len() as u32 * 2;
+        let mut wav = Vec::with_capacity(44 + data_len as usize);
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+        wav.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+        wav.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes()); // bytes per second
+        wav.extend_from_slice(&2u16.to_le_bytes()); // bytes per sample
+        wav.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_len.to_le_bytes());
+        for sample in self.samples.iter() {
+            wav.extend_from_slice(&sample.to_le_bytes());
+        }
+        wav
+    }
+}
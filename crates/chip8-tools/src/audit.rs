@@ -0,0 +1,59 @@
+use chip8_core::chip8::{Chip8, Status};
+use chip8_core::fontset;
+use chip8_core::quirks::Quirks;
+use chip8_core::rng::Rng;
+
+// Instructions per 60 Hz frame, matching the SDL frontend's pace.
+pub const CYCLES_PER_FRAME: usize = 4;
+
+// Change the held keys every few frames, so headless runs go down
+// key-driven code paths too.
+pub fn random_keys(inputs: &mut Rng, frame: u64, keypad: &mut [bool; 16]) {
+    if frame.is_multiple_of(8) {
+        for key in keypad.iter_mut() {
+            *key = inputs.below(8) == 0;
+        }
+    }
+}
+
+// Run `rom` on two machines side by side with the same seed and the same
+// (pseudo-random) key presses, comparing their state hashes after every frame.
+// Any difference means something nondeterministic leaked into the core.
+pub fn check_determinism(rom: &[u8], quirks: Quirks, frames: u64, seed: u64) -> Result<(), String> {
+    let mut machines = [
+        Chip8::new(&fontset::FONT_SET, quirks),
+        Chip8::new(&fontset::FONT_SET, quirks),
+    ];
+    for machine in machines.iter_mut() {
+        machine.load_rom(rom);
+        machine.seed_rng(seed);
+    }
+
+    let mut inputs = Rng::from_seed(seed);
+    let mut keypad = [false; 16];
+
+    for frame in 0..frames {
+        random_keys(&mut inputs, frame, &mut keypad);
+
+        let mut results = Vec::with_capacity(2);
+        for machine in machines.iter_mut() {
+            machine.set_keypad(keypad);
+            results.push(machine.run_frame(CYCLES_PER_FRAME));
+        }
+
+        let (a, b) = (machines[0].state_hash(), machines[1].state_hash());
+        if a != b || results[0] != results[1] {
+            return Err(format!(
+                "state diverged at frame {} (seed {}): {:016x} != {:016x}",
+                frame, seed, a, b
+            ));
+        }
+
+        match results[0] {
+            Ok(Status::Halted | Status::Finished { .. } | Status::Exited) | Err(_) => break,
+            Ok(_) => (),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use chip8_core::chip8::Chip8;
+use chip8_core::fontset;
+use chip8_core::quirks::{self, Quirks};
+use chip8_core::rng::Rng;
+
+use crate::audit::{self, CYCLES_PER_FRAME};
+
+// Whether flipping one quirk changes what is on screen after the run.
+pub struct QuirkEffect {
+    pub name: &'static str,
+    // the new setting, as described by Quirks::toggle()
+    pub setting: String,
+    pub changes_outcome: bool,
+}
+
+// Run `rom` to `start_frame` and snapshot the machine there, just before the
+// glitch being hunted. Then replay the next `frames` frames from the snapshot
+// once as is and once with each quirk flipped, all with the same key presses,
+// and report which quirks end up with a different screen.
+pub fn bisect_quirks(
+    rom: &[u8],
+    quirks: Quirks,
+    start_frame: u64,
+    frames: u64,
+    seed: u64,
+) -> Vec<QuirkEffect> {
+    let mut machine = Chip8::new(&fontset::FONT_SET, quirks);
+    machine.load_rom(rom);
+    machine.seed_rng(seed);
+    let mut inputs = Rng::from_seed(seed);
+    let mut keypad = [false; 16];
+    for frame in 0..start_frame {
+        audit::random_keys(&mut inputs, frame, &mut keypad);
+        machine.set_keypad(keypad);
+        if machine.run_frame(CYCLES_PER_FRAME).is_err() {
+            break;
+        }
+    }
+
+    let replay = |mut machine: Chip8| {
+        let (mut inputs, mut keypad) = (inputs.clone(), keypad);
+        for frame in start_frame..start_frame + frames {
+            audit::random_keys(&mut inputs, frame, &mut keypad);
+            machine.set_keypad(keypad);
+            if machine.run_frame(CYCLES_PER_FRAME).is_err() {
+                break;
+            }
+        }
+        screen_hash(&machine)
+    };
+
+    let baseline = replay(machine.clone());
+    let mut effects = Vec::new();
+    for (n, &name) in quirks::NAMES.iter().enumerate() {
+        let mut flipped = machine.clone();
+        let setting = flipped.quirks_mut().toggle(n).unwrap_or_default();
+        effects.push(QuirkEffect {
+            name,
+            setting,
+            changes_outcome: replay(flipped) != baseline,
+        });
+    }
+    effects
+}
+
+fn screen_hash(machine: &Chip8) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    machine.vram().hash(&mut hasher);
+    machine.hires().hash(&mut hasher);
+    hasher.finish()
+}
@@ -0,0 +1,93 @@
+use chip8_core::chip8::{Chip8, Status};
+use chip8_core::fontset;
+use chip8_core::framebuffer::Framebuffer;
+use chip8_core::quirks::Quirks;
+use chip8_core::rng::Rng;
+
+use crate::audit::{self, CYCLES_PER_FRAME};
+use crate::png::Image;
+
+// Gap between the panels of a diff image, in pixels.
+const GAP: usize = 2;
+
+// The first frame at which two builds of a ROM disagree.
+pub struct Difference {
+    pub frame: u64,
+    // number of vram pixels that differ
+    pub pixels: usize,
+    // both screens and their difference side by side
+    pub image: Image,
+}
+
+// Run two builds of a ROM side by side with the same seed and key presses
+// for up to `frames` frames, comparing their screens after every frame.
+// Returns the first difference, or None if the screens always matched.
+pub fn compare(a: &[u8], b: &[u8], quirks: Quirks, frames: u64, seed: u64) -> Option<Difference> {
+    let mut machines = [
+        Chip8::new(&fontset::FONT_SET, quirks),
+        Chip8::new(&fontset::FONT_SET, quirks),
+    ];
+    for (machine, rom) in machines.iter_mut().zip([a, b]) {
+        machine.load_rom(rom);
+        machine.seed_rng(seed);
+    }
+
+    let mut inputs = Rng::from_seed(seed);
+    let mut keypad = [false; 16];
+
+    for frame in 0..frames {
+        audit::random_keys(&mut inputs, frame, &mut keypad);
+
+        let mut stopped = false;
+        for machine in machines.iter_mut() {
+            machine.set_keypad(keypad);
+            stopped |= matches!(
+                machine.run_frame(CYCLES_PER_FRAME),
+                Ok(Status::Halted | Status::Finished { .. } | Status::Exited) | Err(_)
+            );
+        }
+
+        let (va, vb) = (machines[0].vram(), machines[1].vram());
+        let pixels = va.count_differences(vb);
+        if pixels > 0 {
+            return Some(Difference {
+                frame,
+                pixels,
+                image: diff_image(va, vb),
+            });
+        }
+        if stopped {
+            break;
+        }
+    }
+
+    None
+}
+
+// Screen A, screen B, then both in grey with the differing pixels in red
+// (lit only in A) and green (lit only in B).
+fn diff_image(a: &Framebuffer, b: &Framebuffer) -> Image {
+    let (screen_width, height) = (a.width(), a.height());
+    let width = screen_width * 3 + GAP * 2;
+    let mut rgb = vec![40; width * height * 3];
+
+    for y in 0..height {
+        for x in 0..screen_width {
+            let (pa, pb) = (a.get(x, y) != 0, b.get(x, y) != 0);
+            let lit = |on: bool| if on { [230, 230, 230] } else { [0, 0, 0] };
+            let diff = match (pa, pb) {
+                (true, false) => [230, 40, 40],
+                (false, true) => [40, 230, 40],
+                (true, true) => [110, 110, 110],
+                (false, false) => [0, 0, 0],
+            };
+
+            for (panel, color) in [lit(pa), lit(pb), diff].iter().enumerate() {
+                let i = (y * width + panel * (screen_width + GAP) + x) * 3;
+                rgb[i..i + 3].copy_from_slice(color);
+            }
+        }
+    }
+
+    Image { width, height, rgb }
+}
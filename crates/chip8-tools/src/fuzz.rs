@@ -0,0 +1,85 @@
+use std::panic;
+
+use chip8_core::chip8::{Chip8, Fault, Status};
+use chip8_core::fontset;
+use chip8_core::quirks::Quirks;
+use chip8_core::rng::Rng;
+
+use crate::audit::{self, CYCLES_PER_FRAME};
+
+// Most bits flipped in one mutant.
+const MAX_FLIPS: u64 = 4;
+
+// How the mutants of a fuzzing run ended.
+#[derive(Debug, Default)]
+pub struct FuzzReport {
+    pub mutants: u64,
+    // reported a fault, like a stack overflow
+    pub faulted: u64,
+    // ran off the end of RAM
+    pub halted: u64,
+}
+
+// Run `mutants` copies of `rom` with a few random bits flipped, each for up to
+// `frames` frames with random key presses. Broken programs are expected to
+// fault or halt; a panic is a bug in the emulator, reported with the mutant's
+// seed so it can be reproduced with the same arguments.
+pub fn fuzz(
+    rom: &[u8],
+    quirks: Quirks,
+    mutants: u64,
+    frames: u64,
+    seed: u64,
+) -> Result<FuzzReport, String> {
+    let mut report = FuzzReport::default();
+    if rom.is_empty() {
+        return Err("empty ROM".to_string());
+    }
+
+    for n in 0..mutants {
+        let mutant_seed = seed.wrapping_add(n);
+        let mutant = mutate(rom, mutant_seed);
+
+        match panic::catch_unwind(|| run_mutant(&mutant, quirks, frames, mutant_seed)) {
+            Ok(Ok(Status::Halted)) => report.halted += 1,
+            Ok(Ok(_)) => (),
+            Ok(Err(_)) => report.faulted += 1,
+            Err(_) => return Err(format!("mutant {} panicked (seed {})", n, mutant_seed)),
+        }
+        report.mutants += 1;
+    }
+
+    Ok(report)
+}
+
+fn mutate(rom: &[u8], seed: u64) -> Vec<u8> {
+    let mut rng = Rng::from_seed(seed);
+    let mut mutant = rom.to_vec();
+    for _ in 0..1 + rng.below(MAX_FLIPS) {
+        let byte = rng.below(mutant.len() as u64) as usize;
+        mutant[byte] ^= 1 << rng.below(8);
+    }
+    mutant
+}
+
+fn run_mutant(rom: &[u8], quirks: Quirks, frames: u64, seed: u64) -> Result<Status, Fault> {
+    let mut machine = Chip8::new(&fontset::FONT_SET, quirks);
+    machine.load_rom(rom);
+    machine.seed_rng(seed);
+
+    let mut inputs = Rng::from_seed(seed);
+    let mut keypad = [false; 16];
+    let mut status = Status::Running;
+    for frame in 0..frames {
+        audit::random_keys(&mut inputs, frame, &mut keypad);
+        machine.set_keypad(keypad);
+        status = machine.run_frame(CYCLES_PER_FRAME)?;
+        if matches!(
+            status,
+            Status::Halted | Status::Finished { .. } | Status::Exited
+        ) {
+            break;
+        }
+    }
+    Ok(status)
+}
@@ -0,0 +1,47 @@
+// Parser for hex dumps pasted from forum posts, Octo, or hexdump tools.
+//
+// Bytes may be written as single pairs ("A2 2A"), long runs ("A22A6000"),
+// with 0x prefixes or commas between them. Tokens ending in ':' are taken to
+// be addresses and skipped.
+pub fn parse(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+
+    for (line_no, line) in text.lines().enumerate() {
+        for token in line.split(|c: char| c.is_whitespace() || c == ',') {
+            if token.is_empty() || token.ends_with(':') {
+                continue;
+            }
+
+            let digits = token
+                .strip_prefix("0x")
+                .or_else(|| token.strip_prefix("0X"))
+                .unwrap_or(token);
+
+            if let Some(bad) = digits.chars().find(|c| !c.is_ascii_hexdigit()) {
+                return Err(format!(
+                    "line {}: invalid hex digit '{}' in '{}'",
+                    line_no + 1,
+                    bad,
+                    token
+                ));
+            }
+            if digits.len() % 2 != 0 {
+                return Err(format!(
+                    "line {}: odd number of hex digits in '{}'",
+                    line_no + 1,
+                    token
+                ));
+            }
+
+            for i in (0..digits.len()).step_by(2) {
+                bytes.push(u8::from_str_radix(&digits[i..i + 2], 16).unwrap());
+            }
+        }
+    }
+
+    if bytes.is_empty() {
+        return Err("no hex bytes found".to_string());
+    }
+
+    Ok(bytes)
+}
@@ -0,0 +1,21 @@
+// Headless tools built on chip8-core: determinism audits, fuzzing, build
+// comparison, quirk bisection, quirk test ROMs, movie rendering, ROM
+// previews, stable state hashes, and the file formats they read and write,
+// ROM settings blocks and palettes among them.
+pub mod audio;
+pub mod audit;
+pub mod bisect;
+pub mod diff;
+pub mod fuzz;
+pub mod hexdump;
+pub mod movie;
+pub mod palette;
+pub mod png;
+pub mod preview;
+pub mod render;
+pub mod romheader;
+pub mod statehash;
+pub mod testroms;
+
+#[cfg(test)]
+mod tests;
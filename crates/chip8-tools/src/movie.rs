@@ -0,0 +1,153 @@
+use std::fmt;
+
+use chip8_core::chip8::Chip8;
+use chip8_core::quirks::Quirks;
+use chip8_core::{fontset, memory};
+
+// A recorded run: the ROM, everything that makes its replay deterministic,
+// and the keys held on every 60 Hz frame. Stored as text (.c8m):
+//
+//   chip8-movie 2
+//   rom games/pong.ch8
+//   seed 42
+//   cycles 10
+//   platform schip
+//   quirk shift-vy=on
+//   frames
+//   0000 120
+//   0010 3
+//
+// Each frame line is the keypad as a 16-bit hex mask (bit n for key n),
+// optionally followed by how many frames in a row it stays that way.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Movie {
+    // path of the ROM, relative to the movie file
+    pub rom: String,
+    pub seed: u64,
+    // instructions per frame
+    pub cycles: usize,
+    pub platform: Option<String>,
+    pub quirks: Vec<(String, String)>,
+    pub frames: Vec<[bool; 16]>,
+}
+
+const MAGIC: &str = "chip8-movie 2";
+// Movies from before CXKK got its own generator, whose random numbers no
+// longer replay the same.
+const OLD_MAGIC: &str = "chip8-movie 1";
+
+impl Movie {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().map(str::trim).filter(|l| !l.is_empty());
+        match lines.next() {
+            Some(MAGIC) => (),
+            Some(OLD_MAGIC) => {
+                return Err(format!(
+                    "Movie is in the old {} format, whose random numbers no longer replay the same; record it again",
+                    OLD_MAGIC
+                ))
+            }
+            _ => return Err("Not a chip8-rs movie".to_string()),
+        }
+
+        let (mut rom, mut seed, mut cycles, mut platform) = (None, 0, None, None);
+        let mut quirks = Vec::new();
+        for line in &mut lines {
+            if line == "frames" {
+                break;
+            }
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            let invalid = || format!("Invalid movie line '{}'", line);
+            match key {
+                "rom" => rom = Some(value.to_string()),
+                "seed" => seed = value.parse().map_err(|_| invalid())?,
+                "cycles" => cycles = Some(value.parse().map_err(|_| invalid())?),
+                "platform" => platform = Some(value.to_string()),
+                "quirk" => {
+                    let (name, setting) = value.split_once('=').ok_or_else(invalid)?;
+                    quirks.push((name.to_string(), setting.to_string()));
+                }
+                _ => return Err(invalid()),
+            }
+        }
+
+        let mut frames = Vec::new();
+        for line in lines {
+            let invalid = || format!("Invalid movie frame '{}'", line);
+            let (mask, count) = line.split_once(' ').unwrap_or((line, "1"));
+            let mask = u16::from_str_radix(mask, 16).map_err(|_| invalid())?;
+            let count: usize = count.parse().map_err(|_| invalid())?;
+            let mut keys = [false; 16];
+            for (n, key) in keys.iter_mut().enumerate() {
+                *key = mask >> n & 1 == 1;
+            }
+            frames.resize(frames.len() + count, keys);
+        }
+
+        Ok(Self {
+            rom: rom.ok_or("Movie names no ROM")?,
+            seed,
+            cycles: cycles.ok_or("Movie gives no cycles per frame")?,
+            platform,
+            quirks,
+            frames,
+        })
+    }
+
+    // The quirks to replay with: the platform's preset with the movie's
+    // quirks applied on top.
+    pub fn quirks(&self) -> Result<Quirks, String> {
+        let mut quirks = match &self.platform {
+            Some(platform) => Quirks::preset(platform)
+                .ok_or_else(|| format!("Unknown platform '{}'", platform))?,
+            None => Quirks::default(),
+        };
+        for (name, setting) in self.quirks.iter() {
+            quirks.set(name, setting)?;
+        }
+        Ok(quirks)
+    }
+
+    // A machine ready to replay the movie on `rom`: quirks set, the program
+    // loaded where its platform loads it and the random numbers seeded.
+    pub fn machine(&self, rom: &[u8]) -> Result<Chip8, String> {
+        let mut machine = Chip8::new(&fontset::FONT_SET, self.quirks()?);
+        if let Some(platform) = &self.platform {
+            machine.set_load_address(memory::platform_start_pc(platform));
+        }
+        machine.load_rom(rom);
+        machine.seed_rng(self.seed);
+        Ok(machine)
+    }
+}
+
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", MAGIC)?;
+        writeln!(f, "rom {}", self.rom)?;
+        writeln!(f, "seed {}", self.seed)?;
+        writeln!(f, "cycles {}", self.cycles)?;
+        if let Some(platform) = &self.platform {
+            writeln!(f, "platform {}", platform)?;
+        }
+        for (name, setting) in self.quirks.iter() {
+            writeln!(f, "quirk {}={}", name, setting)?;
+        }
+        writeln!(f, "frames")?;
+
+        let mask = |keys: &[bool; 16]| {
+            keys.iter()
+                .enumerate()
+                .fold(0u16, |mask, (n, &down)| mask | (down as u16) << n)
+        };
+        let mut frames = self.frames.iter().peekable();
+        while let Some(keys) = frames.next() {
+            let mut count = 1;
+            while frames.next_if(|next| *next == keys).is_some() {
+                count += 1;
+            }
+            writeln!(f, "{:04x} {}", mask(keys), count)?;
+        }
+        Ok(())
+    }
+}
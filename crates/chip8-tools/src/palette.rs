@@ -0,0 +1,133 @@
+use std::fmt;
+
+// Colors used to draw unlit and lit pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Palette {
+    pub background: (u8, u8, u8),
+    pub foreground: (u8, u8, u8),
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self {
+            background: (0, 0, 0),
+            foreground: (210, 210, 210),
+        }
+    }
+}
+
+// Palettes that can be cycled through at runtime.
+pub const BUILT_IN: [Palette; 5] = [
+    Palette {
+        background: (0, 0, 0),
+        foreground: (210, 210, 210),
+    },
+    // Octo
+    Palette {
+        background: (0x99, 0x66, 0x00),
+        foreground: (0xff, 0xcc, 0x00),
+    },
+    // green phosphor
+    Palette {
+        background: (0x0a, 0x14, 0x0a),
+        foreground: (0x33, 0xff, 0x66),
+    },
+    // amber monitor
+    Palette {
+        background: (0x14, 0x0c, 0x00),
+        foreground: (0xff, 0xb0, 0x00),
+    },
+    // handheld LCD
+    Palette {
+        background: (0x9b, 0xbc, 0x0f),
+        foreground: (0x0f, 0x38, 0x0f),
+    },
+];
+
+impl Palette {
+    // The built-in palette after this one, or the first if this is a custom palette.
+    pub fn next_built_in(&self) -> Palette {
+        match BUILT_IN.iter().position(|p| p == self) {
+            Some(i) => BUILT_IN[(i + 1) % BUILT_IN.len()],
+            None => BUILT_IN[0],
+        }
+    }
+
+    // Color channels in editing order: background RGB, then foreground RGB.
+    pub fn channel_mut(&mut self, channel: usize) -> Option<&mut u8> {
+        match channel {
+            0 => Some(&mut self.background.0),
+            1 => Some(&mut self.background.1),
+            2 => Some(&mut self.background.2),
+            3 => Some(&mut self.foreground.0),
+            4 => Some(&mut self.foreground.1),
+            5 => Some(&mut self.foreground.2),
+            _ => None,
+        }
+    }
+
+    // Parse "#rrggbb,#rrggbb" (background, foreground). Spaces may be used
+    // instead of the comma, as in the CHIP-8 database's color lists.
+    pub fn parse(text: &str) -> Option<Self> {
+        let colors: Vec<(u8, u8, u8)> = text
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|s| !s.is_empty())
+            .map(parse_color)
+            .collect::<Option<_>>()?;
+
+        match colors[..] {
+            [background, foreground] => Some(Self {
+                background,
+                foreground,
+            }),
+            _ => None,
+        }
+    }
+
+    // Color of a pixel lit for `level` (0.0 to 1.0) of the time.
+    pub fn blend(&self, level: f32) -> (u8, u8, u8) {
+        let mix = |off: u8, on: u8| (off as f32 + (on as f32 - off as f32) * level) as u8;
+        (
+            mix(self.background.0, self.foreground.0),
+            mix(self.background.1, self.foreground.1),
+            mix(self.background.2, self.foreground.2),
+        )
+    }
+}
+
+// A color for the `n`th sprite draw of a frame. Successive draws are a
+// golden angle apart on the color wheel, so neighbours never look alike.
+pub fn draw_hue(n: u16) -> (u8, u8, u8) {
+    let hue = (n as f32 * 137.508) % 360.0 / 60.0;
+    let x = 1.0 - (hue % 2.0 - 1.0).abs();
+    let (r, g, b) = match hue as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    let channel = |c: f32| (64.0 + c * 191.0) as u8;
+    (channel(r), channel(g), channel(b))
+}
+
+impl fmt::Display for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (b, g) = (self.background, self.foreground);
+        write!(
+            f,
+            "#{:02x}{:02x}{:02x},#{:02x}{:02x}{:02x}",
+            b.0, b.1, b.2, g.0, g.1, g.2
+        )
+    }
+}
+
+pub fn parse_color(text: &str) -> Option<(u8, u8, u8)> {
+    let hex = text.strip_prefix('#').unwrap_or(text);
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some((channel(0)?, channel(2)?, channel(4)?))
+}
@@ -0,0 +1,118 @@
+// Just enough of PNG to save screenshots: 8-bit RGB, no filtering, and
+// uncompressed deflate blocks. CHIP-8 frames are tiny, so compression isn't
+// worth a dependency.
+
+// An 8-bit RGB image, three bytes per pixel in rows from the top.
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub rgb: Vec<u8>,
+}
+
+impl Image {
+    // An image of `rgba`, four bytes per pixel, with the alpha dropped.
+    pub fn from_rgba(width: usize, height: usize, rgba: &[u8]) -> Image {
+        let rgb = rgba
+            .chunks(4)
+            .flat_map(|pixel| &pixel[..3])
+            .copied()
+            .collect();
+        Image { width, height, rgb }
+    }
+
+    // The image with every pixel blown up to a `factor` x `factor` block.
+    pub fn scaled(&self, factor: usize) -> Image {
+        let (width, height) = (self.width * factor, self.height * factor);
+        let mut rgb = Vec::with_capacity(width * height * 3);
+        for row in self.rgb.chunks(self.width * 3) {
+            let mut scaled_row = Vec::with_capacity(width * 3);
+            for pixel in row.chunks(3) {
+                for _ in 0..factor {
+                    scaled_row.extend_from_slice(pixel);
+                }
+            }
+            for _ in 0..factor {
+                rgb.extend_from_slice(&scaled_row);
+            }
+        }
+        Image { width, height, rgb }
+    }
+}
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+// Largest payload of a stored deflate block.
+const MAX_STORED_BLOCK: usize = 0xffff;
+
+// Encode `rgb`, three bytes per pixel in rows from the top, as a PNG file.
+pub fn encode(width: u32, height: u32, rgb: &[u8]) -> Vec<u8> {
+    assert_eq!(rgb.len(), (width * height * 3) as usize);
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // bit depth 8, color type 2 (RGB), default compression, filter and no interlacing
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    // every row starts with its filter type, 0 for none
+    let mut scanlines = Vec::with_capacity(rgb.len() + height as usize);
+    for row in rgb.chunks((width * 3) as usize) {
+        scanlines.push(0);
+        scanlines.extend_from_slice(row);
+    }
+
+    let mut png = SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&scanlines));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+// A zlib stream holding `data` in stored (uncompressed) deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // deflate with a 32K window, no preset dictionary
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_STORED_BLOCK).peekable();
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none() as u8;
+        let len = block.len() as u16;
+        out.push(last);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
@@ -0,0 +1,80 @@
+use chip8_core::chip8::{Chip8, Status};
+use chip8_core::fontset;
+use chip8_core::framebuffer::Framebuffer;
+use chip8_core::quirks::Quirks;
+
+use crate::movie::Movie;
+use crate::png::Image;
+
+// How long a preview runs before starting over, in 60 Hz frames.
+pub const FRAMES: usize = 300;
+// Instructions per frame without a demo to take the speed from.
+const DEFAULT_CYCLES: usize = 10;
+
+// A ROM running headlessly on a second machine for a live thumbnail,
+// driven by the keys of a recorded demo movie when there is one, and
+// starting over every FRAMES frames.
+pub struct Preview {
+    rom: Vec<u8>,
+    demo: Option<Movie>,
+    machine: Chip8,
+    frame: usize,
+    stopped: bool,
+}
+
+impl Preview {
+    pub fn new(rom: &[u8], demo: Option<Movie>) -> Result<Self, String> {
+        let machine = start(rom, demo.as_ref())?;
+        Ok(Self {
+            rom: rom.to_vec(),
+            demo,
+            machine,
+            frame: 0,
+            stopped: false,
+        })
+    }
+
+    // Run one more frame and return the screen.
+    pub fn next_frame(&mut self) -> Result<&Framebuffer, String> {
+        let length = self
+            .demo
+            .as_ref()
+            .map_or(FRAMES, |d| d.frames.len().min(FRAMES));
+        if self.frame >= length {
+            self.machine = start(&self.rom, self.demo.as_ref())?;
+            self.frame = 0;
+            self.stopped = false;
+        }
+
+        if !self.stopped {
+            let (keys, cycles) = match &self.demo {
+                Some(demo) => (demo.frames[self.frame], demo.cycles),
+                None => ([false; 16], DEFAULT_CYCLES),
+            };
+            self.machine.set_keypad(keys);
+            self.stopped = matches!(
+                self.machine.run_frame(cycles),
+                Ok(Status::Halted | Status::Finished { .. } | Status::Exited) | Err(_)
+            );
+        }
+        self.frame += 1;
+        Ok(self.machine.vram())
+    }
+}
+
+fn start(rom: &[u8], demo: Option<&Movie>) -> Result<Chip8, String> {
+    match demo {
+        Some(demo) => demo.machine(rom),
+        None => {
+            let mut machine = Chip8::new(&fontset::FONT_SET, Quirks::default());
+            machine.load_rom(rom);
+            Ok(machine)
+        }
+    }
+}
+
+// A screen as a white on black image, one pixel per vram pixel.
+pub fn thumbnail(screen: &Framebuffer) -> Image {
+    let rgba = screen.render_rgba(&[[0x00, 0x00, 0x00, 0xFF], [0xFF; 4]], 1);
+    Image::from_rgba(screen.width(), screen.height(), &rgba)
+}
@@ -0,0 +1,94 @@
+use std::{
+    fs,
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use chip8_core::chip8::{Chip8, Status, HEIGHT, WIDTH};
+
+use crate::audio::Buzzer;
+use crate::movie::Movie;
+
+// Encoding is handed to ffmpeg, which does every container and codec,
+// rather than pulling a video encoder into the build.
+const FFMPEG: &str = "ffmpeg";
+// Size of one vram pixel in the video, per side.
+const SCALE: usize = 8;
+const FRAME_RATE: u32 = 60;
+// Unlit and lit pixels.
+const PALETTE: [[u8; 4]; 2] = [[0, 0, 0, 255], [255, 255, 255, 255]];
+
+// Replay `movie` on `rom` headlessly, as fast as the machine runs, and
+// encode it with its sound into `out`, in whatever format the extension
+// asks ffmpeg for. Returns the number of frames encoded.
+//
+// The replay is deterministic, so it runs twice: once to render the sound
+// into a WAV file next to `out`, and once to stream the frames to ffmpeg.
+pub fn render(movie: &Movie, rom: &[u8], out: &Path) -> Result<usize, String> {
+    let mut buzzer = Buzzer::new(FRAME_RATE);
+    replay(movie, rom, |machine| {
+        buzzer.add_frame(machine.sound_active());
+        Ok(())
+    })?;
+    let audio_path = out.with_extension("audio.wav");
+    fs::write(&audio_path, buzzer.wav()).map_err(|e| e.to_string())?;
+
+    let result = encode(movie, rom, &audio_path, out);
+    let _ = fs::remove_file(&audio_path);
+    result.map(|()| movie.frames.len())
+}
+
+fn encode(movie: &Movie, rom: &[u8], audio_path: &Path, out: &Path) -> Result<(), String> {
+    let mut ffmpeg = Command::new(FFMPEG)
+        .args(["-y", "-loglevel", "error"])
+        .args(["-f", "rawvideo", "-pixel_format", "rgba"])
+        .args([
+            "-video_size",
+            &format!("{}x{}", WIDTH * SCALE, HEIGHT * SCALE),
+        ])
+        .args(["-framerate", &FRAME_RATE.to_string()])
+        .args(["-i", "-", "-i"])
+        .arg(audio_path)
+        .args(["-pix_fmt", "yuv420p", "-shortest"])
+        .arg(out)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Could not run {}: {}", FFMPEG, e))?;
+
+    let mut stdin = ffmpeg.stdin.take().expect("stdin is piped");
+    let written = replay(movie, rom, |machine| {
+        let frame = machine.vram().render_rgba(&PALETTE, SCALE);
+        stdin.write_all(&frame).map_err(|e| e.to_string())
+    });
+    // closing stdin tells ffmpeg the video is over
+    drop(stdin);
+
+    let status = ffmpeg.wait().map_err(|e| e.to_string())?;
+    if !status.success() {
+        return Err(format!("{} failed ({})", FFMPEG, status));
+    }
+    written
+}
+
+// Run the movie frame by frame, calling `each_frame` after every frame.
+// Once the program halts, finishes or faults the last screen is held to the end.
+fn replay<F>(movie: &Movie, rom: &[u8], mut each_frame: F) -> Result<(), String>
+where
+    F: FnMut(&Chip8) -> Result<(), String>,
+{
+    let mut machine = movie.machine(rom)?;
+
+    let mut stopped = false;
+    for &keys in movie.frames.iter() {
+        if !stopped {
+            machine.set_keypad(keys);
+            stopped = matches!(
+                machine.run_frame(movie.cycles),
+                Ok(Status::Halted | Status::Finished { .. } | Status::Exited) | Err(_)
+            );
+        }
+        each_frame(&machine)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,101 @@
+use crate::palette::Palette;
+
+// ROMs may carry their own settings in a text block appended after the
+// program, so a game can be distributed preconfigured as a single file:
+//
+//     <program bytes>
+//     CHIP8-CONFIG
+//     title=Space Invaders
+//     platform=chip8
+//     tickrate=15
+//     palette=#000000 #ffffff
+//     END
+//
+// Key names follow the community CHIP-8 database. The block sits past the
+// end of the program, so other interpreters just load it as unused bytes.
+const START_MARKER: &[u8] = b"CHIP8-CONFIG\n";
+const END_MARKER: &[u8] = b"END\n";
+
+// Highest tickrate accepted, a little past the fastest speed the emulator
+// runs at, so a crafted ROM can't ask for a speed that overflows.
+pub const MAX_TICKRATE: u32 = 2000;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RomHeader {
+    pub title: Option<String>,
+    pub platform: Option<String>,
+    // instructions per 60 Hz frame
+    pub tickrate: Option<u32>,
+    pub palette: Option<Palette>,
+    // lines that couldn't be understood, for the frontend to report
+    pub problems: Vec<String>,
+}
+
+// Split a ROM file into the program and its settings block, if it has one.
+// Lines that can't be understood are skipped and listed in `problems`.
+pub fn split(data: &[u8]) -> (&[u8], Option<RomHeader>) {
+    let trimmed = trim_trailing_whitespace(data);
+    if !trimmed.ends_with(&END_MARKER[..END_MARKER.len() - 1]) {
+        return (data, None);
+    }
+
+    let start = match find_last(trimmed, START_MARKER) {
+        Some(start) => start,
+        None => return (data, None),
+    };
+
+    let body = &trimmed[start + START_MARKER.len()..trimmed.len() - (END_MARKER.len() - 1)];
+    let mut header = RomHeader::default();
+    for line in String::from_utf8_lossy(body).lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => {
+                header.problems.push(format!("ignoring '{}'", line));
+                continue;
+            }
+        };
+
+        match key {
+            "title" => header.title = Some(value.to_string()),
+            "platform" => header.platform = Some(value.to_string()),
+            "tickrate" => match parse_tickrate(value) {
+                Some(n) => header.tickrate = Some(n),
+                None => header
+                    .problems
+                    .push(format!("invalid tickrate '{}'", value)),
+            },
+            "palette" => match Palette::parse(value) {
+                Some(palette) => header.palette = Some(palette),
+                None => header.problems.push(format!("invalid palette '{}'", value)),
+            },
+            _ => header.problems.push(format!("unknown key '{}'", key)),
+        }
+    }
+
+    (&data[..start], Some(header))
+}
+
+// Instructions per frame, from 1 to MAX_TICKRATE.
+pub fn parse_tickrate(value: &str) -> Option<u32> {
+    value
+        .parse()
+        .ok()
+        .filter(|n| (1..=MAX_TICKRATE).contains(n))
+}
+
+fn trim_trailing_whitespace(data: &[u8]) -> &[u8] {
+    let end = data
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .map_or(0, |i| i + 1);
+    &data[..end]
+}
+
+fn find_last(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).rposition(|w| w == needle)
+}
@@ -0,0 +1,37 @@
+use chip8_core::chip8::{Chip8, Status};
+
+use crate::movie::Movie;
+
+// A hash of the machine's state that stays the same across builds and
+// toolchains, unlike Chip8::state_hash, so it can be compared between
+// emulator revisions: FNV-1a over the save state bytes.
+pub fn stable_hash(machine: &Chip8) -> u64 {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    machine
+        .save_state()
+        .to_bytes()
+        .iter()
+        .fold(OFFSET, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(PRIME)
+        })
+}
+
+// Replay `movie` on `rom` for `frames` frames and hash the state it ends
+// in. Past the end of the movie no keys are held; once the program halts,
+// finishes or faults the state stays as it is.
+pub fn replay_hash(movie: &Movie, rom: &[u8], frames: usize) -> Result<u64, String> {
+    let mut machine = movie.machine(rom)?;
+
+    for frame in 0..frames {
+        let keys = movie.frames.get(frame).copied().unwrap_or([false; 16]);
+        machine.set_keypad(keys);
+        if matches!(
+            machine.run_frame(movie.cycles),
+            Ok(Status::Halted | Status::Finished { .. } | Status::Exited) | Err(_)
+        ) {
+            break;
+        }
+    }
+    Ok(stable_hash(&machine))
+}
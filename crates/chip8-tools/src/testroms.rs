@@ -0,0 +1,182 @@
+use chip8_core::chip8::Chip8;
+
+// Tiny ROMs that each exercise one behaviour CHIP-8 interpreters disagree
+// on. Each leaves its verdict in V0 and draws it in the middle of the
+// screen: 1 where the machine behaved like the original COSMAC VIP
+// interpreter, 0 where it behaved like most modern ones. They run as well on
+// any other emulator, to compare it with this one.
+pub struct QuirkRom {
+    pub name: &'static str,
+    pub description: &'static str,
+    // the --quirk setting that makes this emulator behave like the VIP here
+    pub vip_quirk: (&'static str, &'static str),
+    pub rom: Vec<u8>,
+}
+
+// Where programs start, and where a generated ROM keeps its data bytes.
+const START: u16 = 0x200;
+const DATA: u16 = 0x280;
+
+// Frames of CYCLES instructions each that a generated ROM needs to reach its
+// verdict, with room to spare.
+pub const CYCLES: usize = 200;
+pub const FRAMES: usize = 4;
+
+pub fn all() -> Vec<QuirkRom> {
+    vec![
+        shift_source(),
+        load_store_increment(),
+        sprite_clipping(),
+        vf_reset(),
+        display_wait(),
+    ]
+}
+
+// The verdict of a generated ROM that has run for FRAMES frames.
+pub fn verdict(machine: &Chip8) -> u8 {
+    machine.cpu_state().registers[0]
+}
+
+// 8XY6 shifting VY into VX, rather than shifting VX in place.
+fn shift_source() -> QuirkRom {
+    let mut rom = Rom::new();
+    rom.ops(&[
+        0x6110, // V1 = 0x10
+        0x6203, // V2 = 0x03
+        0x8126, // V1 = V2 >> 1 on the VIP, V1 >> 1 elsewhere
+        0x6000, // V0 = 0
+        0x4101, // if V1 == 1
+        0x6001, //   V0 = 1
+    ]);
+    QuirkRom {
+        name: "shift-source",
+        description: "8XY6 shifts VY into VX",
+        vip_quirk: ("shift-vy", "on"),
+        rom: rom.finish(&[]),
+    }
+}
+
+// FX55/FX65 leaving I pointing past the last register they touched.
+fn load_store_increment() -> QuirkRom {
+    let mut rom = Rom::new();
+    rom.ops(&[
+        0xA000 | DATA, // I = data
+        0xF065,        // V0 = data[0]
+        0xF065,        // V0 = data[1] if I moved on, data[0] otherwise
+        0x8100,        // V1 = V0
+        0x6000,        // V0 = 0
+        0x41BB,        // if V1 == data[1]
+        0x6001,        //   V0 = 1
+    ]);
+    QuirkRom {
+        name: "load-store-increment",
+        description: "FX55/FX65 increment I",
+        vip_quirk: ("load-store-increment", "x+1"),
+        rom: rom.finish(&[0xAA, 0xBB]),
+    }
+}
+
+// DXYN clipping sprites at the right edge instead of wrapping them around.
+fn sprite_clipping() -> QuirkRom {
+    let mut rom = Rom::new();
+    rom.ops(&[
+        0x00E0,        // clear the screen
+        0xA000 | DATA, // I = a full row of 8 pixels
+        0x6A3C,        // VA = 60
+        0x6B00,        // VB = 0
+        0xDAB1,        // draw it, 4 pixels past the right edge
+        0xA001 | DATA, // I = a single pixel
+        0x6A00,        // VA = 0
+        0xDAB1,        // draw it where a wrapped row would have ended up
+        0x6000,        // V0 = 0
+        0x4F00,        // if nothing collided
+        0x6001,        //   V0 = 1
+    ]);
+    QuirkRom {
+        name: "sprite-clipping",
+        description: "DXYN clips sprites at the screen edge",
+        vip_quirk: ("clip-sprites", "on"),
+        rom: rom.finish(&[0xFF, 0x80]),
+    }
+}
+
+// 8XY1/8XY2/8XY3 clearing VF as a side effect.
+fn vf_reset() -> QuirkRom {
+    let mut rom = Rom::new();
+    rom.ops(&[
+        0x6F05, // VF = 5
+        0x8121, // V1 |= V2
+        0x6000, // V0 = 0
+        0x4F00, // if VF == 0
+        0x6001, //   V0 = 1
+    ]);
+    QuirkRom {
+        name: "vf-reset",
+        description: "8XY1/8XY2/8XY3 reset VF",
+        vip_quirk: ("vf-reset", "on"),
+        rom: rom.finish(&[]),
+    }
+}
+
+// DXYN waiting for the vertical blank, which the delay timer shows as
+// ticks going by between two draws.
+fn display_wait() -> QuirkRom {
+    let mut rom = Rom::new();
+    rom.ops(&[
+        0x00E0,        // clear the screen
+        0xA000 | DATA, // I = a single pixel
+        0x6102,        // V1 = 2
+        0xF115,        // DT = V1
+        0xD001,        // draw it
+        0xD001,        // and erase it again
+        0xF207,        // V2 = DT
+        0x6000,        // V0 = 0
+        0x3202,        // if the timer has ticked
+        0x6001,        //   V0 = 1
+    ]);
+    QuirkRom {
+        name: "display-wait",
+        description: "DXYN waits for the vertical blank",
+        vip_quirk: ("display-wait", "on"),
+        rom: rom.finish(&[0x80]),
+    }
+}
+
+// A ROM being put together one opcode at a time.
+struct Rom {
+    bytes: Vec<u8>,
+}
+
+impl Rom {
+    fn new() -> Self {
+        Self { bytes: Vec::new() }
+    }
+
+    fn ops(&mut self, ops: &[u16]) {
+        for op in ops {
+            self.bytes.extend_from_slice(&op.to_be_bytes());
+        }
+    }
+
+    // Draw the verdict in V0, stop, and append `data` at DATA.
+    fn finish(mut self, data: &[u8]) -> Vec<u8> {
+        self.ops(&[
+            0x00E0, // clear the screen
+            0xF029, // I = glyph for V0
+            0x6A1E, // VA = 30
+            0x6B0D, // VB = 13
+            0xDAB5, // draw it
+        ]);
+        let end = START + self.bytes.len() as u16;
+        self.ops(&[0x1000 | end]); // loop here forever
+
+        let data_start = (DATA - START) as usize;
+        assert!(
+            self.bytes.len() <= data_start,
+            "quirk ROM code overruns its data"
+        );
+        self.bytes.resize(data_start, 0);
+        self.bytes.extend_from_slice(data);
+        self.bytes
+    }
+}
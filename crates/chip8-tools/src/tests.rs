@@ -0,0 +1,553 @@
+use std::fs;
+use std::time::{Duration, Instant};
+
+use chip8_core::addr::Addr;
+use chip8_core::chip8::{Chip8, Fault, Status};
+use chip8_core::fontset;
+use chip8_core::framebuffer::Framebuffer;
+use chip8_core::input::InputQueue;
+use chip8_core::keypad::Keypad;
+use chip8_core::memory;
+use chip8_core::opstats;
+use chip8_core::quirks::Quirks;
+use chip8_core::savestate::SaveState;
+use chip8_core::timers::TimerClock;
+
+use crate::audio::{self, Buzzer};
+use crate::audit;
+use crate::fuzz;
+use crate::movie::Movie;
+use crate::palette::Palette;
+use crate::preview::Preview;
+use crate::romheader::{self, RomHeader};
+use crate::statehash;
+use crate::testroms;
+
+// The ROMs shipped with the emulator, in the workspace root.
+const ROMS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../roms");
+
+#[test]
+fn bundled_roms_run_deterministically() {
+    for entry in fs::read_dir(ROMS).unwrap() {
+        let path = entry.unwrap().path();
+        let rom = fs::read(&path).unwrap();
+        for seed in 0..3 {
+            if let Err(e) = audit::check_determinism(&rom, Quirks::default(), 600, seed) {
+                panic!("{}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+#[test]
+fn mutated_roms_never_panic() {
+    for entry in fs::read_dir(ROMS).unwrap() {
+        let path = entry.unwrap().path();
+        let rom = fs::read(&path).unwrap();
+        if let Err(e) = fuzz::fuzz(&rom, Quirks::default(), 200, 300, 0) {
+            panic!("{}: {}", path.display(), e);
+        }
+    }
+}
+
+#[test]
+fn quirk_roms_see_modern_behaviour_by_default() {
+    for test in testroms::all() {
+        assert_eq!(
+            run_quirk_rom(&test.rom, Quirks::default()),
+            0,
+            "{}",
+            test.name
+        );
+    }
+}
+
+#[test]
+fn quirk_roms_see_vip_behaviour_with_their_quirk() {
+    for test in testroms::all() {
+        let (name, setting) = test.vip_quirk;
+        let mut quirks = Quirks::default();
+        quirks.set(name, setting).unwrap();
+        assert_eq!(run_quirk_rom(&test.rom, quirks), 1, "{}", test.name);
+    }
+}
+
+fn run_quirk_rom(rom: &[u8], quirks: Quirks) -> u8 {
+    let mut machine = Chip8::new(&fontset::FONT_SET, quirks);
+    machine.load_rom(rom);
+    for _ in 0..testroms::FRAMES {
+        machine.run_frame(testroms::CYCLES).unwrap();
+    }
+    testroms::verdict(&machine)
+}
+
+#[test]
+fn patches_apply_to_a_running_program() {
+    let mut machine = Chip8::new(&fontset::FONT_SET, Quirks::default());
+    // V0 = 5, then jump back forever
+    machine.load_rom(&[0x60, 0x05, 0x12, 0x00]);
+    machine.run_frame(10).unwrap();
+    assert_eq!(machine.cpu_state().registers[0], 5);
+
+    machine.patch(Addr::new(0x200), &[0x60, 0x07]);
+    assert_eq!(machine.peek(Addr::new(0x201)), 0x07);
+    machine.run_frame(10).unwrap();
+    assert_eq!(machine.cpu_state().registers[0], 7);
+}
+
+#[test]
+fn timer_clock_carries_partial_ticks_over() {
+    let mut clock = TimerClock::new(60);
+    // 10 ms is less than a tick, twice that is one with change left over
+    assert_eq!(clock.advance(Duration::from_millis(10)), 0);
+    assert_eq!(clock.advance(Duration::from_millis(10)), 1);
+    assert_eq!(clock.advance(Duration::from_millis(1000)), 60);
+}
+
+#[test]
+fn proportional_steps_add_up_to_timer_ticks() {
+    // at 600 instructions per second, ten steps take one 60 Hz tick
+    let mut clock = TimerClock::new(60);
+    let ticks: u32 = (0..600).map(|_| clock.advance_step(600)).sum();
+    assert_eq!(ticks, 60);
+}
+
+#[test]
+fn movies_survive_a_round_trip() {
+    let mut frames = vec![[false; 16]; 5];
+    frames[2][0x4] = true;
+    frames[3][0xF] = true;
+    let movie = Movie {
+        rom: "pong.ch8".to_string(),
+        seed: 42,
+        cycles: 10,
+        platform: Some("schip".to_string()),
+        quirks: vec![("shift-vy".to_string(), "on".to_string())],
+        frames,
+    };
+    assert_eq!(Movie::parse(&movie.to_string()), Ok(movie));
+
+    // older movies can't replay the same random numbers
+    let old = "chip8-movie 1\nrom pong.ch8\ncycles 10\nframes\n";
+    assert!(Movie::parse(old).unwrap_err().contains("record it again"));
+}
+
+#[test]
+fn changed_bytes_group_into_ranges() {
+    let before = [0u8; 16];
+    let mut after = before;
+    after[2] = 1;
+    after[3] = 1;
+    after[4] = 1;
+    after[9] = 7;
+    assert_eq!(
+        memory::changed_ranges(&before, &after),
+        vec![(2, 4), (9, 9)]
+    );
+}
+
+#[test]
+fn previews_start_over_when_the_demo_ends() {
+    // one instruction a frame: I = glyph 0, draw its top row, loop forever
+    let rom = [0xA0, 0x00, 0xD0, 0x01, 0x12, 0x04];
+    let demo = Movie {
+        rom: String::new(),
+        seed: 0,
+        cycles: 1,
+        platform: None,
+        quirks: Vec::new(),
+        frames: vec![[false; 16]; 3],
+    };
+    let mut preview = Preview::new(&rom, Some(demo)).unwrap();
+    let first = preview.next_frame().unwrap().clone();
+    for _ in 0..2 {
+        preview.next_frame().unwrap();
+    }
+    assert_eq!(*preview.next_frame().unwrap(), first);
+}
+
+#[test]
+fn jumping_to_itself_finishes_the_program() {
+    // V0 = 5, then 1202 jumps to itself
+    let mut machine = Chip8::new(&fontset::FONT_SET, Quirks::default());
+    machine.load_rom(&[0x60, 0x05, 0x12, 0x02]);
+    assert_eq!(
+        machine.run_frame(10),
+        Ok(Status::Finished {
+            pc: Addr::new(0x202)
+        })
+    );
+    assert_eq!(machine.cpu_state().registers[0], 5);
+}
+
+#[test]
+fn beeps_render_for_as_long_as_the_sound_timer_runs() {
+    // ST = 30, then jump to itself
+    let mut machine = Chip8::new(&fontset::FONT_SET, Quirks::default());
+    machine.load_rom(&[0x60, 0x1E, 0xF0, 0x18, 0x12, 0x04]);
+    let mut buzzer = Buzzer::new(60);
+    for _ in 0..60 {
+        machine.run_frame(10).unwrap();
+        buzzer.add_frame(machine.sound_active());
+    }
+    // the first frame's tick already counts ST down to 29
+    let sounding = buzzer.samples().iter().filter(|&&s| s != 0).count();
+    assert_eq!(sounding, 29 * audio::SAMPLE_RATE as usize / 60);
+    assert_eq!(buzzer.samples().len(), audio::SAMPLE_RATE as usize);
+}
+
+#[test]
+fn reset_restores_ram_as_loaded() {
+    // V0 = 5, I = 0x200, store V0 over the program, then loop forever
+    let mut machine = Chip8::new(&fontset::FONT_SET, Quirks::default());
+    machine.load_rom(&[0x60, 0x05, 0xA2, 0x00, 0xF0, 0x55, 0x12, 0x06]);
+    machine.run_frame(10).unwrap();
+    assert_eq!(machine.peek(Addr::new(0x200)), 0x05);
+
+    machine.reset();
+    assert_eq!(machine.peek(Addr::new(0x200)), 0x60);
+    assert_eq!(machine.cpu_state().registers[0], 0);
+}
+
+#[test]
+fn save_states_survive_a_round_trip() {
+    let rom = fs::read(format!("{}/pong2.c8", ROMS)).unwrap();
+    let mut machine = Chip8::new(&fontset::FONT_SET, Quirks::default());
+    machine.load_rom(&rom);
+    for _ in 0..120 {
+        machine.run_frame(10).unwrap();
+    }
+
+    let bytes = machine.save_state().to_bytes();
+    let mut restored = Chip8::new(&fontset::FONT_SET, Quirks::default());
+    restored
+        .load_state(&SaveState::parse(&bytes).unwrap())
+        .unwrap();
+    assert_eq!(restored.state_hash(), machine.state_hash());
+}
+
+#[test]
+fn two_page_hires_programs_draw_at_64x64() {
+    let mut rom = vec![0x12, 0x60];
+    rom.resize(0xC0, 0);
+    // I = glyph 0, V0 = 0, V1 = 40, draw its top row, loop forever
+    rom.extend_from_slice(&[0xA0, 0x00, 0x60, 0x00, 0x61, 0x28, 0xD0, 0x11, 0x12, 0xC8]);
+    let mut machine = Chip8::new(&fontset::FONT_SET, Quirks::default());
+    machine.load_rom(&rom);
+    machine.run_frame(10).unwrap();
+
+    assert!(machine.two_page());
+    // each pixel is two vram pixels wide and one tall
+    assert_eq!(machine.vram().get(7, 40), 1);
+    assert_eq!(machine.vram().get(8, 40), 0);
+    assert_eq!(machine.vram().get(0, 41), 0);
+}
+
+#[test]
+fn replay_hashes_are_repeatable() {
+    let rom = fs::read(format!("{}/pong2.c8", ROMS)).unwrap();
+    let movie = Movie {
+        rom: String::new(),
+        seed: 7,
+        cycles: 10,
+        platform: None,
+        quirks: Vec::new(),
+        frames: vec![[false; 16]; 60],
+    };
+    let hash = statehash::replay_hash(&movie, &rom, 120).unwrap();
+    assert_eq!(statehash::replay_hash(&movie, &rom, 120).unwrap(), hash);
+    assert_ne!(statehash::replay_hash(&movie, &rom, 121).unwrap(), hash);
+}
+
+#[test]
+fn eti660_programs_load_at_0x600() {
+    // a program loaded at 0x200 before is cleared away
+    let mut machine = Chip8::new(&fontset::FONT_SET, Quirks::default());
+    machine.load_rom(&[0x60, 0x01]);
+    machine.set_load_address(memory::platform_start_pc("eti660"));
+    // V0 = 5, then 1602 jumps to itself
+    machine.load_rom(&[0x60, 0x05, 0x16, 0x02]);
+    assert_eq!(machine.peek(Addr::new(0x200)), 0);
+    assert_eq!(
+        machine.run_frame(10),
+        Ok(Status::Finished {
+            pc: Addr::new(0x602)
+        })
+    );
+    assert_eq!(machine.cpu_state().registers[0], 5);
+}
+
+#[test]
+fn rpl_flags_outlive_a_reset() {
+    // V0 = 1, V1 = 2, save V0..V1 to the flags, then jump to itself
+    let mut machine = Chip8::new(&fontset::FONT_SET, Quirks::default());
+    machine.load_rom(&[0x60, 0x01, 0x61, 0x02, 0xF1, 0x75, 0x12, 0x06]);
+    machine.run_frame(10).unwrap();
+    assert_eq!(machine.rpl_flags()[..3], [1, 2, 0]);
+
+    // restore V0..V1 from the flags
+    machine.load_rom(&[0xF1, 0x85, 0x12, 0x02]);
+    machine.reset();
+    machine.run_frame(10).unwrap();
+    assert_eq!(machine.cpu_state().registers[..2], [1, 2]);
+}
+
+#[test]
+fn dxy0_draws_16x16_sprites() {
+    // hires, I = the sprite after the code, draw it at 0,0, then loop forever
+    let mut rom = vec![0x00, 0xFF, 0xA2, 0x08, 0xD0, 0x00, 0x12, 0x06];
+    rom.extend_from_slice(&[0xFF; 32]);
+    let mut machine = Chip8::new(&fontset::FONT_SET, Quirks::default());
+    machine.load_rom(&rom);
+    machine.run_frame(10).unwrap();
+
+    let lit = machine.vram().pixels().iter().filter(|&&p| p != 0).count();
+    assert_eq!(lit, 16 * 16);
+    assert_eq!(machine.vram().get(15, 15), 1);
+    assert_eq!(machine.vram().get(16, 0), 0);
+}
+
+#[test]
+fn collision_count_sets_vf_to_the_rows_hit_in_hires_only() {
+    let run = |hires: bool| {
+        // hires or CLS, I = the sprite after the code, draw it at 0,0 twice,
+        // then loop forever
+        let mode = if hires { 0xFF } else { 0xE0 };
+        let rom = [
+            0x00, mode, 0xA2, 0x0C, 0x60, 0x00, 0xD0, 0x03, 0xD0, 0x03, 0x12, 0x0A, 0xFF, 0x00,
+            0xFF,
+        ];
+        let quirks = Quirks {
+            collision_count: true,
+            ..Quirks::default()
+        };
+        let mut machine = Chip8::new(&fontset::FONT_SET, quirks);
+        machine.load_rom(&rom);
+        machine.run_frame(10).unwrap();
+        machine.cpu_state().registers[0xF]
+    };
+
+    // rows 0 and 2 are lit and hit the first draw; row 1 is blank
+    assert_eq!(run(true), 2);
+    assert_eq!(run(false), 1);
+}
+
+#[test]
+fn scrolling_moves_the_screen_and_clears_behind_it() {
+    // hires, draw glyph 0 at 0,0, scroll down 3 and right 4, then loop forever
+    let rom = [0x00, 0xFF, 0xD0, 0x05, 0x00, 0xC3, 0x00, 0xFB, 0x12, 0x08];
+    let mut machine = Chip8::new(&fontset::FONT_SET, Quirks::default());
+    machine.load_rom(&rom);
+    let mut drawn = machine.clone();
+    drawn.run_frame(2).unwrap();
+    machine.run_frame(10).unwrap();
+
+    for y in 0..5 {
+        for x in 0..8 {
+            assert_eq!(machine.vram().get(x + 4, y + 3), drawn.vram().get(x, y));
+        }
+    }
+    let lit = |m: &Chip8| m.vram().pixels().iter().filter(|&&p| p != 0).count();
+    assert_eq!(lit(&machine), lit(&drawn));
+    assert_eq!(machine.vram().get(0, 0), 0);
+}
+
+#[test]
+fn a_streak_of_unknown_opcodes_faults_at_its_start() {
+    // jump over V0 = 1 into 0xFFFF and the empty RAM after it
+    let mut machine = Chip8::new(&fontset::FONT_SET, Quirks::default());
+    machine.load_rom(&[0x12, 0x04, 0x60, 0x01, 0xFF, 0xFF]);
+    machine.set_runaway_streak(Some(4));
+    assert_eq!(
+        machine.run_frame(10),
+        Err(Fault::Runaway {
+            pc: Addr::new(0x204),
+            jump: Some(Addr::new(0x200))
+        })
+    );
+}
+
+#[test]
+fn fx30_points_i_at_the_big_digits() {
+    // V0 = 8, I = big 8, hires, draw it 10 rows tall, then loop forever
+    let rom = [0x60, 0x08, 0xF0, 0x30, 0x00, 0xFF, 0xD1, 0x1A, 0x12, 0x08];
+    let mut machine = Chip8::new(&fontset::FONT_SET, Quirks::default());
+    machine.load_rom(&rom);
+    machine.run_frame(10).unwrap();
+
+    let glyph = &fontset::BIG_FONT_SET[80..90];
+    for (y, &row) in glyph.iter().enumerate() {
+        for x in 0..8 {
+            let lit = row & (0x80 >> x) != 0;
+            assert_eq!(machine.vram().get(x, y) == 1, lit);
+        }
+    }
+}
+
+#[test]
+fn rgba_frames_follow_the_palette_and_scale() {
+    let mut screen = Framebuffer::new(2, 1, 1);
+    screen.xor(0, 1, 0, 1);
+    let (unlit, lit) = ([1, 2, 3, 255], [4, 5, 6, 255]);
+    let rgba = screen.render_rgba(&[unlit, lit], 2);
+
+    let row = [unlit, unlit, lit, lit].concat();
+    assert_eq!(rgba, [row.clone(), row].concat());
+}
+
+#[test]
+fn roms_past_4k_need_more_ram() {
+    let rom = vec![0xAB; 8000];
+    let mut machine = Chip8::new(&fontset::FONT_SET, Quirks::default());
+    assert_eq!(machine.load_rom(&rom), 8000 - (memory::RAM_SIZE - 0x200));
+
+    machine.set_ram_size(memory::MAX_RAM_SIZE);
+    assert_eq!(machine.load_rom(&rom), 0);
+    assert_eq!(machine.peek(Addr::new(0x200 + 7999)), 0xAB);
+    assert_eq!(machine.peek(Addr::new(0x200 + 8000)), 0);
+}
+
+#[test]
+fn opcode_stats_count_instructions_by_pattern() {
+    // V0 += 1 three times, then a jump to itself
+    let rom = [0x70, 0x01, 0x70, 0x01, 0x70, 0x01, 0x12, 0x06];
+    let mut machine = Chip8::new(&fontset::FONT_SET, Quirks::default());
+    machine.load_rom(&rom);
+    machine.set_opcode_stats(true);
+    for _ in 0..5 {
+        machine.cycle().unwrap();
+    }
+
+    let csv = machine.opcode_stats().unwrap().to_csv();
+    let rows: Vec<_> = csv
+        .lines()
+        .map(|line| line.split(',').take(2).collect::<Vec<_>>())
+        .collect();
+    assert_eq!(rows, [["opcode", "count"], ["7XKK", "3"], ["1NNN", "2"]]);
+    assert_eq!(opstats::pattern(0xD120), "DXY0");
+    assert_eq!(opstats::pattern(0x8FFF), "unknown");
+}
+
+#[test]
+fn exit_stops_the_program_where_it_is() {
+    // V0 = 1, exit, then V0 = 2 which never runs
+    let mut machine = Chip8::new(&fontset::FONT_SET, Quirks::default());
+    machine.load_rom(&[0x60, 0x01, 0x00, 0xFD, 0x60, 0x02]);
+    assert_eq!(machine.run_frame(10), Ok(Status::Exited));
+    assert_eq!(machine.cycle(), Ok(Status::Exited));
+    assert_eq!(machine.cpu_state().pc, Addr::new(0x202));
+    assert_eq!(machine.cpu_state().registers[0], 1);
+}
+
+#[test]
+fn keypad_edges_wait_to_be_taken() {
+    let mut key_5 = [false; 16];
+    key_5[5] = true;
+    let mut keypad = Keypad::default();
+    keypad.update(key_5);
+    keypad.update(key_5);
+    keypad.update([false; 16]);
+    keypad.update([false; 16]);
+
+    assert_eq!(keypad.take_pressed(), Some(5));
+    assert_eq!(keypad.take_pressed(), None);
+    assert_eq!(keypad.take_released(), Some(5));
+    assert_eq!(keypad.take_released(), None);
+
+    keypad.update(key_5);
+    keypad.clear_edges();
+    assert_eq!(keypad.take_pressed(), None);
+    assert!(keypad.is_down(5));
+}
+
+#[test]
+fn a_tap_within_one_frame_reaches_fx0a() {
+    // wait for a key into V0, then jump to itself
+    let mut machine = Chip8::new(&fontset::FONT_SET, Quirks::default());
+    machine.load_rom(&[0xF0, 0x0A, 0x12, 0x02]);
+    assert_eq!(machine.cycle(), Ok(Status::WaitingForKey));
+
+    let start = Instant::now();
+    let ms = |n| start + Duration::from_millis(n);
+    let mut queue = InputQueue::default();
+    queue.push(ms(2), 5, true);
+    queue.push(ms(5), 5, false);
+    // the frame's slices, the last too short to run anything, then the
+    // update the run loop makes after them
+    for until in [ms(8), ms(16), ms(17)] {
+        machine.set_keypad(queue.take_until(until));
+    }
+
+    assert_eq!(
+        machine.run_frame(10),
+        Ok(Status::Finished {
+            pc: Addr::new(0x202)
+        })
+    );
+    assert_eq!(machine.cpu_state().registers[0], 5);
+}
+
+#[test]
+fn rom_settings_blocks_split_off_the_program() {
+    let rom = b"\x12\x00CHIP8-CONFIG\ntitle=Pong\nplatform=schip\ntickrate=15\npalette=#000000 #ffffff\nEND\n";
+    let (program, header) = romheader::split(rom);
+    assert_eq!(program, [0x12, 0x00]);
+    assert_eq!(
+        header,
+        Some(RomHeader {
+            title: Some("Pong".to_string()),
+            platform: Some("schip".to_string()),
+            tickrate: Some(15),
+            palette: Palette::parse("#000000 #ffffff"),
+            problems: Vec::new(),
+        })
+    );
+
+    // without the END line it is all program
+    let rom = b"\x12\x00CHIP8-CONFIG\ntitle=Pong\n";
+    assert_eq!(romheader::split(rom), (&rom[..], None));
+}
+
+#[test]
+fn rom_settings_blocks_reject_runaway_tickrates() {
+    for tickrate in ["0", "1073741824", "fast"] {
+        let rom = format!("\x12\x00CHIP8-CONFIG\ntickrate={}\nEND\n", tickrate);
+        let header = romheader::split(rom.as_bytes()).1.unwrap();
+        assert_eq!(header.tickrate, None);
+        assert_eq!(
+            header.problems,
+            [format!("invalid tickrate '{}'", tickrate)]
+        );
+    }
+}
+
+#[test]
+fn fx1e_carry_flags_leaving_the_12_bit_address_space() {
+    let run = |i: u16, fx1e_carry: bool| {
+        // I = i, V0 = 1, VF = 7, I += V0, then jump to itself
+        let rom = [
+            0xA0 | (i >> 8) as u8,
+            i as u8,
+            0x60,
+            0x01,
+            0x6F,
+            0x07,
+            0xF0,
+            0x1E,
+            0x12,
+            0x08,
+        ];
+        let quirks = Quirks {
+            fx1e_carry,
+            ..Quirks::default()
+        };
+        let mut machine = Chip8::new(&fontset::FONT_SET, quirks);
+        machine.load_rom(&rom);
+        machine.run_frame(10).unwrap();
+        machine.cpu_state().registers[0xF]
+    };
+
+    assert_eq!(run(0xFFF, true), 1);
+    assert_eq!(run(0xFFE, true), 0);
+    assert_eq!(run(0xFFF, false), 7);
+}
@@ -0,0 +1,71 @@
+// Everything the emulator can be asked to do. Frontends only turn input
+// into actions: key combinations, the command palette and dropped files all
+// queue them up, and Emulator::handle_action is the one place they are
+// carried out. The comments give the default key for each.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    // Ctrl+V: load a hex dump or ROM URL from the clipboard.
+    PasteRom,
+    // Ctrl+R: reload the ROM file from disk and restart.
+    ReloadRom,
+    // F5: restart the loaded program without reading it again.
+    Reset,
+    // F6: save the machine's state for this ROM.
+    SaveState,
+    // F12: go back to the state last saved with F6.
+    LoadState,
+    // Backspace held: run time backwards, one saved frame per 60 Hz tick.
+    // Sent with true when the key goes down and false when it comes up.
+    Rewind(bool),
+    // P or Pause: stop and resume execution.
+    TogglePause,
+    // N while paused: execute a single instruction.
+    Step,
+    // F1: show or hide the keypad guide.
+    ToggleKeypadGuide,
+    // F3: show or hide the metrics overlay.
+    ToggleMetrics,
+    // M: show or hide the pixel magnifier.
+    ToggleMagnifier,
+    // Click while paused: flip the game pixel at this vram position.
+    TogglePixel(usize, usize),
+    // Arrow keys: move the magnifier or the palette editor selection.
+    Arrow(i32, i32),
+    // F7: switch to the next built-in palette.
+    CyclePalette,
+    // F8: show or hide the palette editor.
+    TogglePaletteEditor,
+    // F9: save the next presented frames as PNGs.
+    CaptureBurst,
+    // Ctrl+C: copy the last presented frame to the clipboard as an image.
+    CopyScreenshot,
+    // F10: show or hide the profiler overlay.
+    ToggleProfiler,
+    // F11: mark RAM as it is now and show what changes from there on, or
+    // hide the view again.
+    ToggleMemoryDiff,
+    // Shift+F11: take RAM as it is now as the new baseline of the memory diff.
+    MarkMemory,
+    // + and -: run more or fewer instructions per second.
+    ChangeSpeed(bool),
+    // Ctrl+S: run this ROM at the current speed from now on.
+    SaveSpeed,
+    // Command palette only: show or hide the sound timer bar.
+    ToggleBeepBar,
+    // Command palette only: tint each sprite draw of a frame in its own color.
+    ToggleDrawOrder,
+    // Ctrl+P: search all emulator actions by name.
+    ToggleCommandPalette,
+    // Shift+F1..F9: flip a quirk without resetting the machine.
+    ToggleQuirk(usize),
+    // A file was dropped on the window, or opened from Finder on macOS.
+    OpenRom(String),
+    // Ctrl+T: open an empty tab to load another ROM into.
+    NewTab,
+    // Ctrl+Tab and Ctrl+Shift+Tab: show the next or previous open ROM.
+    SwitchTab(bool),
+    // Ctrl+F4: close the shown ROM's tab.
+    CloseTab,
+    // Window closed, or Cmd+Q/Cmd+W on macOS.
+    Quit,
+}
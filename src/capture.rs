@@ -0,0 +1,87 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use chip8_tools::png::{self, Image};
+
+// Frames per row of a sprite sheet.
+const SHEET_COLUMNS: usize = 8;
+// Gap between frames on a sprite sheet, in frame pixels.
+const SHEET_GAP: usize = 1;
+
+// Captures a run of consecutive presented frames, either as numbered PNGs
+// or as a single sprite sheet written once the last frame is in.
+pub struct Burst {
+    dir: PathBuf,
+    frames: usize,
+    captured: usize,
+    sheet: Option<Vec<Image>>,
+}
+
+impl Burst {
+    // Start a burst of `frames` frames in a new timestamped directory under `data_dir`.
+    pub fn new(data_dir: &Path, frames: usize, sheet: bool) -> io::Result<Self> {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |t| t.as_millis());
+        let dir = data_dir.join("screenshots").join(format!("burst-{}", time));
+        fs::create_dir_all(&dir)?;
+
+        Ok(Self {
+            dir,
+            frames,
+            captured: 0,
+            sheet: if sheet { Some(Vec::new()) } else { None },
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    // Add the next frame. Returns true once the burst is complete.
+    pub fn add(&mut self, image: Image) -> io::Result<bool> {
+        self.captured += 1;
+        match &mut self.sheet {
+            Some(images) => images.push(image),
+            None => {
+                let path = self.dir.join(format!("frame-{:04}.png", self.captured));
+                let png = png::encode(image.width as u32, image.height as u32, &image.rgb);
+                fs::write(path, png)?;
+            }
+        }
+
+        let done = self.captured >= self.frames;
+        if done {
+            if let Some(images) = &self.sheet {
+                let sheet = sprite_sheet(images);
+                let png = png::encode(sheet.width as u32, sheet.height as u32, &sheet.rgb);
+                fs::write(self.dir.join("sheet.png"), png)?;
+            }
+        }
+        Ok(done)
+    }
+}
+
+// Lay frames out left to right, top to bottom, SHEET_COLUMNS to a row.
+pub fn sprite_sheet(images: &[Image]) -> Image {
+    let (frame_width, frame_height) = (images[0].width, images[0].height);
+    let columns = images.len().min(SHEET_COLUMNS);
+    let rows = images.len().div_ceil(columns);
+    let width = columns * (frame_width + SHEET_GAP) - SHEET_GAP;
+    let height = rows * (frame_height + SHEET_GAP) - SHEET_GAP;
+
+    let mut rgb = vec![0; width * height * 3];
+    for (n, image) in images.iter().enumerate() {
+        let left = (n % columns) * (frame_width + SHEET_GAP);
+        let top = (n / columns) * (frame_height + SHEET_GAP);
+        for (y, row) in image.rgb.chunks(frame_width * 3).enumerate() {
+            let start = ((top + y) * width + left) * 3;
+            rgb[start..start + row.len()].copy_from_slice(row);
+        }
+    }
+
+    Image { width, height, rgb }
+}
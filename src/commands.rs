@@ -0,0 +1,146 @@
+use crate::action::Action;
+use crate::quirks;
+
+// Most matches the command palette shows at once.
+const MAX_SHOWN: usize = 8;
+
+// An emulator action as listed in the command palette.
+pub struct Command {
+    pub name: String,
+    // the keys it is bound to, shown next to the name so they can be learned
+    pub binding: String,
+    pub action: Action,
+}
+
+// Every action that can be run from the command palette.
+pub fn all() -> Vec<Command> {
+    let command = |name: &str, binding: &str, action| Command {
+        name: name.to_string(),
+        binding: binding.to_string(),
+        action,
+    };
+
+    let mut commands = vec![
+        command("Pause / resume", "P", Action::TogglePause),
+        command("Step one instruction", "N", Action::Step),
+        command("Reload ROM", "Ctrl+R", Action::ReloadRom),
+        command("Reset", "F5", Action::Reset),
+        command("Save state", "F6", Action::SaveState),
+        command("Load state", "F12", Action::LoadState),
+        command("Paste ROM from clipboard", "Ctrl+V", Action::PasteRom),
+        command("New tab", "Ctrl+T", Action::NewTab),
+        command("Next tab", "Ctrl+Tab", Action::SwitchTab(true)),
+        command("Previous tab", "Ctrl+Shift+Tab", Action::SwitchTab(false)),
+        command("Close tab", "Ctrl+F4", Action::CloseTab),
+        command("Speed up", "+", Action::ChangeSpeed(true)),
+        command("Slow down", "-", Action::ChangeSpeed(false)),
+        command("Save speed for this ROM", "Ctrl+S", Action::SaveSpeed),
+        command("Show keypad", "F1", Action::ToggleKeypadGuide),
+        command("Toggle metrics", "F3", Action::ToggleMetrics),
+        command("Toggle magnifier", "M", Action::ToggleMagnifier),
+        command("Next palette", "F7", Action::CyclePalette),
+        command("Edit palette", "F8", Action::TogglePaletteEditor),
+        command("Capture screenshot burst", "F9", Action::CaptureBurst),
+        command("Copy screenshot", "Ctrl+C", Action::CopyScreenshot),
+        command("Toggle profiler", "F10", Action::ToggleProfiler),
+        command("Toggle memory diff", "F11", Action::ToggleMemoryDiff),
+        command("Mark memory baseline", "Shift+F11", Action::MarkMemory),
+        command("Toggle beep bar", "", Action::ToggleBeepBar),
+        command("Toggle draw order view", "", Action::ToggleDrawOrder),
+        command("Quit", "", Action::Quit),
+    ];
+    for (n, name) in quirks::NAMES.iter().enumerate() {
+        commands.push(Command {
+            name: format!("Toggle quirk {}", name),
+            binding: format!("Shift+F{}", n + 1),
+            action: Action::ToggleQuirk(n),
+        });
+    }
+    commands
+}
+
+// State of the open command palette: the typed query and the highlighted match.
+pub struct CommandPalette {
+    commands: Vec<Command>,
+    query: String,
+    selected: usize,
+}
+
+impl CommandPalette {
+    pub fn new() -> Self {
+        Self {
+            commands: all(),
+            query: String::new(),
+            selected: 0,
+        }
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn type_text(&mut self, text: &str) {
+        self.query.push_str(text);
+        self.selected = 0;
+    }
+
+    pub fn backspace(&mut self) {
+        self.query.pop();
+        self.selected = 0;
+    }
+
+    pub fn move_selection(&mut self, rows: i32) {
+        let count = self.matches().len().max(1) as i32;
+        self.selected = (self.selected as i32 + rows).rem_euclid(count) as usize;
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    // Commands matching the query, best first.
+    pub fn matches(&self) -> Vec<&Command> {
+        let mut scored: Vec<_> = self
+            .commands
+            .iter()
+            .filter_map(|c| fuzzy_score(&self.query, &c.name).map(|score| (score, c)))
+            .collect();
+        // stable, so equally good matches keep the registry order
+        scored.sort_by_key(|&(score, _)| -score);
+        scored.into_iter().take(MAX_SHOWN).map(|(_, c)| c).collect()
+    }
+
+    pub fn selected_action(&self) -> Option<Action> {
+        self.matches()
+            .get(self.selected)
+            .map(|command| command.action.clone())
+    }
+}
+
+// How well `query` matches `name`, or None if it doesn't. Every character of
+// the query has to appear in the name in order, ignoring case and spaces;
+// runs of consecutive characters and characters starting a word score higher,
+// so "tm" prefers "Toggle magnifier" over "Step one instruction".
+pub fn fuzzy_score(query: &str, name: &str) -> Option<i32> {
+    let name: Vec<char> = name.chars().map(|c| c.to_ascii_lowercase()).collect();
+    let mut score = 0;
+    let mut pos = 0;
+    let mut previous = None;
+
+    for q in query.chars().filter(|c| !c.is_whitespace()) {
+        let q = q.to_ascii_lowercase();
+        let found = pos + name[pos..].iter().position(|&c| c == q)?;
+
+        score += 1;
+        if previous.is_some_and(|p| p + 1 == found) {
+            score += 4;
+        }
+        if found == 0 || !name[found - 1].is_alphanumeric() {
+            score += 6;
+        }
+
+        previous = Some(found);
+        pos = found + 1;
+    }
+    Some(score)
+}
@@ -0,0 +1,563 @@
+use crate::addr::Addr;
+use crate::chip8::UnknownOpcodes;
+use crate::display::KeyMapping;
+use crate::emulator::{MAX_IPS, MIN_IPS};
+use crate::filter::{self, FilterSpec};
+use crate::fontset;
+use crate::hud::Hud;
+use crate::keys;
+use crate::memory;
+use crate::pacing::Pacing;
+use crate::palette::{self, Palette};
+use crate::quirks::{self, KeyWait, Quirks};
+use crate::romheader;
+use crate::stick::StickMapping;
+use crate::timers::StepTimers;
+
+// Unknown opcodes in a row that stop the program by default. Real programs
+// hardly ever run even two; a program counter lost in sprite data or empty
+// RAM runs nothing else.
+const DEFAULT_UNKNOWN_STREAK: usize = 16;
+
+#[derive(Debug)]
+pub struct Config {
+    pub rom_path: Option<String>,
+    pub key_mapping: KeyMapping,
+    // Host inputs pressing a hex key, by frontend-neutral name (see keys.rs),
+    // taking over from the default layout for those inputs.
+    pub key_bindings: Vec<(String, usize)>,
+    // Which keys a gamepad's left stick presses.
+    pub stick_mapping: StickMapping,
+    pub quirks: Quirks,
+    // The hex digit font, built in or read from a file with --font.
+    pub font: Vec<u8>,
+    // Platform whose quirk preset was picked with --platform, overriding the ROM's own setting.
+    pub platform: Option<String>,
+    // Where programs are loaded and start, overriding the platform's address.
+    pub load_address: Option<Addr>,
+    // Bytes of RAM, 4 KB unless more is asked for.
+    pub ram_size: usize,
+    // Quirks set individually, applied on top of whichever preset is in use.
+    pub quirk_overrides: Vec<(String, String)>,
+    pub log_to_file: bool,
+    // Log what the emulator does in more detail, for bug reports.
+    pub verbose: bool,
+    // Window scale; picked from the display DPI when not given.
+    pub scale: Option<u32>,
+    // Experimental: blend all emulated frames into each presented one.
+    pub motion_blend: bool,
+    // Log a summary of the session on exit.
+    pub summary: bool,
+    // CSV file to write instruction counts and time per opcode to on exit.
+    pub opcode_stats: Option<String>,
+    // Report fatal errors as JSON objects on stderr, for wrapper scripts.
+    pub json_errors: bool,
+    // Run the ROM headlessly for this many frames checking for nondeterminism.
+    pub audit_frames: Option<u64>,
+    // Run this many randomly mutated copies of the ROM headlessly, checking for panics.
+    pub fuzz_mutants: Option<u64>,
+    // Run this other build of the ROM alongside it headlessly and report the
+    // first frame where their screens differ.
+    pub diff_rom: Option<String>,
+    // Snapshot the ROM at this frame and check which quirks change the
+    // screen over the frames that follow.
+    pub bisect_frame: Option<u64>,
+    // Rate the delay and sound timers count down at.
+    pub timer_hz: u32,
+    // Whether single steps while paused advance the timers.
+    pub step_timers: StepTimers,
+    // Instructions per 60 Hz frame, overriding the ROM's own setting.
+    pub tickrate: Option<u32>,
+    // Instructions per second, overriding both --tickrate and the ROM.
+    pub ips: Option<u32>,
+    // Seed for CXKK, so every run of a ROM sees the same random numbers.
+    pub seed: Option<u64>,
+    // Colors, overriding the ROM's own setting.
+    pub palette: Option<Palette>,
+    // Present at 30 Hz, skip redundant redraws and sleep longer when idle.
+    pub low_power: bool,
+    // How the run loop waits for the next frame.
+    pub pacing: Pacing,
+    // Raise the OS timer resolution to 1 ms (Windows only).
+    pub high_res_timer: bool,
+    // Only scale the game by whole numbers when the window is resized.
+    pub integer_scale: bool,
+    // Color around the game when it doesn't fill the window.
+    pub border_color: Option<(u8, u8, u8)>,
+    // Margin drawn around the game, in lores pixels horizontally and vertically.
+    pub overscan: (u32, u32),
+    // Flash the overscan margin while the buzzer sounds.
+    pub flash_overscan: bool,
+    // Sound the buzzer for part of every timer tick only, so it stutters
+    // with the frame rate like some original hardware.
+    pub pulsed_buzz: bool,
+    // Number of frames a screenshot burst captures.
+    pub burst_frames: usize,
+    // Save screenshot bursts as one sprite sheet instead of numbered PNGs.
+    pub burst_sheet: bool,
+    // Show a bar counting down long beeps.
+    pub beep_bar: bool,
+    // Show the frame after the current one, emulated ahead with the current keys.
+    pub run_ahead: bool,
+    // Stop once the program jumps to itself, the usual way for it to end.
+    pub halt_when_finished: bool,
+    // What to do about opcodes the machine doesn't implement.
+    pub unknown_opcodes: UnknownOpcodes,
+    // Unknown opcodes in a row taken to mean the program counter has run
+    // into data, or None to never assume so.
+    pub unknown_streak: Option<usize>,
+    // Stop with a fault on such a streak rather than pausing to look around.
+    pub unknown_streak_faults: bool,
+    // Nonstandard: map host time, a frame counter and random bytes into RAM.
+    pub host_ports: bool,
+    // Post-processing applied to the picture, in order.
+    pub filters: Vec<FilterSpec>,
+    // More ROMs to open in tabs of their own next to the first one.
+    pub tabs: Vec<String>,
+    // Custom overlay drawn from game memory every frame.
+    pub hud: Option<Hud>,
+    // File to export live machine state to every frame (see sharedmem.rs).
+    pub shared_memory: Option<String>,
+    // Run plain CHIP-8 with nothing customized, to rule settings out when a
+    // game looks wrong.
+    pub safe_mode: bool,
+}
+
+impl Config {
+    pub fn from_args<I: Iterator<Item = String>>(mut args: I) -> Result<Self, String> {
+        let mut rom_path = None;
+        let mut key_mapping = KeyMapping::Scancode;
+        let mut key_bindings = Vec::new();
+        let mut stick_mapping = StickMapping::default();
+        let mut quirks = Quirks::default();
+        let mut font = fontset::FONT_SET.to_vec();
+        let mut platform = None;
+        let mut load_address = None;
+        let mut ram_size = memory::RAM_SIZE;
+        let mut quirk_overrides = Vec::new();
+        // there is no console to log to in windowed release builds on Windows
+        let mut log_to_file = cfg!(all(windows, not(debug_assertions)));
+        let mut verbose = false;
+        let mut scale = None;
+        let mut motion_blend = false;
+        let mut summary = false;
+        let mut opcode_stats = None;
+        let mut json_errors = false;
+        let mut audit_frames = None;
+        let mut fuzz_mutants = None;
+        let mut diff_rom = None;
+        let mut bisect_frame = None;
+        let mut timer_hz = 60;
+        let mut step_timers = StepTimers::Frozen;
+        let mut tickrate = None;
+        let mut ips = None;
+        let mut seed = None;
+        let mut palette = None;
+        let mut low_power = false;
+        let mut pacing = Pacing::Sleep;
+        let mut high_res_timer = false;
+        let mut integer_scale = false;
+        let mut border_color = None;
+        let mut overscan = (0, 0);
+        let mut flash_overscan = false;
+        let mut pulsed_buzz = false;
+        let mut halt_when_finished = false;
+        let mut unknown_opcodes = UnknownOpcodes::Warn;
+        let mut unknown_streak = Some(DEFAULT_UNKNOWN_STREAK);
+        let mut unknown_streak_faults = false;
+        let mut burst_frames = 30;
+        let mut burst_sheet = false;
+        let mut beep_bar = false;
+        let mut run_ahead = false;
+        let mut host_ports = false;
+        let mut filters = Vec::new();
+        let mut hud = None;
+        let mut shared_memory = None;
+        let mut tabs = Vec::new();
+        let mut safe_mode = false;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--key-mapping" => {
+                    let value = next_value(&mut args, &arg)?;
+                    key_mapping = KeyMapping::parse(&value).ok_or_else(|| {
+                        format!(
+                            "Invalid key mapping '{}' (expected scancode or keycode)",
+                            value
+                        )
+                    })?;
+                }
+                "--bind" => key_bindings.push(keys::parse_binding(&next_value(&mut args, &arg)?)?),
+                "--stick-keys" => {
+                    let value = next_value(&mut args, &arg)?;
+                    stick_mapping.keys = StickMapping::parse_keys(&value).ok_or_else(|| {
+                        format!(
+                            "Invalid stick keys '{}' (expected four hex digits for up, left, right, down)",
+                            value
+                        )
+                    })?;
+                }
+                "--dead-zone" => {
+                    let value = next_value(&mut args, &arg)?;
+                    match value.parse() {
+                        Ok(n) if (0.0..1.0).contains(&n) => stick_mapping.dead_zone = n,
+                        _ => return Err(format!("Invalid dead zone '{}'", value)),
+                    }
+                }
+                "--stick-diagonals" => stick_mapping.diagonals = true,
+                "--key-wait" => {
+                    let value = next_value(&mut args, &arg)?;
+                    quirks.key_wait = KeyWait::parse(&value).ok_or_else(|| {
+                        format!("Invalid key wait '{}' (expected release or press)", value)
+                    })?;
+                    quirk_overrides.push(("key-wait".to_string(), value));
+                }
+                "--platform" => {
+                    let value = next_value(&mut args, &arg)?;
+                    quirks = Quirks::preset(&value).ok_or_else(|| {
+                        format!(
+                            "Unknown platform '{}' (expected one of {})",
+                            value,
+                            quirks::PLATFORMS.join(", ")
+                        )
+                    })?;
+                    // quirks given earlier on the command line still apply
+                    for (name, setting) in quirk_overrides.iter() {
+                        quirks.set(name, setting)?;
+                    }
+                    platform = Some(value);
+                }
+                "--load-address" => {
+                    let value = next_value(&mut args, &arg)?;
+                    let digits = value.strip_prefix("0x").unwrap_or(&value);
+                    load_address = match u16::from_str_radix(digits, 16) {
+                        Ok(addr) => Some(Addr::new(addr)),
+                        Err(_) => return Err(format!("Invalid load address '{}'", value)),
+                    };
+                }
+                "--font" => font = parse_font(&next_value(&mut args, &arg)?)?,
+                "--ram-size" => {
+                    let value = next_value(&mut args, &arg)?;
+                    ram_size = parse_ram_size(&value).ok_or_else(|| {
+                        format!("Invalid RAM size '{}' (expected 4K to 64K)", value)
+                    })?;
+                }
+                "--log-file" => log_to_file = true,
+                "--verbose" => verbose = true,
+                "--motion-blend" => motion_blend = true,
+                "--summary" => summary = true,
+                "--opcode-stats" => opcode_stats = Some(next_value(&mut args, &arg)?),
+                "--json-errors" => json_errors = true,
+                "--low-power" => low_power = true,
+                "--high-res-timer" => high_res_timer = true,
+                "--pacing" => {
+                    let value = next_value(&mut args, &arg)?;
+                    pacing = Pacing::parse(&value).ok_or_else(|| {
+                        format!(
+                            "Invalid pacing '{}' (expected sleep, spin or hybrid)",
+                            value
+                        )
+                    })?;
+                }
+                "--integer-scale" => integer_scale = true,
+                "--burst-sheet" => burst_sheet = true,
+                "--beep-bar" => beep_bar = true,
+                "--run-ahead" => run_ahead = true,
+                "--flash-overscan" => flash_overscan = true,
+                "--pulsed-buzz" => pulsed_buzz = true,
+                "--halt-when-finished" => halt_when_finished = true,
+                "--unknown-opcodes" => {
+                    let value = next_value(&mut args, &arg)?;
+                    unknown_opcodes = UnknownOpcodes::parse(&value).ok_or_else(|| {
+                        format!(
+                            "Invalid unknown opcode policy '{}' (expected ignore, warn or halt)",
+                            value
+                        )
+                    })?;
+                }
+                "--unknown-streak" => {
+                    let value = next_value(&mut args, &arg)?;
+                    unknown_streak = match value.parse() {
+                        Ok(0) => None,
+                        Ok(n) => Some(n),
+                        Err(_) => return Err(format!("Invalid unknown opcode streak '{}'", value)),
+                    };
+                }
+                "--unknown-streak-faults" => unknown_streak_faults = true,
+                "--overscan" => {
+                    let value = next_value(&mut args, &arg)?;
+                    overscan = parse_overscan(&value).ok_or_else(|| {
+                        format!("Invalid overscan '{}' (expected N or X,Y)", value)
+                    })?;
+                }
+                "--host-ports" => host_ports = true,
+                "--burst-frames" => {
+                    let value = next_value(&mut args, &arg)?;
+                    match value.parse() {
+                        Ok(n) if n > 0 => burst_frames = n,
+                        _ => return Err(format!("Invalid frame count '{}'", value)),
+                    }
+                }
+                "--border-color" => {
+                    let value = next_value(&mut args, &arg)?;
+                    border_color = Some(palette::parse_color(&value).ok_or_else(|| {
+                        format!("Invalid border color '{}' (expected #rrggbb)", value)
+                    })?);
+                }
+                "--filters" => filters = filter::parse_chain(&next_value(&mut args, &arg)?)?,
+                "--tab" => tabs.push(next_value(&mut args, &arg)?),
+                "--hud" => {
+                    let path = next_value(&mut args, &arg)?;
+                    let text = std::fs::read_to_string(&path)
+                        .map_err(|e| format!("Could not read HUD {}: {}", path, e))?;
+                    hud = Some(Hud::parse(&text)?);
+                }
+                "--safe-mode" => safe_mode = true,
+                "--shared-memory" => shared_memory = Some(next_value(&mut args, &arg)?),
+                "--timer-hz" => {
+                    let value = next_value(&mut args, &arg)?;
+                    match value.parse() {
+                        Ok(hz) if hz > 0 => timer_hz = hz,
+                        _ => return Err(format!("Invalid timer rate '{}'", value)),
+                    }
+                }
+                "--step-timers" => {
+                    let value = next_value(&mut args, &arg)?;
+                    step_timers = StepTimers::parse(&value).ok_or_else(|| {
+                        format!(
+                            "Invalid step timers '{}' (expected frozen or proportional)",
+                            value
+                        )
+                    })?;
+                }
+                "--tickrate" => {
+                    let value = next_value(&mut args, &arg)?;
+                    match romheader::parse_tickrate(&value) {
+                        Some(n) => tickrate = Some(n),
+                        None => {
+                            return Err(format!(
+                                "Invalid tickrate '{}' (expected 1 to {})",
+                                value,
+                                romheader::MAX_TICKRATE
+                            ))
+                        }
+                    }
+                }
+                "--ips" => {
+                    let value = next_value(&mut args, &arg)?;
+                    match value.parse() {
+                        Ok(n) if (MIN_IPS..=MAX_IPS).contains(&n) => ips = Some(n),
+                        _ => {
+                            return Err(format!(
+                                "Invalid instructions per second '{}' (expected {} to {})",
+                                value, MIN_IPS, MAX_IPS
+                            ))
+                        }
+                    }
+                }
+                "--seed" => {
+                    let value = next_value(&mut args, &arg)?;
+                    seed = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("Invalid seed '{}'", value))?,
+                    );
+                }
+                "--palette" => {
+                    let value = next_value(&mut args, &arg)?;
+                    palette = Some(Palette::parse(&value).ok_or_else(|| {
+                        format!("Invalid palette '{}' (expected #rrggbb,#rrggbb)", value)
+                    })?);
+                }
+                "--audit-determinism" => {
+                    let value = next_value(&mut args, &arg)?;
+                    let frames = value
+                        .parse()
+                        .map_err(|_| format!("Invalid frame count '{}'", value))?;
+                    audit_frames = Some(frames);
+                }
+                "--diff" => diff_rom = Some(next_value(&mut args, &arg)?),
+                "--bisect-quirks" => {
+                    let value = next_value(&mut args, &arg)?;
+                    let frame = value
+                        .parse()
+                        .map_err(|_| format!("Invalid frame number '{}'", value))?;
+                    bisect_frame = Some(frame);
+                }
+                "--fuzz" => {
+                    let value = next_value(&mut args, &arg)?;
+                    let mutants = value
+                        .parse()
+                        .map_err(|_| format!("Invalid mutant count '{}'", value))?;
+                    fuzz_mutants = Some(mutants);
+                }
+                "--scale" => {
+                    let value = next_value(&mut args, &arg)?;
+                    match value.parse() {
+                        Ok(n) if n > 0 => scale = Some(n),
+                        _ => return Err(format!("Invalid scale '{}'", value)),
+                    }
+                }
+                "--quirk" => {
+                    let value = next_value(&mut args, &arg)?;
+                    let (name, setting) = value.split_once('=').ok_or_else(|| {
+                        format!("Invalid quirk '{}' (expected name=value)", value)
+                    })?;
+                    quirks.set(name, setting)?;
+                    quirk_overrides.push((name.to_string(), setting.to_string()));
+                }
+                _ if arg.starts_with("--") => return Err(format!("Unknown option: {}", arg)),
+                _ => rom_path = Some(arg),
+            }
+        }
+
+        // loading a program clears RAM from its address on
+        if let Some(addr) = load_address {
+            let fonts_end = memory::Layout::default().fonts_end();
+            if addr.index() < fonts_end || addr.index() >= ram_size {
+                return Err(format!(
+                    "Load address {:#05x} is outside RAM past the fonts ({:#05x} to {:#05x})",
+                    addr,
+                    fonts_end,
+                    ram_size - 1
+                ));
+            }
+        }
+
+        let config = Self {
+            rom_path,
+            key_mapping,
+            key_bindings,
+            stick_mapping,
+            quirks,
+            font,
+            platform,
+            load_address,
+            ram_size,
+            quirk_overrides,
+            log_to_file,
+            verbose,
+            scale,
+            motion_blend,
+            summary,
+            opcode_stats,
+            json_errors,
+            audit_frames,
+            fuzz_mutants,
+            diff_rom,
+            bisect_frame,
+            timer_hz,
+            step_timers,
+            tickrate,
+            ips,
+            seed,
+            palette,
+            low_power,
+            pacing,
+            high_res_timer,
+            integer_scale,
+            border_color,
+            overscan,
+            flash_overscan,
+            pulsed_buzz,
+            halt_when_finished,
+            unknown_opcodes,
+            unknown_streak,
+            unknown_streak_faults,
+            burst_frames,
+            burst_sheet,
+            beep_bar,
+            run_ahead,
+            host_ports,
+            filters,
+            tabs,
+            hud,
+            shared_memory,
+            safe_mode,
+        };
+        Ok(if safe_mode { config.safe() } else { config })
+    }
+
+    // These settings with everything that changes how a game runs or looks
+    // put back to the plain CHIP-8 defaults, and verbose logging on. Input,
+    // speed and the tools are left alone.
+    fn safe(self) -> Self {
+        Self {
+            verbose: true,
+            quirks: Quirks::default(),
+            font: fontset::FONT_SET.to_vec(),
+            platform: Some("chip8".to_string()),
+            load_address: None,
+            ram_size: memory::RAM_SIZE,
+            quirk_overrides: Vec::new(),
+            motion_blend: false,
+            palette: Some(Palette::default()),
+            border_color: None,
+            overscan: (0, 0),
+            flash_overscan: false,
+            pulsed_buzz: false,
+            run_ahead: false,
+            host_ports: false,
+            filters: Vec::new(),
+            hud: None,
+            ..self
+        }
+    }
+}
+
+fn next_value<I: Iterator<Item = String>>(args: &mut I, option: &str) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("Missing value for {}", option))
+}
+
+// "N" for the same margin all round, or "X,Y" for separate horizontal and
+// vertical margins. Kept small enough that the game stays the main thing.
+fn parse_overscan(value: &str) -> Option<(u32, u32)> {
+    let (x, y) = match value.split_once(',') {
+        Some((x, y)) => (x.parse().ok()?, y.parse().ok()?),
+        None => {
+            let n = value.parse().ok()?;
+            (n, n)
+        }
+    };
+    if x > 32 || y > 32 {
+        return None;
+    }
+    Some((x, y))
+}
+
+// One of the interpreter fonts by name, or a file of 80 bytes: 5 per digit,
+// 0 to F, the top bit of each the leftmost pixel.
+fn parse_font(value: &str) -> Result<Vec<u8>, String> {
+    if let Some(font) = fontset::named(value) {
+        return Ok(font.to_vec());
+    }
+    let font = std::fs::read(value).map_err(|e| {
+        format!(
+            "Could not read font {}: {} (built-in fonts are {})",
+            value,
+            e,
+            fontset::NAMES.join(", ")
+        )
+    })?;
+    if font.len() != fontset::FONT_SET.len() {
+        return Err(format!(
+            "Font {} is {} bytes, expected {}",
+            value,
+            font.len(),
+            fontset::FONT_SET.len()
+        ));
+    }
+    Ok(font)
+}
+
+// A byte count, or kilobytes with a K after them, like 64K.
+fn parse_ram_size(value: &str) -> Option<usize> {
+    let size = match value.strip_suffix(['K', 'k']) {
+        Some(kilobytes) => kilobytes.parse::<usize>().ok()? * 1024,
+        None => value.parse().ok()?,
+    };
+    (memory::RAM_SIZE..=memory::MAX_RAM_SIZE)
+        .contains(&size)
+        .then_some(size)
+}
@@ -0,0 +1,68 @@
+use std::{
+    fs, io, panic,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use sdl2::messagebox::{show_simple_message_box, MessageBoxFlag};
+
+// Message and location of the last panic, picked up by the crash report.
+static LAST_PANIC: Mutex<Option<String>> = Mutex::new(None);
+
+// Remember panic messages for write_report(), on top of printing them as usual.
+pub fn install_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        *LAST_PANIC.lock().unwrap_or_else(|e| e.into_inner()) = Some(info.to_string());
+        default_hook(info);
+    }));
+}
+
+// Write a report about the last panic to a new file in `dir`. `context`
+// describes the machine and `settings` the options it was started with.
+pub fn write_report(dir: &Path, context: &str, settings: &str) -> io::Result<PathBuf> {
+    let panic = LAST_PANIC
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take()
+        .unwrap_or_else(|| "unknown panic".to_string());
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |t| t.as_secs());
+
+    let mut report = String::new();
+    report += &format!("{}\n\n", panic);
+    report += &format!(
+        "chip8-rs {} ({} build, {}-{})\n",
+        env!("CARGO_PKG_VERSION"),
+        if cfg!(debug_assertions) {
+            "debug"
+        } else {
+            "release"
+        },
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    );
+    report += &format!("time: {}\n\n", time);
+    report += &format!("{}\n", context);
+    report += &format!("settings: {}\n", settings);
+
+    let dir = dir.join("crashes");
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("crash-{}.txt", time));
+    fs::write(&path, report)?;
+    Ok(path)
+}
+
+// Tell the user where the report went, since there may be no console to see the panic.
+pub fn show_message(report: &io::Result<PathBuf>) {
+    let message = match report {
+        Ok(path) => format!(
+            "The emulator crashed. A report was saved to\n{}\n\nPlease attach it when reporting the problem.",
+            path.display()
+        ),
+        Err(e) => format!("The emulator crashed, and the crash report could not be saved: {}", e),
+    };
+    let _ = show_simple_message_box(MessageBoxFlag::ERROR, "CHIP-8", &message, None);
+}
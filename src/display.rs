@@ -1,159 +1,1585 @@
-use sdl2;
-use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use sdl2::pixels;
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired, AudioStatus};
+use sdl2::controller::{Axis, Button, GameController};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod, Scancode};
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::{self, PixelFormatEnum};
 use sdl2::rect::Rect;
-use sdl2::render::Canvas;
+use sdl2::render::{BlendMode, Canvas};
 use sdl2::video::Window;
+use sdl2::AudioSubsystem;
 use sdl2::EventPump;
+use sdl2::GameControllerSubsystem;
+use sdl2::VideoSubsystem;
 
-const WIDTH: u32 = 64;
-const HEIGHT: u32 = 32;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
+use crate::action::Action;
+use crate::commands::CommandPalette;
+use crate::config::Config;
+use crate::filter::{self, Filter, Frame};
+use crate::framebuffer::Framebuffer;
+use crate::hud::Shape;
+use crate::input::InputQueue;
+use crate::palette::{self, Palette};
+use crate::stick::StickMapping;
+use crate::text;
+use chip8_tools::png::Image;
+
+use crate::chip8;
+
+// Window title until a ROM gives one.
+pub const DEFAULT_TITLE: &str = "Chip-8 Emulator";
+
+// Size of the emulated screen in lores pixels, which sets the window size.
+const WIDTH: u32 = chip8::LORES_WIDTH as u32;
+const HEIGHT: u32 = chip8::LORES_HEIGHT as u32;
+
+// Size of the frame buffer, big enough for SCHIP hires mode.
+const FRAME_WIDTH: usize = chip8::WIDTH;
+const FRAME_HEIGHT: usize = chip8::HEIGHT;
+
+// Window scale at the reference 96 DPI.
 const PIXEL_SCALE: u32 = 8;
-const SCR_WIDTH: u32 = WIDTH * PIXEL_SCALE;
-const SCR_HEIGHT: u32 = HEIGHT * PIXEL_SCALE;
+const REFERENCE_DPI: f32 = 96.0;
+
+// The magnifier shows this many pixels on each side of its center.
+const MAGNIFIER_RADIUS: i32 = 4;
+
+const TOAST_DURATION: Duration = Duration::from_secs(3);
+
+// How host keys are translated into hex keypad indices.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyMapping {
+    // Physical key positions (QWERTY layout), independent of the OS keyboard layout.
+    Scancode,
+    // Whatever character the OS keyboard layout produces.
+    Keycode,
+}
+
+impl KeyMapping {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "scancode" => Some(KeyMapping::Scancode),
+            "keycode" => Some(KeyMapping::Keycode),
+            _ => None,
+        }
+    }
+}
 
 pub struct Display {
     canvas: Canvas<Window>,
+    video: VideoSubsystem,
     events: EventPump,
-    audio: AudioDevice<SquareWave>,
+    audio_subsystem: AudioSubsystem,
+    // None while no output device could be opened
+    audio: Option<AudioDevice<SquareWave>>,
+    key_mapping: KeyMapping,
+    // --bind overrides, by keys.rs input name
+    key_bindings: HashMap<String, usize>,
+    // hex keys held on the keyboard and gamepads, as of the latest event
+    keys_down: [bool; 16],
+    // the same changes, waiting for the frames they happened in
+    input: InputQueue,
+    // when SDL started, which its event timestamps count from
+    sdl_epoch: Instant,
+    controller_subsystem: GameControllerSubsystem,
+    // open gamepads; they are closed when dropped
+    controllers: Vec<GameController>,
+    stick_mapping: StickMapping,
+    // left stick position, -1.0 to 1.0 on each axis
+    stick: (f32, f32),
+    actions: Vec<Action>,
+    // brightness of each pixel summed over the frames pushed since the last present
+    frame: [[f32; FRAME_WIDTH]; FRAME_HEIGHT],
+    blended_frames: u32,
+    // resolution of the last pushed frame, in case the program switched modes
+    resolution: (usize, usize),
+    motion_blend: bool,
+    // post-processing applied to the game picture, in order
+    filters: Vec<Box<dyn Filter>>,
+    // pixel at the center of the magnifier, when it is shown
+    magnifier: Option<(usize, usize)>,
+    metrics: Vec<String>,
+    show_metrics: bool,
+    // the keypad guide, with the host key for each hex key
+    show_keypad: bool,
+    palette: Palette,
+    // selected color channel, while the palette editor is open
+    palette_editor: Option<usize>,
+    // only scale the game by whole numbers, centered in the window
+    integer_scale: bool,
+    // fills the window around the game, the palette background when not set
+    border_color: Option<(u8, u8, u8)>,
+    // margin around the game in lores pixels, horizontally and vertically,
+    // drawn in the palette background like the overscan area of a TV
+    overscan: (u32, u32),
+    // light the overscan area up in the foreground color while the buzzer sounds
+    flash_overscan: bool,
+    beeping: bool,
+    // timer rate the buzzer is gated to, when the buzz is pulsed
+    pulsed_buzz: Option<u32>,
+    // draw order view: the DXYN number, counting from 1, that last drew to
+    // each frame pixel this frame, 0 for none
+    draw_tags: Option<Vec<u16>>,
+    // fraction of a long beep still to go, shown as a bar under the game
+    beep_bar: Option<f32>,
+    // custom overlay from --hud
+    hud: Vec<Shape>,
+    // profiler overlay: how often each frame pixel is drawn to, 0.0 to 1.0,
+    // and a list of the busiest addresses
+    heatmap: Option<Vec<f32>>,
+    hot_spots: Vec<String>,
+    // lines of the memory diff panel, while it is shown
+    memory_diff: Option<Vec<String>>,
+    // the vram pixel under the mouse, and what the emulator says about it
+    hovered: Option<(usize, usize)>,
+    pixel_info: Option<String>,
+    // while open, the keyboard types into it instead of driving the keypad
+    command_palette: Option<CommandPalette>,
+    // short notification shown at the bottom of the window, and when it expires
+    toast: Option<(String, Instant)>,
+    redraw: bool,
+    // events picked up while sleeping, handled by the next poll_input()
+    pending_events: Vec<Event>,
 }
 
 impl Display {
-    pub fn new() -> Self {
-        let sdl_context = sdl2::init().unwrap();
-        let video_subsys = sdl_context.video().unwrap();
+    // Open the window and audio. Fails when SDL can't be set up.
+    pub fn new(config: &Config) -> Result<Self, String> {
+        // render at the monitor's real resolution instead of letting Windows upscale us,
+        // and keep scaled pixels sharp
+        sdl2::hint::set("SDL_WINDOWS_DPI_AWARENESS", "permonitorv2");
+        sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", "nearest");
+
+        let sdl_context = sdl2::init()?;
+        let sdl_epoch = Instant::now();
+        let video_subsys = sdl_context.video()?;
+        let scale = config.scale.unwrap_or_else(|| default_scale(&video_subsys));
         let window = video_subsys
-            .window("Chip-8 Emulator", SCR_WIDTH, SCR_HEIGHT)
+            .window(DEFAULT_TITLE, WIDTH * scale, HEIGHT * scale)
             .position_centered()
             .opengl()
+            .allow_highdpi()
+            .resizable()
             .build()
-            .map_err(|e| e.to_string())
-            .unwrap();
+            .map_err(|e| e.to_string())?;
 
-        let mut canvas = window
-            .into_canvas()
-            .build()
-            .map_err(|e| e.to_string())
-            .unwrap();
+        let mut canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
 
         canvas.set_draw_color(pixels::Color::RGB(0, 0, 0));
         canvas.clear();
         canvas.present();
 
-        let audio_subsystem = sdl_context.audio().unwrap();
+        let audio_subsystem = sdl_context.audio()?;
+        // gamepads already plugged in are announced with ControllerDeviceAdded too
+        let controller_subsystem = sdl_context.game_controller()?;
+
+        let audio = open_audio(
+            &audio_subsystem,
+            config.pulsed_buzz.then_some(config.timer_hz),
+        )?;
+        audio.resume();
 
-        let desired_spec = AudioSpecDesired {
-            freq: Some(44100),
-            channels: Some(1), // mono
-            samples: None,     // default sample size
+        let events = sdl_context.event_pump()?;
+
+        Ok(Self {
+            canvas,
+            video: video_subsys,
+            events,
+            audio_subsystem,
+            audio: Some(audio),
+            key_mapping: config.key_mapping,
+            key_bindings: config.key_bindings.iter().cloned().collect(),
+            keys_down: [false; 16],
+            input: InputQueue::default(),
+            sdl_epoch,
+            controller_subsystem,
+            controllers: Vec::new(),
+            stick_mapping: config.stick_mapping,
+            stick: (0.0, 0.0),
+            actions: Vec::new(),
+            frame: [[0.0; FRAME_WIDTH]; FRAME_HEIGHT],
+            blended_frames: 0,
+            resolution: (WIDTH as usize, HEIGHT as usize),
+            motion_blend: config.motion_blend,
+            filters: config.filters.iter().map(filter::build).collect(),
+            magnifier: None,
+            metrics: Vec::new(),
+            show_metrics: false,
+            show_keypad: false,
+            palette: Palette::default(),
+            palette_editor: None,
+            integer_scale: config.integer_scale,
+            border_color: config.border_color,
+            overscan: config.overscan,
+            flash_overscan: config.flash_overscan,
+            pulsed_buzz: config.pulsed_buzz.then_some(config.timer_hz),
+            beeping: false,
+            command_palette: None,
+            beep_bar: None,
+            hud: Vec::new(),
+            draw_tags: None,
+            heatmap: None,
+            hot_spots: Vec::new(),
+            memory_diff: None,
+            hovered: None,
+            pixel_info: None,
+            toast: None,
+            redraw: false,
+            pending_events: Vec::new(),
+        })
+    }
+
+    // Hand over a new emulated frame. It is shown on the next present().
+    // Pixels lit on any plane are shown lit; anything beyond the frame
+    // buffer's size is cut off.
+    pub fn push_frame(&mut self, pixels: &Framebuffer, hires: bool) {
+        self.resolution = if hires {
+            (FRAME_WIDTH, FRAME_HEIGHT)
+        } else {
+            (WIDTH as usize, HEIGHT as usize)
         };
 
-        let device = audio_subsystem
-            .open_playback(None, &desired_spec, |spec| {
-                // Show obtained AudioSpec
-                println!("{:?}", spec);
+        if !self.motion_blend || self.blended_frames == 0 {
+            self.frame = [[0.0; FRAME_WIDTH]; FRAME_HEIGHT];
+            self.blended_frames = 0;
+        }
 
-                // initialize the audio callback
-                SquareWave {
-                    phase_inc: 240.0 / spec.freq as f32,
-                    phase: 0.0,
-                    volume: 0.25,
+        for (frame_row, row) in self.frame.iter_mut().zip(pixels.rows()) {
+            for (sum, &pixel) in frame_row.iter_mut().zip(row.iter()) {
+                if pixel != 0 {
+                    *sum += 1.0;
                 }
-            })
-            .unwrap();
+            }
+        }
+        self.blended_frames += 1;
+    }
 
-        device.resume();
+    // Show the pushed frames. With motion blending on, every frame emulated
+    // since the last present contributes to the pixel brightness, so sprites
+    // that move several times per 60 Hz frame leave a trail instead of
+    // skipping positions.
+    pub fn present(&mut self) {
+        let view = self.viewport();
+        // size of one lores pixel, used to size the overlays
+        let pixel_size = (view.width() / WIDTH).max(1);
+        let frames = self.blended_frames.max(1) as f32;
 
-        Self {
-            canvas,
-            events: sdl_context.event_pump().unwrap(),
-            audio: device,
+        let (r, g, b) = self.border_color.unwrap_or(self.palette.background);
+        self.canvas.set_draw_color(pixels::Color::RGB(r, g, b));
+        self.canvas.clear();
+
+        if self.overscan != (0, 0) {
+            let (r, g, b) = if self.flash_overscan && self.beeping {
+                self.palette.foreground
+            } else {
+                self.palette.background
+            };
+            self.canvas.set_draw_color(pixels::Color::RGB(r, g, b));
+            let _ = self.canvas.fill_rect(self.screen());
+        }
+        if self.filters.is_empty() {
+            self.draw_frame(view, frames);
+        } else {
+            self.draw_filtered(view, frames);
+        }
+        if self.heatmap.is_some() {
+            self.draw_profile(view, pixel_size);
+        }
+        if let Some(remaining) = self.beep_bar {
+            self.draw_beep_bar(view, remaining, pixel_size);
+        }
+        if !self.hud.is_empty() {
+            self.draw_hud(view, pixel_size);
+        }
+        if let Some(center) = self.magnifier {
+            self.draw_magnifier(center, pixel_size);
+        }
+        if self.show_metrics {
+            self.draw_metrics(pixel_size);
+        }
+        if let Some(lines) = self.memory_diff.clone() {
+            self.draw_memory_diff(&lines, pixel_size);
+        }
+        if self.show_keypad {
+            self.draw_keypad(pixel_size);
         }
+        if let Some(info) = self.pixel_info.clone() {
+            self.draw_pixel_info(&info, pixel_size);
+        }
+        if let Some(selected) = self.palette_editor {
+            self.draw_palette_editor(selected, pixel_size);
+        }
+        if self.command_palette.is_some() {
+            self.draw_command_palette(pixel_size);
+        }
+        if let Some((message, _)) = self.toast.clone() {
+            self.draw_toast(&message, pixel_size);
+        }
+
+        self.canvas.present();
+
+        // keep the blended result around in case it is presented again
+        for row in self.frame.iter_mut() {
+            for sum in row.iter_mut() {
+                *sum /= frames;
+            }
+        }
+        self.blended_frames = 0;
+    }
+
+    // Color of frame pixel `x`, `y` at brightness `level`. With the draw
+    // order view on, pixels a sprite was drawn to this frame take the hue of
+    // that draw, dimmed where the draw erased them.
+    fn pixel_color(&self, x: usize, y: usize, level: f32) -> (u8, u8, u8) {
+        let tag = match &self.draw_tags {
+            Some(tags) => tags[y * FRAME_WIDTH + x],
+            None => 0,
+        };
+        if tag == 0 {
+            return self.palette.blend(level);
+        }
+
+        let (r, g, b) = palette::draw_hue(tag);
+        let dim = |c: u8| (c as f32 * (0.25 + 0.75 * level)) as u8;
+        (dim(r), dim(g), dim(b))
     }
 
-    pub fn draw(&mut self, pixels: &[[u8; WIDTH as usize]; HEIGHT as usize]) {
-        for (y, row) in pixels.iter().enumerate() {
-            for (x, &col) in row.iter().enumerate() {
-                let x = (x as u32) * PIXEL_SCALE;
-                let y = (y as u32) * PIXEL_SCALE;
+    // Draw the frame into `view` unfiltered, one rectangle per pixel.
+    fn draw_frame(&mut self, view: Rect, frames: f32) {
+        // the frame buffer is drawn at hires resolution; when the scale is not a
+        // whole number, pixel edges are rounded so neighbouring pixels still meet
+        let edge_x = |x: usize| view.x() + (x as u32 * view.width() / FRAME_WIDTH as u32) as i32;
+        let edge_y = |y: usize| view.y() + (y as u32 * view.height() / FRAME_HEIGHT as u32) as i32;
+        for (y, row) in self.frame.iter().enumerate() {
+            let (top, bottom) = (edge_y(y), edge_y(y + 1));
+            for (x, &sum) in row.iter().enumerate() {
+                let (left, right) = (edge_x(x), edge_x(x + 1));
 
-                let color = if col == 0 {
-                    pixels::Color::RGB(0, 0, 0)
+                let (r, g, b) = self.pixel_color(x, y, sum / frames);
+                let color = pixels::Color::RGB(r, g, b);
+
+                self.canvas.set_draw_color(color);
+
+                let _ = self.canvas.fill_rect(Rect::new(
+                    left,
+                    top,
+                    (right - left) as u32,
+                    (bottom - top) as u32,
+                ));
+            }
+        }
+    }
+
+    // Draw the frame through the filter chain into `view`.
+    fn draw_filtered(&mut self, view: Rect, frames: f32) {
+        let (width, height) = (
+            FRAME_WIDTH * filter::SUBPIXELS,
+            FRAME_HEIGHT * filter::SUBPIXELS,
+        );
+        let mut rgba = Vec::with_capacity(width * height * 4);
+        let mut line = Vec::with_capacity(width * 4);
+        for (y, row) in self.frame.iter().enumerate() {
+            line.clear();
+            for (x, &sum) in row.iter().enumerate() {
+                let (r, g, b) = self.pixel_color(x, y, sum / frames);
+                for _ in 0..filter::SUBPIXELS {
+                    line.extend_from_slice(&[r, g, b, 255]);
+                }
+            }
+            for _ in 0..filter::SUBPIXELS {
+                rgba.extend_from_slice(&line);
+            }
+        }
+
+        let mut frame = Frame {
+            width,
+            height,
+            cell: width / self.resolution.0,
+            rgba,
+        };
+        for filter in self.filters.iter_mut() {
+            filter.apply(&mut frame);
+        }
+
+        let creator = self.canvas.texture_creator();
+        let texture =
+            creator.create_texture_streaming(PixelFormatEnum::RGBA32, width as u32, height as u32);
+        if let Ok(mut texture) = texture {
+            let _ = texture.update(None, &frame.rgba, width * 4);
+            let _ = self.canvas.copy(&texture, None, view);
+        }
+    }
+
+    // The last presented frame at hires resolution in the current palette,
+    // without overlays.
+    pub fn frame_image(&self) -> Image {
+        let frames = self.blended_frames.max(1) as f32;
+        let mut rgb = Vec::with_capacity(FRAME_WIDTH * FRAME_HEIGHT * 3);
+        for row in self.frame.iter() {
+            for &sum in row.iter() {
+                let (r, g, b) = self.palette.blend(sum / frames);
+                rgb.extend_from_slice(&[r, g, b]);
+            }
+        }
+
+        Image {
+            width: FRAME_WIDTH,
+            height: FRAME_HEIGHT,
+            rgb,
+        }
+    }
+
+    // Size of the game plus its overscan margin, in hires pixels.
+    fn screen_size(&self) -> (u32, u32) {
+        let (margin_x, margin_y) = (self.overscan.0 * 2, self.overscan.1 * 2);
+        (
+            FRAME_WIDTH as u32 + 2 * margin_x,
+            FRAME_HEIGHT as u32 + 2 * margin_y,
+        )
+    }
+
+    // The part of the window the emulated screen covers, overscan included, in
+    // physical pixels (which on HiDPI screens outnumber the window size in
+    // points). It keeps its aspect ratio and is centered, with borders filling
+    // the rest of the window.
+    fn screen(&self) -> Rect {
+        let (output_width, output_height) = self.canvas.output_size().unwrap_or((WIDTH, HEIGHT));
+        let (screen_width, screen_height) = self.screen_size();
+        let (width, height) = if self.integer_scale {
+            let scale = (output_width / screen_width)
+                .min(output_height / screen_height)
+                .max(1);
+            (screen_width * scale, screen_height * scale)
+        } else {
+            let width = output_width
+                .min(output_height * screen_width / screen_height)
+                .max(screen_width);
+            (width, width * screen_height / screen_width)
+        };
+
+        Rect::new(
+            output_width.saturating_sub(width) as i32 / 2,
+            output_height.saturating_sub(height) as i32 / 2,
+            width,
+            height,
+        )
+    }
+
+    // The part of the window the game itself is drawn in, inside the overscan margin.
+    fn viewport(&self) -> Rect {
+        let screen = self.screen();
+        let (screen_width, screen_height) = self.screen_size();
+        let margin_x = self.overscan.0 * 2 * screen.width() / screen_width;
+        let margin_y = self.overscan.1 * 2 * screen.height() / screen_height;
+        Rect::new(
+            screen.x() + margin_x as i32,
+            screen.y() + margin_y as i32,
+            screen.width() - 2 * margin_x,
+            screen.height() - 2 * margin_y,
+        )
+    }
+
+    // Show `message` over the game for a few seconds.
+    pub fn show_toast(&mut self, message: &str) {
+        self.toast = Some((message.to_string(), Instant::now() + TOAST_DURATION));
+        self.redraw = true;
+    }
+
+    // Whether the overlays changed on their own and the frame should be presented again.
+    pub fn take_redraw(&mut self) -> bool {
+        std::mem::replace(&mut self.redraw, false)
+    }
+
+    fn draw_toast(&mut self, message: &str, pixel_size: u32) {
+        let text_scale = (pixel_size / 4).max(1);
+        let (output_width, output_height) = self.canvas.output_size().unwrap_or((WIDTH, HEIGHT));
+        let width = (text::width(message) + 2) * text_scale;
+        let height = (text::GLYPH_HEIGHT + 2) * text_scale;
+        let left = output_width.saturating_sub(width) as i32 / 2;
+        let top = output_height.saturating_sub(height + pixel_size) as i32;
+
+        self.canvas.set_draw_color(pixels::Color::RGB(40, 40, 40));
+        let _ = self.canvas.fill_rect(Rect::new(left, top, width, height));
+        self.draw_text(
+            left + text_scale as i32,
+            top + text_scale as i32,
+            text_scale,
+            message,
+            pixels::Color::RGB(230, 230, 230),
+        );
+    }
+
+    // Whether the open output device is the one that went away. sdl2 doesn't
+    // give out the device's id to compare with the event's, but SDL reports
+    // a disconnected device as stopped while ours is otherwise only ever
+    // playing or paused.
+    fn audio_lost(&self) -> bool {
+        self.audio
+            .as_ref()
+            .is_some_and(|a| a.status() == AudioStatus::Stopped)
+    }
+
+    // The output device went away (e.g. Bluetooth headphones disconnected),
+    // or one turned up while there was none, so move the beep over to
+    // whatever the default device is now.
+    fn reopen_audio(&mut self) {
+        let was_playing = self
+            .audio
+            .as_ref()
+            .is_some_and(|a| a.status() == AudioStatus::Playing);
+        self.audio = None;
+
+        match open_audio(&self.audio_subsystem, self.pulsed_buzz) {
+            Ok(audio) => {
+                if was_playing {
+                    audio.resume();
+                }
+                self.audio = Some(audio);
+                self.show_toast("AUDIO DEVICE CHANGED");
+            }
+            Err(e) => {
+                log!("Could not reopen audio: {}", e);
+                self.show_toast("NO AUDIO DEVICE");
+            }
+        }
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        let _ = self.canvas.window_mut().set_title(title);
+    }
+
+    pub fn set_metrics(&mut self, lines: Vec<String>) {
+        self.metrics = lines;
+    }
+
+    pub fn toggle_metrics(&mut self) {
+        self.show_metrics = !self.show_metrics;
+    }
+
+    pub fn metrics_visible(&self) -> bool {
+        self.show_metrics
+    }
+
+    fn draw_metrics(&mut self, pixel_size: u32) {
+        let lines = self.metrics.clone();
+        self.draw_panel(0, 0, pixel_size, &lines, pixels::Color::RGB(120, 220, 120));
+    }
+
+    // Draw lines of text on a dark box with its top left corner at (x, y).
+    fn draw_panel(
+        &mut self,
+        x: i32,
+        y: i32,
+        pixel_size: u32,
+        lines: &[String],
+        color: pixels::Color,
+    ) {
+        let text_scale = (pixel_size / 4).max(1);
+        let line = (text::GLYPH_HEIGHT + 2) * text_scale;
+        let width = lines.iter().map(|l| text::width(l)).max().unwrap_or(0);
+
+        self.canvas.set_draw_color(pixels::Color::RGB(40, 40, 40));
+        let _ = self.canvas.fill_rect(Rect::new(
+            x,
+            y,
+            (width + 2) * text_scale,
+            lines.len() as u32 * line + text_scale,
+        ));
+
+        for (n, l) in lines.iter().enumerate() {
+            let line_y = y + (n as u32 * line + text_scale) as i32;
+            self.draw_text(x + text_scale as i32, line_y, text_scale, l, color);
+        }
+    }
+
+    pub fn palette(&self) -> Palette {
+        self.palette
+    }
+
+    pub fn toggle_palette_editor(&mut self) {
+        self.palette_editor = match self.palette_editor {
+            Some(_) => None,
+            None => Some(0),
+        };
+    }
+
+    pub fn palette_editor_open(&self) -> bool {
+        self.palette_editor.is_some()
+    }
+
+    // Move the editor's selection by `rows` channels and change the selected channel by `amount`.
+    pub fn edit_palette(&mut self, rows: i32, amount: i32) {
+        if let Some(selected) = self.palette_editor {
+            let selected = (selected as i32 + rows).rem_euclid(6) as usize;
+            if let Some(channel) = self.palette.channel_mut(selected) {
+                *channel = (*channel as i32 + amount).clamp(0, 255) as u8;
+            }
+            self.palette_editor = Some(selected);
+        }
+    }
+
+    fn draw_palette_editor(&mut self, selected: usize, pixel_size: u32) {
+        let mut palette = self.palette;
+        let names = ["BG R", "BG G", "BG B", "FG R", "FG G", "FG B"];
+        let mut lines = vec!["PALETTE  UP/DOWN LEFT/RIGHT".to_string()];
+        for (n, name) in names.iter().enumerate() {
+            let marker = if n == selected { '>' } else { ' ' };
+            let value = palette.channel_mut(n).map_or(0, |c| *c);
+            lines.push(format!("{} {} {:02X}", marker, name, value));
+        }
+
+        let (_, output_height) = self.canvas.output_size().unwrap_or((WIDTH, HEIGHT));
+        let text_scale = (pixel_size / 4).max(1);
+        let height = lines.len() as u32 * (text::GLYPH_HEIGHT + 2) * text_scale + text_scale;
+        let top = output_height.saturating_sub(height) as i32;
+        self.draw_panel(
+            0,
+            top,
+            pixel_size,
+            &lines,
+            pixels::Color::RGB(230, 230, 230),
+        );
+    }
+
+    pub fn set_draw_tags(&mut self, tags: Option<Vec<u16>>) {
+        self.draw_tags = tags;
+    }
+
+    // The custom overlay to draw over the game from now on.
+    pub fn set_hud(&mut self, shapes: Vec<Shape>) {
+        self.hud = shapes;
+    }
+
+    // Show how much of the current beep is left, from 1.0 down to 0.0, or
+    // hide the bar with None.
+    pub fn set_beep_bar(&mut self, remaining: Option<f32>) {
+        if self.beep_bar != remaining {
+            self.beep_bar = remaining;
+            self.redraw = true;
+        }
+    }
+
+    // Shapes are placed in lores pixels; text uses the overlay text size.
+    fn draw_hud(&mut self, view: Rect, pixel_size: u32) {
+        let text_scale = (pixel_size / 4).max(1);
+        let shapes = std::mem::take(&mut self.hud);
+        for shape in shapes.iter() {
+            match shape {
+                Shape::Rect(rect) => {
+                    let (r, g, b) = rect.color;
+                    self.canvas.set_draw_color(pixels::Color::RGB(r, g, b));
+                    let _ = self.canvas.fill_rect(Rect::new(
+                        view.x() + rect.x * pixel_size as i32,
+                        view.y() + rect.y * pixel_size as i32,
+                        rect.width * pixel_size,
+                        rect.height * pixel_size,
+                    ));
+                }
+                Shape::Text { x, y, color, text } => {
+                    let (r, g, b) = *color;
+                    self.draw_text(
+                        view.x() + x * pixel_size as i32,
+                        view.y() + y * pixel_size as i32,
+                        text_scale,
+                        text,
+                        pixels::Color::RGB(r, g, b),
+                    );
+                }
+            }
+        }
+        self.hud = shapes;
+    }
+
+    // A bar along the bottom edge of the game that shrinks towards the center.
+    fn draw_beep_bar(&mut self, view: Rect, remaining: f32, pixel_size: u32) {
+        let width = (view.width() as f32 * remaining).round() as u32;
+        let height = (pixel_size / 2).max(1);
+        let (r, g, b) = self.palette.foreground;
+        self.canvas.set_draw_color(pixels::Color::RGB(r, g, b));
+        let _ = self.canvas.fill_rect(Rect::new(
+            view.x() + (view.width() - width) as i32 / 2,
+            view.bottom() - height as i32,
+            width.max(1),
+            height,
+        ));
+    }
+
+    // Show the profiler overlay, or hide it with None.
+    pub fn set_profile(&mut self, profile: Option<(Vec<f32>, Vec<String>)>) {
+        match profile {
+            Some((heatmap, hot_spots)) => {
+                self.heatmap = Some(heatmap);
+                self.hot_spots = hot_spots;
+            }
+            None => {
+                self.heatmap = None;
+                self.hot_spots.clear();
+            }
+        }
+        self.redraw = true;
+    }
+
+    // Tint the game red where it is redrawn most, and list the hottest
+    // addresses in the bottom right corner.
+    fn draw_profile(&mut self, view: Rect, pixel_size: u32) {
+        let heatmap = match &self.heatmap {
+            Some(heatmap) => heatmap,
+            None => return,
+        };
+
+        self.canvas.set_blend_mode(BlendMode::Blend);
+        for (i, &heat) in heatmap.iter().enumerate() {
+            if heat > 0.0 {
+                let (x, y) = ((i % FRAME_WIDTH) as u32, (i / FRAME_WIDTH) as u32);
+                let left = view.x() + (x * view.width() / FRAME_WIDTH as u32) as i32;
+                let top = view.y() + (y * view.height() / FRAME_HEIGHT as u32) as i32;
+                let right = view.x() + ((x + 1) * view.width() / FRAME_WIDTH as u32) as i32;
+                let bottom = view.y() + ((y + 1) * view.height() / FRAME_HEIGHT as u32) as i32;
+
+                let alpha = (40.0 + heat * 160.0) as u8;
+                self.canvas
+                    .set_draw_color(pixels::Color::RGBA(255, 60, 0, alpha));
+                let _ = self.canvas.fill_rect(Rect::new(
+                    left,
+                    top,
+                    (right - left) as u32,
+                    (bottom - top) as u32,
+                ));
+            }
+        }
+        self.canvas.set_blend_mode(BlendMode::None);
+
+        let mut lines = vec!["HOT ADDRESSES".to_string()];
+        lines.extend(self.hot_spots.iter().cloned());
+        let (output_width, output_height) = self.canvas.output_size().unwrap_or((WIDTH, HEIGHT));
+        let text_scale = (pixel_size / 4).max(1);
+        let width = (lines.iter().map(|l| text::width(l)).max().unwrap_or(0) + 2) * text_scale;
+        let height = lines.len() as u32 * (text::GLYPH_HEIGHT + 2) * text_scale + text_scale;
+        self.draw_panel(
+            output_width.saturating_sub(width) as i32,
+            output_height.saturating_sub(height) as i32,
+            pixel_size,
+            &lines,
+            pixels::Color::RGB(255, 160, 80),
+        );
+    }
+
+    // Show the memory diff panel with these lines, or hide it with None.
+    pub fn set_memory_diff(&mut self, lines: Option<Vec<String>>) {
+        if self.memory_diff != lines {
+            self.memory_diff = lines;
+            self.redraw = true;
+        }
+    }
+
+    // The vram pixel under the mouse pointer, if it is over the game.
+    pub fn hovered_pixel(&self) -> Option<(usize, usize)> {
+        self.hovered
+    }
+
+    pub fn set_pixel_info(&mut self, info: Option<String>) {
+        if self.pixel_info != info {
+            self.pixel_info = info;
+            self.redraw = true;
+        }
+    }
+
+    // In the bottom left corner, clear of the toasts.
+    fn draw_pixel_info(&mut self, info: &str, pixel_size: u32) {
+        let (_, output_height) = self.canvas.output_size().unwrap_or((WIDTH, HEIGHT));
+        let text_scale = (pixel_size / 4).max(1);
+        let height = (text::GLYPH_HEIGHT + 2) * text_scale + text_scale;
+        self.draw_panel(
+            0,
+            output_height.saturating_sub(height) as i32,
+            pixel_size,
+            &[info.to_string()],
+            pixels::Color::RGB(255, 204, 0),
+        );
+    }
+
+    // The vram pixel at window position `x`, `y`, in window coordinates,
+    // which differ from drawing coordinates on high-DPI displays.
+    fn pixel_at(&self, x: i32, y: i32) -> Option<(usize, usize)> {
+        let (window_width, _) = self.canvas.window().size();
+        let (output_width, _) = self.canvas.output_size().ok()?;
+        let ratio = output_width as f32 / window_width.max(1) as f32;
+        let (x, y) = ((x as f32 * ratio) as i32, (y as f32 * ratio) as i32);
+
+        let view = self.viewport();
+        if !view.contains_point((x, y)) {
+            return None;
+        }
+        let col = (x - view.x()) as usize * FRAME_WIDTH / view.width() as usize;
+        let row = (y - view.y()) as usize * FRAME_HEIGHT / view.height() as usize;
+        Some((col, row))
+    }
+
+    // In the top right corner, clear of the metrics panel.
+    fn draw_memory_diff(&mut self, lines: &[String], pixel_size: u32) {
+        let (output_width, _) = self.canvas.output_size().unwrap_or((WIDTH, HEIGHT));
+        let text_scale = (pixel_size / 4).max(1);
+        let width = (lines.iter().map(|l| text::width(l)).max().unwrap_or(0) + 2) * text_scale;
+        self.draw_panel(
+            output_width.saturating_sub(width) as i32,
+            0,
+            pixel_size,
+            lines,
+            pixels::Color::RGB(120, 200, 255),
+        );
+    }
+
+    pub fn toggle_command_palette(&mut self) {
+        let text_input = self.video.text_input();
+        if self.command_palette.take().is_some() {
+            text_input.stop();
+        } else {
+            self.command_palette = Some(CommandPalette::new());
+            self.release_keys(Instant::now());
+            text_input.start();
+        }
+    }
+
+    // Keys typed into the open command palette. Enter runs the highlighted
+    // command as if its key had been pressed.
+    fn command_palette_key(&mut self, keycode: Keycode, keymod: Mod) {
+        let palette = match &mut self.command_palette {
+            Some(palette) => palette,
+            None => return,
+        };
+        match keycode {
+            Keycode::Up => palette.move_selection(-1),
+            Keycode::Down => palette.move_selection(1),
+            Keycode::Backspace => palette.backspace(),
+            Keycode::Return | Keycode::KpEnter => {
+                if let Some(action) = palette.selected_action() {
+                    self.actions.push(action);
+                }
+                self.toggle_command_palette();
+            }
+            Keycode::Escape => self.toggle_command_palette(),
+            _ => {
+                if action_for(keycode, keymod) == Some(Action::ToggleCommandPalette) {
+                    self.toggle_command_palette();
+                }
+            }
+        }
+        self.redraw = true;
+    }
+
+    fn draw_command_palette(&mut self, pixel_size: u32) {
+        let palette = match &self.command_palette {
+            Some(palette) => palette,
+            None => return,
+        };
+        let mut lines = vec![format!("> {}_", palette.query())];
+        for (n, command) in palette.matches().iter().enumerate() {
+            let marker = if n == palette.selected() { '*' } else { ' ' };
+            lines.push(format!("{} {}  {}", marker, command.name, command.binding));
+        }
+
+        let (output_width, _) = self.canvas.output_size().unwrap_or((WIDTH, HEIGHT));
+        let text_scale = (pixel_size / 4).max(1);
+        let width = (lines.iter().map(|l| text::width(l)).max().unwrap_or(0) + 2) * text_scale;
+        let left = output_width.saturating_sub(width) as i32 / 2;
+        self.draw_panel(
+            left,
+            pixel_size as i32,
+            pixel_size,
+            &lines,
+            pixels::Color::RGB(230, 230, 230),
+        );
+    }
+
+    pub fn set_keypad_guide(&mut self, shown: bool) {
+        self.show_keypad = shown;
+        self.redraw = true;
+    }
+
+    pub fn keypad_guide_shown(&self) -> bool {
+        self.show_keypad
+    }
+
+    // The host key that presses hex key `key`: a --bind for it if there is
+    // one, otherwise the default layout's key, named as the user's keyboard
+    // labels it.
+    fn host_key_name(&self, key: usize) -> String {
+        let bound = self
+            .key_bindings
+            .iter()
+            .filter(|&(_, &k)| k == key)
+            .map(|(name, _)| name)
+            .min();
+        if let Some(name) = bound {
+            let short = name.strip_prefix("Key").unwrap_or(name);
+            return short
+                .strip_prefix("Gamepad:")
+                .unwrap_or(short)
+                .to_uppercase();
+        }
+
+        let default = DEFAULT_LAYOUT[key].to_string();
+        let name = match self.key_mapping {
+            KeyMapping::Scancode => Scancode::from_name(&default)
+                .and_then(Keycode::from_scancode)
+                .map(|k| k.name()),
+            KeyMapping::Keycode => None,
+        };
+        name.unwrap_or(default).to_uppercase()
+    }
+
+    // The keypad as the games see it, each key labelled with its host key
+    // and lit while held.
+    fn draw_keypad(&mut self, pixel_size: u32) {
+        let text_scale = (pixel_size / 4).max(1);
+        let labels: Vec<String> = (0..16)
+            .map(|key| {
+                let mut name = self.host_key_name(key);
+                name.truncate(KEYPAD_LABEL_LEN);
+                format!("{:X} {}", key, name)
+            })
+            .collect();
+        let cell_width =
+            (labels.iter().map(|l| text::width(l)).max().unwrap_or(0) + 2) * text_scale;
+        let cell_height = (text::GLYPH_HEIGHT + 2) * text_scale;
+        let title = "KEYPAD - F1 TO CLOSE";
+        let (output_width, output_height) = self.canvas.output_size().unwrap_or((WIDTH, HEIGHT));
+        let width =
+            ((cell_width + text_scale) * 4 + text_scale).max((text::width(title) + 2) * text_scale);
+        let height = (cell_height + text_scale) * 5 + text_scale;
+        let left = output_width.saturating_sub(width) as i32 / 2;
+        let top = output_height.saturating_sub(height) as i32 / 2;
+
+        self.canvas.set_draw_color(pixels::Color::RGB(40, 40, 40));
+        let _ = self.canvas.fill_rect(Rect::new(left, top, width, height));
+        self.draw_text(
+            left + text_scale as i32,
+            top + text_scale as i32,
+            text_scale,
+            title,
+            pixels::Color::RGB(230, 230, 230),
+        );
+        for (row, keys) in KEYPAD_ROWS.iter().enumerate() {
+            for (col, &key) in keys.iter().enumerate() {
+                let x = left + (text_scale + col as u32 * (cell_width + text_scale)) as i32;
+                let y = top + (text_scale + (row as u32 + 1) * (cell_height + text_scale)) as i32;
+                let (fill, ink) = if self.keys_down[key] {
+                    (pixels::Color::RGB(255, 204, 0), pixels::Color::RGB(0, 0, 0))
                 } else {
+                    (
+                        pixels::Color::RGB(70, 70, 70),
+                        pixels::Color::RGB(230, 230, 230),
+                    )
+                };
+                self.canvas.set_draw_color(fill);
+                let _ = self
+                    .canvas
+                    .fill_rect(Rect::new(x, y, cell_width, cell_height));
+                self.draw_text(
+                    x + text_scale as i32,
+                    y + text_scale as i32,
+                    text_scale,
+                    &labels[key],
+                    ink,
+                );
+            }
+        }
+    }
+
+    pub fn toggle_magnifier(&mut self) {
+        self.magnifier = match self.magnifier {
+            Some(_) => None,
+            None => Some((WIDTH as usize / 2, HEIGHT as usize / 2)),
+        };
+    }
+
+    pub fn move_magnifier(&mut self, dx: i32, dy: i32) {
+        let (width, height) = self.resolution;
+        if let Some((x, y)) = self.magnifier {
+            self.magnifier = Some((wrap(x as i32 + dx, width), wrap(y as i32 + dy, height)));
+        }
+    }
+
+    // Whether pixel (x, y), in the current resolution, is lit.
+    fn pixel_lit(&self, x: usize, y: usize) -> bool {
+        let block = FRAME_WIDTH / self.resolution.0;
+        self.frame[y * block][x * block] > 0.0
+    }
+
+    // Blow up the pixels around `center` in the top right corner, labelled with
+    // their coordinates, to make off-by-one sprite placement easy to see.
+    fn draw_magnifier(&mut self, center: (usize, usize), pixel_size: u32) {
+        let text_scale = (pixel_size / 4).max(1);
+        let (width, height) = self.resolution;
+        // the program may have switched to a lower resolution since the magnifier moved
+        let center = (center.0 % width, center.1 % height);
+        let view = self.viewport();
+        let cell = 12 * text_scale;
+        let label = (text::width("00") + 2) * text_scale;
+        let line = (text::GLYPH_HEIGHT + 2) * text_scale;
+        let side = 2 * MAGNIFIER_RADIUS as u32;
+
+        let (output_width, _) = self.canvas.output_size().unwrap_or((WIDTH, HEIGHT));
+        let panel_width = label + side * cell + text_scale;
+        let panel_height = line + side * cell + line + text_scale;
+        let left = output_width.saturating_sub(panel_width + pixel_size) as i32;
+        let top = pixel_size as i32;
+
+        // mark the inspected pixel in the game view
+        self.canvas.set_draw_color(pixels::Color::RGB(230, 60, 60));
+        let left_edge = view.x() + (center.0 as u32 * view.width() / width as u32) as i32;
+        let top_edge = view.y() + (center.1 as u32 * view.height() / height as u32) as i32;
+        let _ = self.canvas.draw_rect(Rect::new(
+            left_edge,
+            top_edge,
+            (view.width() / width as u32).max(1),
+            (view.height() / height as u32).max(1),
+        ));
+
+        self.canvas.set_draw_color(pixels::Color::RGB(40, 40, 40));
+        let _ = self
+            .canvas
+            .fill_rect(Rect::new(left, top, panel_width, panel_height));
+
+        let grid_left = left + label as i32;
+        let grid_top = top + line as i32;
+        let label_color = pixels::Color::RGB(200, 200, 80);
+
+        for i in 0..side as i32 {
+            let x = wrap(center.0 as i32 - MAGNIFIER_RADIUS + i, width);
+            let y = wrap(center.1 as i32 - MAGNIFIER_RADIUS + i, height);
+            let offset = i * cell as i32 + text_scale as i32;
+            self.draw_text(
+                grid_left + offset,
+                top,
+                text_scale,
+                &format!("{:02}", x),
+                label_color,
+            );
+            self.draw_text(
+                left + text_scale as i32,
+                grid_top + offset,
+                text_scale,
+                &format!("{:02}", y),
+                label_color,
+            );
+        }
+
+        for j in 0..side as i32 {
+            for i in 0..side as i32 {
+                let x = wrap(center.0 as i32 - MAGNIFIER_RADIUS + i, width);
+                let y = wrap(center.1 as i32 - MAGNIFIER_RADIUS + j, height);
+                let color = if self.pixel_lit(x, y) {
                     pixels::Color::RGB(210, 210, 210)
+                } else {
+                    pixels::Color::RGB(0, 0, 0)
                 };
 
+                let rect = Rect::new(
+                    grid_left + i * cell as i32,
+                    grid_top + j * cell as i32,
+                    cell - text_scale,
+                    cell - text_scale,
+                );
                 self.canvas.set_draw_color(color);
+                let _ = self.canvas.fill_rect(rect);
 
-                let _ =
-                    self.canvas
-                        .fill_rect(Rect::new(x as i32, y as i32, PIXEL_SCALE, PIXEL_SCALE));
+                if i == MAGNIFIER_RADIUS && j == MAGNIFIER_RADIUS {
+                    self.canvas.set_draw_color(pixels::Color::RGB(230, 60, 60));
+                    let _ = self.canvas.draw_rect(rect);
+                }
+            }
+        }
+
+        let value = self.pixel_lit(center.0, center.1) as u8;
+        let status = format!("X:{:02} Y:{:02} ={}", center.0, center.1, value);
+        let status_top = grid_top + (side * cell) as i32 + text_scale as i32;
+        self.draw_text(
+            left + text_scale as i32,
+            status_top,
+            text_scale,
+            &status,
+            label_color,
+        );
+    }
+
+    fn draw_text(&mut self, x: i32, y: i32, scale: u32, text: &str, color: pixels::Color) {
+        self.canvas.set_draw_color(color);
+        for (n, c) in text.chars().enumerate() {
+            let glyph_x = x + (n as u32 * (text::GLYPH_WIDTH + 1) * scale) as i32;
+            for (row, bits) in text::glyph(c).iter().enumerate() {
+                for col in 0..text::GLYPH_WIDTH {
+                    if bits >> (text::GLYPH_WIDTH - 1 - col) & 1 == 1 {
+                        let _ = self.canvas.fill_rect(Rect::new(
+                            glyph_x + (col * scale) as i32,
+                            y + (row as u32 * scale) as i32,
+                            scale,
+                            scale,
+                        ));
+                    }
+                }
             }
         }
-        self.canvas.present();
     }
 
-    pub fn update_keypad(&mut self) -> [bool; 16] {
-        let mut keypad = [false; 16];
+    // Drain pending SDL events, queueing keypad changes for take_keypad().
+    //
+    // The keypad follows KeyDown/KeyUp events rather than a snapshot of the
+    // keyboard, stamped with when SDL saw them rather than when they are
+    // handled here.
+    pub fn poll_input(&mut self) {
+        if let Some((_, expires)) = self.toast {
+            if Instant::now() >= expires {
+                self.toast = None;
+                self.redraw = true;
+            }
+        }
 
-        for event in self.events.poll_iter() {
-            if let Event::Quit { .. } = event {
-                std::process::exit(0);
-            };
+        let mut events = std::mem::take(&mut self.pending_events);
+        events.extend(self.events.poll_iter());
+        for event in events {
+            match event {
+                // also sent by the standard macOS menu bar's Quit item
+                Event::Quit { .. } => self.actions.push(Action::Quit),
+                Event::DropFile { filename, .. } => self.actions.push(Action::OpenRom(filename)),
+                Event::ControllerDeviceAdded { which, .. } => {
+                    if let Ok(controller) = self.controller_subsystem.open(which) {
+                        log!("Gamepad connected: {}", controller.name());
+                        self.controllers.push(controller);
+                    }
+                }
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    self.controllers.retain(|c| c.instance_id() != which);
+                    self.stick = (0.0, 0.0);
+                }
+                Event::ControllerButtonDown {
+                    button, timestamp, ..
+                } => {
+                    if let Some(i) = self.map_button(button) {
+                        self.set_key(i, true, timestamp);
+                    }
+                }
+                Event::ControllerButtonUp {
+                    button, timestamp, ..
+                } => {
+                    if let Some(i) = self.map_button(button) {
+                        self.set_key(i, false, timestamp);
+                    }
+                }
+                Event::ControllerAxisMotion { axis, value, .. } => {
+                    let value = value as f32 / i16::MAX as f32;
+                    match axis {
+                        Axis::LeftX => self.stick.0 = value,
+                        Axis::LeftY => self.stick.1 = value,
+                        _ => (),
+                    }
+                }
+                Event::AudioDeviceRemoved {
+                    iscapture: false, ..
+                } if self.audio_lost() => self.reopen_audio(),
+                // a device coming back after the last one went away
+                Event::AudioDeviceAdded {
+                    iscapture: false, ..
+                } if self.audio.is_none() => self.reopen_audio(),
+                // repeats are fine here, so holding backspace or an arrow keeps going
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    keymod,
+                    ..
+                } if self.command_palette.is_some() => self.command_palette_key(keycode, keymod),
+                Event::TextInput { text, .. } => {
+                    if let Some(palette) = &mut self.command_palette {
+                        palette.type_text(&text);
+                        self.redraw = true;
+                    }
+                }
+                Event::KeyDown {
+                    keycode,
+                    scancode,
+                    keymod,
+                    repeat: false,
+                    timestamp,
+                    ..
+                } => {
+                    // hotkeys held with a modifier come first; bare keys are
+                    // the keypad's when it wants them, so layouts and --bind
+                    // can put hex keys where the letter hotkeys are
+                    let action = keycode.and_then(|k| action_for(k, keymod));
+                    let modified = keymod.intersects(
+                        Mod::LCTRLMOD
+                            | Mod::RCTRLMOD
+                            | Mod::LALTMOD
+                            | Mod::RALTMOD
+                            | Mod::LGUIMOD
+                            | Mod::RGUIMOD,
+                    );
+                    match (action, self.map_key(keycode, scancode)) {
+                        (Some(action), _) if modified => self.actions.push(action),
+                        (_, Some(i)) => {
+                            self.set_key(i, true, timestamp);
+                            self.redraw |= self.show_keypad;
+                        }
+                        (Some(action), None) => self.actions.push(action),
+                        (None, None) => (),
+                    }
+                }
+                Event::KeyUp {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => self.actions.push(Action::Rewind(false)),
+                Event::KeyUp {
+                    keycode,
+                    scancode,
+                    timestamp,
+                    ..
+                } => {
+                    if let Some(i) = self.map_key(keycode, scancode) {
+                        self.set_key(i, false, timestamp);
+                        self.redraw |= self.show_keypad;
+                    }
+                }
+                Event::Window {
+                    win_event: WindowEvent::SizeChanged(..),
+                    ..
+                } => self.redraw = true,
+                Event::MouseMotion { x, y, .. } => self.hovered = self.pixel_at(x, y),
+                Event::Window {
+                    win_event: WindowEvent::Leave,
+                    ..
+                } => self.hovered = None,
+                Event::MouseButtonDown {
+                    mouse_btn: MouseButton::Left,
+                    x,
+                    y,
+                    ..
+                } => {
+                    if let Some((col, row)) = self.pixel_at(x, y) {
+                        self.actions.push(Action::TogglePixel(col, row));
+                    }
+                }
+                // we won't see the KeyUp for keys released while unfocused
+                Event::Window {
+                    win_event: WindowEvent::FocusLost,
+                    timestamp,
+                    ..
+                } => {
+                    self.release_keys(self.event_time(timestamp));
+                    self.actions.push(Action::Rewind(false));
+                }
+                _ => (),
+            }
         }
+    }
 
-        let keys: Vec<Keycode> = self
-            .events
-            .keyboard_state()
-            .pressed_scancodes()
-            .filter_map(Keycode::from_scancode)
-            .collect();
+    // The keypad for a frame ending at `until`, from the key changes up to
+    // then and the stick as it is now.
+    pub fn take_keypad(&mut self, until: Instant) -> [bool; 16] {
+        let stick = self.stick_mapping.keys_for(self.stick.0, self.stick.1);
+        let mut keypad = self.input.take_until(until);
+        for (key, &held) in keypad.iter_mut().zip(stick.iter()) {
+            *key |= held;
+        }
+        keypad
+    }
 
-        for key in keys {
-            let index = match key {
-                Keycode::Num1 => Some(0x1),
-                Keycode::Num2 => Some(0x2),
-                Keycode::Num3 => Some(0x3),
-                Keycode::Num4 => Some(0xc),
-                Keycode::Q => Some(0x4),
-                Keycode::W => Some(0x5),
-                Keycode::E => Some(0x6),
-                Keycode::R => Some(0xd),
-                Keycode::A => Some(0x7),
-                Keycode::S => Some(0x8),
-                Keycode::D => Some(0x9),
-                Keycode::F => Some(0xe),
-                Keycode::Z => Some(0xa),
-                Keycode::X => Some(0x0),
-                Keycode::C => Some(0xb),
-                Keycode::V => Some(0xf),
-                _ => None,
-            };
+    // The most key changes that waited for their frame at once since the
+    // last call, for the metrics overlay.
+    pub fn input_queue_peak(&mut self) -> usize {
+        self.input.peak()
+    }
+
+    // SDL event timestamps are milliseconds since SDL started.
+    fn event_time(&self, timestamp: u32) -> Instant {
+        let at = self.sdl_epoch + Duration::from_millis(timestamp as u64);
+        at.min(Instant::now())
+    }
 
-            if let Some(i) = index {
-                keypad[i] = true;
+    fn set_key(&mut self, key: usize, down: bool, timestamp: u32) {
+        self.keys_down[key] = down;
+        self.input.push(self.event_time(timestamp), key, down);
+    }
+
+    // Let go of every key, when the keyboard goes elsewhere and the key ups
+    // won't arrive.
+    fn release_keys(&mut self, at: Instant) {
+        for key in 0..16 {
+            if self.keys_down[key] {
+                self.keys_down[key] = false;
+                self.input.push(at, key, false);
             }
         }
+    }
 
-        keypad
+    // Sleep until an event arrives or `timeout` passes, whichever comes first.
+    pub fn wait_event(&mut self, timeout: Duration) {
+        if let Some(event) = self.events.wait_event_timeout(timeout.as_millis() as u32) {
+            self.pending_events.push(event);
+        }
+    }
+
+    fn map_key(&self, keycode: Option<Keycode>, scancode: Option<Scancode>) -> Option<usize> {
+        let name = match self.key_mapping {
+            KeyMapping::Scancode => scancode.map(|s| s.name().to_string()),
+            KeyMapping::Keycode => keycode.map(|k| k.name()),
+        };
+        let bound = name.filter(|n| n.len() == 1).and_then(|n| {
+            self.key_bindings
+                .get(&format!("Key{}", n.to_ascii_uppercase()))
+        });
+        if let Some(&key) = bound {
+            return Some(key);
+        }
+        match self.key_mapping {
+            KeyMapping::Scancode => scancode.and_then(scancode_to_key),
+            KeyMapping::Keycode => keycode.and_then(keycode_to_key),
+        }
+    }
+
+    // Gamepad buttons only press keys they are bound to with --bind.
+    fn map_button(&self, button: Button) -> Option<usize> {
+        let name = match button {
+            Button::A => "South",
+            Button::B => "East",
+            Button::X => "West",
+            Button::Y => "North",
+            Button::DPadUp => "Up",
+            Button::DPadDown => "Down",
+            Button::DPadLeft => "Left",
+            Button::DPadRight => "Right",
+            Button::Start => "Start",
+            Button::Back => "Back",
+            _ => return None,
+        };
+        self.key_bindings.get(&format!("Gamepad:{}", name)).copied()
+    }
+
+    // Actions asked for since the last call.
+    pub fn take_actions(&mut self) -> Vec<Action> {
+        std::mem::take(&mut self.actions)
+    }
+
+    pub fn clipboard_text(&self) -> Result<String, String> {
+        self.video.clipboard().clipboard_text()
+    }
+
+    // Put `image` on the system clipboard, for pasting into other programs.
+    pub fn copy_image(&self, image: &Image) -> Result<(), String> {
+        let bytes = image
+            .rgb
+            .chunks(3)
+            .flat_map(|p| [p[0], p[1], p[2], 0xFF])
+            .collect::<Vec<u8>>();
+        let mut clipboard = arboard::Clipboard::new().map_err(|e| e.to_string())?;
+        clipboard
+            .set_image(arboard::ImageData {
+                width: image.width,
+                height: image.height,
+                bytes: bytes.into(),
+            })
+            .map_err(|e| e.to_string())
+    }
+
+    pub fn start_audio(&mut self) {
+        if let Some(audio) = &self.audio {
+            audio.resume();
+        }
+        self.set_beeping(true);
     }
 
-    pub fn start_audio(&self) {
-        self.audio.resume();
+    pub fn stop_audio(&mut self) {
+        if let Some(audio) = &self.audio {
+            audio.pause();
+        }
+        self.set_beeping(false);
     }
 
-    pub fn stop_audio(&self) {
-        self.audio.pause();
+    // A timer tick happened: start the next pulse of a pulsed buzz.
+    pub fn timer_tick(&mut self) {
+        if let (Some(_), Some(audio)) = (self.pulsed_buzz, &mut self.audio) {
+            audio.lock().gate = 0.0;
+        }
+    }
+
+    fn set_beeping(&mut self, beeping: bool) {
+        if self.flash_overscan && beeping != self.beeping {
+            self.redraw = true;
+        }
+        self.beeping = beeping;
     }
 }
 
+fn open_audio(
+    subsystem: &AudioSubsystem,
+    pulse_hz: Option<u32>,
+) -> Result<AudioDevice<SquareWave>, String> {
+    let desired_spec = AudioSpecDesired {
+        freq: Some(44100),
+        channels: Some(1), // mono
+        samples: None,     // default sample size
+    };
+
+    subsystem.open_playback(None, &desired_spec, |spec| {
+        // Show obtained AudioSpec
+        log!("{:?}", spec);
+
+        // initialize the audio callback
+        SquareWave {
+            phase_inc: 240.0 / spec.freq as f32,
+            phase: 0.0,
+            volume: 0.25,
+            pulsed: pulse_hz.is_some(),
+            gate: 0.0,
+            gate_inc: pulse_hz.unwrap_or(0) as f32 / spec.freq as f32,
+        }
+    })
+}
+
+fn wrap(value: i32, size: usize) -> usize {
+    value.rem_euclid(size as i32) as usize
+}
+
+// Integer window scale matching the OS scaling of the primary display.
+// macOS already sizes windows in points, so only the reference scale is needed there.
+fn default_scale(video: &VideoSubsystem) -> u32 {
+    if cfg!(target_os = "macos") {
+        return PIXEL_SCALE;
+    }
+
+    match video.display_dpi(0) {
+        Ok((_, hdpi, _)) => PIXEL_SCALE * ((hdpi / REFERENCE_DPI).round() as u32).max(1),
+        Err(_) => PIXEL_SCALE,
+    }
+}
+
+fn action_for(keycode: Keycode, keymod: Mod) -> Option<Action> {
+    let ctrl = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+    let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+    let cmd = cfg!(target_os = "macos") && keymod.intersects(Mod::LGUIMOD | Mod::RGUIMOD);
+
+    match keycode {
+        Keycode::Q | Keycode::W if cmd => Some(Action::Quit),
+        Keycode::V if ctrl => Some(Action::PasteRom),
+        Keycode::C if ctrl => Some(Action::CopyScreenshot),
+        Keycode::R if ctrl => Some(Action::ReloadRom),
+        Keycode::S if ctrl => Some(Action::SaveSpeed),
+        Keycode::P if ctrl => Some(Action::ToggleCommandPalette),
+        Keycode::T if ctrl => Some(Action::NewTab),
+        Keycode::Tab if ctrl => Some(Action::SwitchTab(!shift)),
+        Keycode::F4 if ctrl => Some(Action::CloseTab),
+        Keycode::P | Keycode::Pause => Some(Action::TogglePause),
+        Keycode::N => Some(Action::Step),
+        Keycode::F1 if shift => Some(Action::ToggleQuirk(0)),
+        Keycode::F2 if shift => Some(Action::ToggleQuirk(1)),
+        Keycode::F3 if shift => Some(Action::ToggleQuirk(2)),
+        Keycode::F4 if shift => Some(Action::ToggleQuirk(3)),
+        Keycode::F5 if shift => Some(Action::ToggleQuirk(4)),
+        Keycode::F6 if shift => Some(Action::ToggleQuirk(5)),
+        Keycode::F7 if shift => Some(Action::ToggleQuirk(6)),
+        Keycode::F8 if shift => Some(Action::ToggleQuirk(7)),
+        Keycode::F9 if shift => Some(Action::ToggleQuirk(8)),
+        Keycode::M => Some(Action::ToggleMagnifier),
+        Keycode::Equals | Keycode::Plus | Keycode::KpPlus => Some(Action::ChangeSpeed(true)),
+        Keycode::Minus | Keycode::KpMinus => Some(Action::ChangeSpeed(false)),
+        Keycode::F1 => Some(Action::ToggleKeypadGuide),
+        Keycode::F3 => Some(Action::ToggleMetrics),
+        Keycode::F5 => Some(Action::Reset),
+        Keycode::F6 => Some(Action::SaveState),
+        Keycode::F12 => Some(Action::LoadState),
+        Keycode::Backspace => Some(Action::Rewind(true)),
+        Keycode::Left => Some(Action::Arrow(-1, 0)),
+        Keycode::Right => Some(Action::Arrow(1, 0)),
+        Keycode::Up => Some(Action::Arrow(0, -1)),
+        Keycode::Down => Some(Action::Arrow(0, 1)),
+        Keycode::F7 if !shift => Some(Action::CyclePalette),
+        Keycode::F8 if !shift => Some(Action::TogglePaletteEditor),
+        Keycode::F9 if !shift => Some(Action::CaptureBurst),
+        Keycode::F10 => Some(Action::ToggleProfiler),
+        Keycode::F11 if shift => Some(Action::MarkMemory),
+        Keycode::F11 => Some(Action::ToggleMemoryDiff),
+        _ => None,
+    }
+}
+
+// 1 2 3 C      1 2 3 4
+// 4 5 6 D  <-  Q W E R
+// 7 8 9 E      A S D F
+// A 0 B F      Z X C V
+const KEYPAD_ROWS: [[usize; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xc],
+    [0x4, 0x5, 0x6, 0xd],
+    [0x7, 0x8, 0x9, 0xe],
+    [0xa, 0x0, 0xb, 0xf],
+];
+// the default host key for each hex key, 0 to F
+const DEFAULT_LAYOUT: [char; 16] = [
+    'X', '1', '2', '3', 'Q', 'W', 'E', 'A', 'S', 'D', 'Z', 'C', '4', 'R', 'F', 'V',
+];
+// host key names are cut to this many characters in the keypad guide
+const KEYPAD_LABEL_LEN: usize = 5;
+
+fn scancode_to_key(scancode: Scancode) -> Option<usize> {
+    match scancode {
+        Scancode::Num1 => Some(0x1),
+        Scancode::Num2 => Some(0x2),
+        Scancode::Num3 => Some(0x3),
+        Scancode::Num4 => Some(0xc),
+        Scancode::Q => Some(0x4),
+        Scancode::W => Some(0x5),
+        Scancode::E => Some(0x6),
+        Scancode::R => Some(0xd),
+        Scancode::A => Some(0x7),
+        Scancode::S => Some(0x8),
+        Scancode::D => Some(0x9),
+        Scancode::F => Some(0xe),
+        Scancode::Z => Some(0xa),
+        Scancode::X => Some(0x0),
+        Scancode::C => Some(0xb),
+        Scancode::V => Some(0xf),
+        _ => None,
+    }
+}
+
+fn keycode_to_key(keycode: Keycode) -> Option<usize> {
+    match keycode {
+        Keycode::Num1 => Some(0x1),
+        Keycode::Num2 => Some(0x2),
+        Keycode::Num3 => Some(0x3),
+        Keycode::Num4 => Some(0xc),
+        Keycode::Q => Some(0x4),
+        Keycode::W => Some(0x5),
+        Keycode::E => Some(0x6),
+        Keycode::R => Some(0xd),
+        Keycode::A => Some(0x7),
+        Keycode::S => Some(0x8),
+        Keycode::D => Some(0x9),
+        Keycode::F => Some(0xe),
+        Keycode::Z => Some(0xa),
+        Keycode::X => Some(0x0),
+        Keycode::C => Some(0xb),
+        Keycode::V => Some(0xf),
+        _ => None,
+    }
+}
+
+// Fraction of each timer tick a pulsed buzz sounds for.
+const PULSE_DUTY: f32 = 0.5;
+
 struct SquareWave {
     phase_inc: f32,
     phase: f32,
     volume: f32,
+    // whether the tone is cut off PULSE_DUTY into each timer tick
+    pulsed: bool,
+    // time since the last timer tick, in ticks; reset by Display::timer_tick
+    gate: f32,
+    gate_inc: f32,
 }
 
 impl AudioCallback for SquareWave {
@@ -162,12 +1588,16 @@ impl AudioCallback for SquareWave {
     fn callback(&mut self, out: &mut [f32]) {
         // Generate a square wave
         for x in out.iter_mut() {
-            *x = if self.phase <= 0.5 {
+            *x = if self.pulsed && self.gate >= PULSE_DUTY {
+                0.0
+            } else if self.phase <= 0.5 {
                 self.volume
             } else {
                 -self.volume
             };
             self.phase = (self.phase + self.phase_inc) % 1.0;
+            // stays silent if ticks stop coming, rather than wrapping around
+            self.gate = (self.gate + self.gate_inc).min(1.0);
         }
     }
 }
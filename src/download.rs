@@ -0,0 +1,178 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use crate::sha1;
+
+// Downloads are handed to curl, which is on every desktop OS we ship for,
+// rather than pulling an HTTP and TLS stack into the build.
+const CURL: &str = "curl";
+const TIMEOUT_SECS: &str = "30";
+
+pub fn is_url(text: &str) -> bool {
+    text.starts_with("http://") || text.starts_with("https://")
+}
+
+// A downloaded ROM in the cache.
+pub struct CacheEntry {
+    pub url: String,
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+// Fetch `url` on a background thread. The result arrives on the returned
+// channel as the path of the cached copy.
+pub fn spawn_fetch(url: &str, data_dir: &Path) -> Receiver<Result<PathBuf, String>> {
+    let (sender, receiver) = mpsc::channel();
+    let url = url.to_string();
+    let cache_dir = cache_dir(data_dir);
+    thread::spawn(move || {
+        let _ = sender.send(fetch(&url, &cache_dir));
+    });
+    receiver
+}
+
+// Download `url` into the cache, or revalidate the cached copy with its ETag.
+// If the server can't be reached, the cached copy is used as is, so ROMs
+// loaded once keep working offline.
+pub fn fetch(url: &str, cache_dir: &Path) -> Result<PathBuf, String> {
+    fs::create_dir_all(cache_dir).map_err(|e| e.to_string())?;
+    let key = sha1::hex_digest(url.as_bytes());
+    let rom_path = cache_dir.join(format!("{}.rom", key));
+    let meta_path = cache_dir.join(format!("{}.meta", key));
+    let part_path = cache_dir.join(format!("{}.part", key));
+    let headers_path = cache_dir.join(format!("{}.headers", key));
+
+    let cached = rom_path.exists();
+    let etag = fs::read_to_string(&meta_path)
+        .ok()
+        .and_then(|meta| meta_value(&meta, "etag").map(str::to_string));
+
+    let mut curl = Command::new(CURL);
+    curl.args(["--silent", "--show-error", "--location"])
+        .args(["--max-time", TIMEOUT_SECS])
+        .args(["--write-out", "%{http_code}"])
+        .arg("--dump-header")
+        .arg(&headers_path)
+        .arg("--output")
+        .arg(&part_path);
+    if let (true, Some(etag)) = (cached, &etag) {
+        curl.arg("--header").arg(format!("If-None-Match: {}", etag));
+    }
+    curl.arg(url);
+
+    let result = curl.output();
+    let headers = fs::read_to_string(&headers_path).unwrap_or_default();
+    let _ = fs::remove_file(&headers_path);
+
+    let status = match result {
+        Ok(output) if output.status.success() => {
+            String::from_utf8_lossy(&output.stdout).to_string()
+        }
+        Ok(output) => {
+            let _ = fs::remove_file(&part_path);
+            let error = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            return offline_copy(url, &rom_path, cached, error);
+        }
+        Err(e) => {
+            return offline_copy(url, &rom_path, cached, format!("could not run curl: {}", e))
+        }
+    };
+
+    match status.trim() {
+        "304" if cached => {
+            let _ = fs::remove_file(&part_path);
+            Ok(rom_path)
+        }
+        "200" => {
+            fs::rename(&part_path, &rom_path).map_err(|e| e.to_string())?;
+            let mut meta = format!("url={}\n", url);
+            if let Some(etag) = header_value(&headers, "etag") {
+                meta += &format!("etag={}\n", etag);
+            }
+            fs::write(&meta_path, meta).map_err(|e| e.to_string())?;
+            Ok(rom_path)
+        }
+        status => {
+            let _ = fs::remove_file(&part_path);
+            offline_copy(url, &rom_path, cached, format!("HTTP status {}", status))
+        }
+    }
+}
+
+fn offline_copy(
+    url: &str,
+    rom_path: &Path,
+    cached: bool,
+    error: String,
+) -> Result<PathBuf, String> {
+    if cached {
+        log!(
+            "Could not download {} ({}), using the cached copy",
+            url,
+            error
+        );
+        Ok(rom_path.to_path_buf())
+    } else {
+        Err(format!("Could not download {}: {}", url, error))
+    }
+}
+
+// Everything in the download cache.
+pub fn list(data_dir: &Path) -> io::Result<Vec<CacheEntry>> {
+    let dir = cache_dir(data_dir);
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut cached = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == "meta") {
+            let meta = fs::read_to_string(&path)?;
+            let rom_path = path.with_extension("rom");
+            cached.push(CacheEntry {
+                url: meta_value(&meta, "url").unwrap_or("?").to_string(),
+                size: fs::metadata(&rom_path).map_or(0, |m| m.len()),
+                path: rom_path,
+            });
+        }
+    }
+    cached.sort_by(|a, b| a.url.cmp(&b.url));
+    Ok(cached)
+}
+
+pub fn clear(data_dir: &Path) -> io::Result<()> {
+    match fs::remove_dir_all(cache_dir(data_dir)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+fn cache_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("cache")
+}
+
+fn meta_value<'a>(meta: &'a str, key: &str) -> Option<&'a str> {
+    meta.lines()
+        .filter_map(|line| line.split_once('='))
+        .find(|(k, _)| *k == key)
+        .map(|(_, value)| value)
+}
+
+// Value of an HTTP header in curl's header dump. After redirects the dump
+// holds several responses, and the last one is the one that counts.
+fn header_value<'a>(headers: &'a str, name: &str) -> Option<&'a str> {
+    headers
+        .lines()
+        .rev()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(k, _)| k.trim().eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.trim())
+}
@@ -0,0 +1,1355 @@
+use std::{
+    collections::VecDeque,
+    fs, io,
+    path::PathBuf,
+    sync::mpsc::{Receiver, TryRecvError},
+    time::{Duration, Instant},
+};
+
+use chip8_tools::hexdump;
+
+use crate::action::Action;
+use crate::addr::Addr;
+use crate::capture::Burst;
+use crate::chip8::{Chip8, Fault, Status, RPL_FLAGS};
+use crate::config::Config;
+use crate::display::{self, Display};
+use crate::download;
+use crate::framebuffer::Framebuffer;
+use crate::hud::Hud;
+use crate::memory;
+use crate::opstats::OpcodeStats;
+use crate::pacing::{HighResTimer, Pacing};
+use crate::palette::Palette;
+use crate::paths;
+use crate::quirks::Quirks;
+use crate::romconfig::RomConfig;
+use crate::romheader;
+use crate::savestate::SaveState;
+use crate::sha1;
+use crate::sharedmem::SharedState;
+use crate::timers::{StepTimers, TimerClock};
+
+const PRESENT_PERIOD: Duration = Duration::from_nanos(1_000_000_000 / 60);
+const LOW_POWER_PRESENT_PERIOD: Duration = Duration::from_nanos(1_000_000_000 / 30);
+
+// How long to block waiting for input when nothing is executing.
+const IDLE_WAIT: Duration = Duration::from_millis(4);
+const LOW_POWER_IDLE_WAIT: Duration = Duration::from_millis(100);
+const METRICS_PERIOD: Duration = Duration::from_secs(1);
+
+// Addresses listed by the profiler overlay.
+const HOT_SPOTS: usize = 8;
+
+// Screenshots copied to the clipboard are scaled up this much from hires pixels.
+const CLIPBOARD_SCALE: usize = 4;
+
+// Ranges listed by the memory diff view, and bytes shown of each.
+const MEMORY_DIFF_LINES: usize = 12;
+const MEMORY_DIFF_BYTES: usize = 4;
+
+// Beeps shorter than this many timer ticks don't get a countdown bar.
+const BEEP_BAR_MIN_TICKS: u8 = 30;
+
+// Instructions per second when neither the ROM nor the command line say otherwise.
+const DEFAULT_IPS: u32 = 250;
+
+// Speeds the machine runs at, and the step of the speed hotkeys. Games want
+// anything from a few hundred instructions per second to tens of thousands
+// for SCHIP titles.
+pub const MIN_IPS: u32 = 60;
+pub const MAX_IPS: u32 = 100_000;
+const SPEED_STEP_PERCENT: u32 = 125;
+
+// Timer ticks of history kept for rewinding: ten seconds.
+const REWIND_FRAMES: usize = 600;
+
+// ROM setting listing the debugging views open for it, comma separated.
+const DEBUG_VIEWS_KEY: &str = "debug-views";
+
+// Longest stretch of wall-clock time that is caught up on at once. Stalls
+// beyond this (window drags, modal dialogs) are dropped rather than replayed
+// as a burst of timer ticks when execution resumes.
+const MAX_CATCH_UP: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RunState {
+    Running,
+    Paused,
+    WaitingForKey,
+    Halted,
+    Faulted(Fault),
+}
+
+// A ROM kept in memory in the background while another one is shown. Its
+// machine doesn't run until it is switched back in.
+struct Tab {
+    chip8: Chip8,
+    state: RunState,
+    rom_path: Option<PathBuf>,
+    ips: u32,
+    cycle_debt: Duration,
+    timer_clock: TimerClock,
+    rom_hash: Option<String>,
+    rom_config: Option<RomConfig>,
+    palette: Palette,
+    // its debugging views, which follow its machine
+    profiling: bool,
+    draw_order: bool,
+    memory_baseline: Option<Vec<u8>>,
+}
+
+// Drives a Chip8 machine from the SDL frontend.
+pub struct Emulator {
+    chip8: Chip8,
+    display: Display,
+    state: RunState,
+    rom_path: Option<PathBuf>,
+    last_update: Instant,
+    ips: u32,
+    cycle_debt: Duration,
+    timer_hz: u32,
+    timer_clock: TimerClock,
+    step_timers: StepTimers,
+    last_present: Instant,
+    frame_pending: bool,
+    low_power: bool,
+    present_period: Duration,
+    pacing: Pacing,
+    // keeps the OS timer resolution raised while the emulator runs
+    _high_res_timer: Option<HighResTimer>,
+    // the last frame handed to the display, to skip redrawing identical ones
+    last_frame: Option<Framebuffer>,
+    // Chip8::frame_version() of the last frame handed to the display, None
+    // when it was given something else, like a run-ahead frame
+    drawn_version: Option<u64>,
+    started: Instant,
+    frames_presented: u64,
+    faults: u64,
+    rom_hash: Option<String>,
+    rom_config: Option<RomConfig>,
+    // actions waiting to be handled, from every frontend, oldest first
+    actions: VecDeque<Action>,
+    // the other open ROMs, in the order Ctrl+Tab visits them
+    tabs: VecDeque<Tab>,
+    // a ROM being downloaded in the background
+    download: Option<Receiver<Result<PathBuf, String>>>,
+    // screenshot burst in progress
+    burst: Option<Burst>,
+    burst_frames: usize,
+    burst_sheet: bool,
+    beep_bar: bool,
+    // sound timer value the current beep started at
+    beep_length: u8,
+    profiling: bool,
+    // RAM as it was when the memory diff view was marked, while it is shown
+    memory_baseline: Option<Vec<u8>>,
+    // the machine at each recent timer tick, oldest first, and whether
+    // the rewind key is held
+    rewind: VecDeque<SaveState>,
+    rewinding: bool,
+    draw_order: bool,
+    // custom overlay, refreshed from memory on every present
+    hud: Option<Hud>,
+    // RPL user flags as last saved for the current ROM
+    saved_rpl_flags: [u8; RPL_FLAGS],
+    // live state export for external tools
+    shared_state: Option<SharedState>,
+    run_ahead: bool,
+    halt_when_finished: bool,
+    // fault rather than pause when the program counter runs into data
+    unknown_streak_faults: bool,
+    // counters at the start of the current metrics period
+    metrics_start: Instant,
+    metrics_instructions: u64,
+    metrics_frames: u64,
+    // settings given on the command line, which win over those embedded in a ROM
+    cli_tickrate: Option<u32>,
+    cli_ips: Option<u32>,
+    seed: Option<u64>,
+    cli_palette: Option<Palette>,
+    cli_platform: Option<String>,
+    cli_load_address: Option<Addr>,
+    cli_quirk_overrides: Vec<(String, String)>,
+    // the quirks the command line asked for, overrides included
+    cli_quirks: Quirks,
+    // neither read nor write per-ROM settings (see Config::safe)
+    safe_mode: bool,
+}
+
+impl Emulator {
+    pub fn new(chip8: Chip8, display: Display, config: &Config) -> Self {
+        Self {
+            chip8,
+            display,
+            state: RunState::Running,
+            rom_path: None,
+            last_update: Instant::now(),
+            ips: DEFAULT_IPS,
+            cycle_debt: Duration::from_secs(0),
+            timer_hz: config.timer_hz,
+            timer_clock: TimerClock::new(config.timer_hz),
+            step_timers: config.step_timers,
+            last_present: Instant::now(),
+            frame_pending: false,
+            low_power: config.low_power,
+            present_period: if config.low_power {
+                LOW_POWER_PRESENT_PERIOD
+            } else {
+                PRESENT_PERIOD
+            },
+            pacing: config.pacing,
+            _high_res_timer: if config.high_res_timer {
+                Some(HighResTimer::start())
+            } else {
+                None
+            },
+            last_frame: None,
+            drawn_version: None,
+            started: Instant::now(),
+            frames_presented: 0,
+            faults: 0,
+            rom_hash: None,
+            rom_config: None,
+            actions: VecDeque::new(),
+            tabs: VecDeque::new(),
+            download: None,
+            burst: None,
+            burst_frames: config.burst_frames,
+            burst_sheet: config.burst_sheet,
+            beep_bar: config.beep_bar,
+            beep_length: 0,
+            profiling: false,
+            memory_baseline: None,
+            rewind: VecDeque::new(),
+            rewinding: false,
+            draw_order: false,
+            hud: config.hud.clone(),
+            saved_rpl_flags: [0; RPL_FLAGS],
+            shared_state: None,
+            run_ahead: config.run_ahead,
+            halt_when_finished: config.halt_when_finished,
+            unknown_streak_faults: config.unknown_streak_faults,
+            metrics_start: Instant::now(),
+            metrics_instructions: 0,
+            metrics_frames: 0,
+            cli_tickrate: config.tickrate,
+            cli_ips: config.ips,
+            seed: config.seed,
+            cli_palette: config.palette,
+            cli_platform: config.platform.clone(),
+            cli_load_address: config.load_address,
+            cli_quirk_overrides: config.quirk_overrides.clone(),
+            cli_quirks: config.quirks,
+            safe_mode: config.safe_mode,
+        }
+    }
+
+    // Export the machine's state to `shared` every presented frame.
+    pub fn set_shared_state(&mut self, shared: SharedState) {
+        self.shared_state = Some(shared);
+    }
+
+    // Load a ROM from disk, remembering where it came from so it can be reloaded.
+    pub fn load_rom_file(&mut self, path: &str) -> io::Result<()> {
+        let data = fs::read(path)?;
+        self.restart(&data);
+        self.rom_path = Some(PathBuf::from(path));
+        Ok(())
+    }
+
+    // Ask for an action to be carried out at the start of the next loop
+    // iteration, along with those from the display.
+    pub fn queue(&mut self, action: Action) {
+        self.actions.push_back(action);
+    }
+
+    // Load a ROM from disk into a new tab next to the current one.
+    pub fn load_rom_file_in_tab(&mut self, path: &str) -> io::Result<()> {
+        let data = fs::read(path)?;
+        self.new_tab();
+        self.restart(&data);
+        self.rom_path = Some(PathBuf::from(path));
+        Ok(())
+    }
+
+    // Make `tab` the one shown, and the one shown so far `tab`.
+    fn swap_tab(&mut self, tab: &mut Tab) {
+        std::mem::swap(&mut self.chip8, &mut tab.chip8);
+        std::mem::swap(&mut self.state, &mut tab.state);
+        std::mem::swap(&mut self.rom_path, &mut tab.rom_path);
+        std::mem::swap(&mut self.ips, &mut tab.ips);
+        std::mem::swap(&mut self.cycle_debt, &mut tab.cycle_debt);
+        std::mem::swap(&mut self.timer_clock, &mut tab.timer_clock);
+        std::mem::swap(&mut self.rom_hash, &mut tab.rom_hash);
+        std::mem::swap(&mut self.rom_config, &mut tab.rom_config);
+        let palette = self.display.palette();
+        self.display.set_palette(tab.palette);
+        tab.palette = palette;
+        std::mem::swap(&mut self.profiling, &mut tab.profiling);
+        std::mem::swap(&mut self.draw_order, &mut tab.draw_order);
+        std::mem::swap(&mut self.memory_baseline, &mut tab.memory_baseline);
+        if !self.profiling {
+            self.display.set_profile(None);
+        }
+        if !self.draw_order {
+            self.display.set_draw_tags(None);
+        }
+        self.update_memory_diff();
+        // both tabs' flags were saved before the swap
+        self.saved_rpl_flags = self.chip8.rpl_flags();
+
+        // the history belongs to the machine that was shown
+        self.rewind.clear();
+        self.display.stop_audio();
+        self.last_frame = None;
+        self.drawn_version = None;
+        self.frame_pending = true;
+    }
+
+    // Open an empty tab, with the current machine's settings, and show it.
+    fn new_tab(&mut self) {
+        let mut chip8 = self.chip8.clone();
+        chip8.load_rom(&[]);
+        chip8.reset();
+        chip8.set_profiling(false);
+        chip8.set_draw_tagging(false);
+        let mut tab = Tab {
+            chip8,
+            state: RunState::Halted,
+            rom_path: None,
+            ips: self.ips,
+            cycle_debt: Duration::from_secs(0),
+            timer_clock: TimerClock::new(self.timer_hz),
+            rom_hash: None,
+            rom_config: None,
+            palette: self.display.palette(),
+            profiling: false,
+            draw_order: false,
+            memory_baseline: None,
+        };
+        self.swap_tab(&mut tab);
+        self.tabs.push_back(tab);
+    }
+
+    fn switch_tab(&mut self, forward: bool) {
+        let next = if forward {
+            self.tabs.pop_front()
+        } else {
+            self.tabs.pop_back()
+        };
+        let mut tab = match next {
+            Some(tab) => tab,
+            None => {
+                self.notify("NO OTHER TABS");
+                return;
+            }
+        };
+        self.swap_tab(&mut tab);
+        if forward {
+            self.tabs.push_back(tab);
+        } else {
+            self.tabs.push_front(tab);
+        }
+        self.notify_tab();
+    }
+
+    // Throw away the shown tab and show the next one.
+    fn close_tab(&mut self) {
+        match self.tabs.pop_front() {
+            Some(mut tab) => {
+                self.swap_tab(&mut tab);
+                self.notify_tab();
+            }
+            None => self.notify("LAST TAB"),
+        }
+    }
+
+    fn notify_tab(&mut self) {
+        let name = self
+            .rom_path
+            .as_ref()
+            .and_then(|path| path.file_stem())
+            .map_or("EMPTY".to_string(), |stem| {
+                stem.to_string_lossy().into_owned()
+            });
+        self.notify(&format!("TAB {} OF {}", name, self.tabs.len() + 1));
+    }
+
+    // Show the first tab opened again, after opening several at startup.
+    pub fn show_first_tab(&mut self) {
+        if !self.tabs.is_empty() {
+            self.switch_tab(true);
+        }
+    }
+
+    // Download a ROM in the background and start it once it arrives. Any
+    // running program is stopped meanwhile.
+    pub fn load_rom_url(&mut self, url: &str) {
+        self.download = Some(download::spawn_fetch(url, &paths::data_dir()));
+        self.state = RunState::Halted;
+        self.notify("DOWNLOADING ROM");
+    }
+
+    // Start saving the next presented frames to a new screenshot directory.
+    fn start_burst(&mut self) {
+        match Burst::new(&paths::data_dir(), self.burst_frames, self.burst_sheet) {
+            Ok(burst) => {
+                log!(
+                    "Capturing {} frames to {}",
+                    self.burst_frames,
+                    burst.dir().display()
+                );
+                self.burst = Some(burst);
+                // the frame on screen now counts as the first one
+                self.frame_pending = true;
+            }
+            Err(e) => self.notify(&format!("Could not start capture: {}", e)),
+        }
+    }
+
+    // Copy the frame on screen to the clipboard, blown up so it isn't
+    // postage-stamp sized where it gets pasted.
+    fn copy_screenshot(&mut self) {
+        let image = self.display.frame_image().scaled(CLIPBOARD_SCALE);
+        match self.display.copy_image(&image) {
+            Ok(()) => self.notify("SCREENSHOT COPIED"),
+            Err(e) => self.notify(&format!("Could not copy screenshot: {}", e)),
+        }
+    }
+
+    fn capture_frame(&mut self) {
+        let burst = match &mut self.burst {
+            Some(burst) => burst,
+            None => return,
+        };
+
+        match burst.add(self.display.frame_image()) {
+            Ok(false) => (),
+            Ok(true) => {
+                self.burst = None;
+                self.notify("CAPTURE DONE");
+            }
+            Err(e) => {
+                self.burst = None;
+                self.notify(&format!("Capture failed: {}", e));
+            }
+        }
+    }
+
+    fn poll_download(&mut self) {
+        let result = match self.download.as_ref().map(|d| d.try_recv()) {
+            Some(Ok(result)) => result,
+            Some(Err(TryRecvError::Empty)) | None => return,
+            Some(Err(TryRecvError::Disconnected)) => Err("download thread died".to_string()),
+        };
+        self.download = None;
+
+        // the cached copy is what gets reloaded, so Ctrl+R works offline
+        match result {
+            Ok(path) => self.queue(Action::OpenRom(path.to_string_lossy().into_owned())),
+            Err(e) => self.notify(&e),
+        }
+    }
+
+    // Nothing to run until a ROM is dropped on the window.
+    pub fn wait_for_rom(&mut self) {
+        log!("No game defined, drop a ROM onto the window to start");
+        self.state = RunState::Halted;
+        // a good moment to learn the keys
+        self.display.set_keypad_guide(true);
+    }
+
+    pub fn run(&mut self) {
+        let mut logged_state = self.state;
+        loop {
+            self.display.poll_input();
+            self.poll_download();
+            self.actions.extend(self.display.take_actions());
+            while let Some(action) = self.actions.pop_front() {
+                verbose!("Action: {:?}", action);
+                if action == Action::Quit {
+                    // readers learn the export is gone from its handshake file going away
+                    self.shared_state = None;
+                    return;
+                }
+                self.handle_action(action);
+            }
+
+            let now = Instant::now();
+            let elapsed = now - self.last_update;
+            self.last_update = now;
+
+            match self.state {
+                // rewinding also gets a crashed program going again
+                _ if self.rewinding && self.state != RunState::Paused => self.rewind(elapsed),
+                RunState::Running | RunState::WaitingForKey => self.run_frames(now, elapsed),
+                RunState::Paused | RunState::Halted | RunState::Faulted(_) => (),
+            }
+            if self.state != logged_state {
+                verbose!("Run state: {:?} -> {:?}", logged_state, self.state);
+                logged_state = self.state;
+            }
+            // key changes not run through yet still reach single steps and the
+            // next frame; the machine keeps their edges until it takes them
+            self.chip8.set_keypad(self.display.take_keypad(now));
+
+            if self.chip8.rpl_flags() != self.saved_rpl_flags {
+                self.save_rpl_flags();
+            }
+            if self.memory_baseline.is_some() {
+                self.update_memory_diff();
+            }
+            self.update_pixel_info();
+            // every vram change is an emulated frame, but only one presented
+            // frame is shown per 60 Hz period
+            if self.display.take_redraw() {
+                self.frame_pending = true;
+            }
+            let running_ahead = self.run_ahead && self.is_executing();
+            if running_ahead {
+                // the frame ahead can change even when the current one doesn't
+                self.frame_pending = true;
+            } else if self.drawn_version != Some(self.chip8.frame_version()) {
+                self.drawn_version = Some(self.chip8.frame_version());
+                self.push_frame();
+            }
+            if self.frame_pending && now - self.last_present >= self.present_period {
+                if running_ahead {
+                    self.push_frame_ahead();
+                }
+                if self.draw_order {
+                    self.display.set_draw_tags(self.chip8.take_draw_tags());
+                }
+                if let Some(hud) = &self.hud {
+                    let chip8 = &self.chip8;
+                    self.display
+                        .set_hud(hud.shapes(|addr| chip8.peek(Addr::new(addr))));
+                }
+                self.display.present();
+                self.capture_frame();
+                if let Some(shared) = &mut self.shared_state {
+                    shared.update(&self.chip8);
+                }
+                self.frames_presented += 1;
+                self.last_present = now;
+                self.frame_pending = false;
+            }
+
+            if now - self.metrics_start >= METRICS_PERIOD {
+                self.update_metrics(now);
+            }
+
+            if self.beep_bar {
+                self.update_beep_bar();
+            }
+            if self.chip8.sound_active() && self.is_executing() && !self.rewinding {
+                self.display.start_audio();
+            } else {
+                self.display.stop_audio();
+            }
+
+            if self.is_executing() {
+                self.pacing.wait_until(self.next_present_slot());
+            } else if self.low_power {
+                self.display.wait_event(LOW_POWER_IDLE_WAIT);
+            } else {
+                self.display.wait_event(IDLE_WAIT);
+            }
+        }
+    }
+
+    // The next time a frame could be presented, one present period after the
+    // last one or a whole number of periods later if that has passed.
+    fn next_present_slot(&self) -> Instant {
+        let now = Instant::now();
+        let due = self.last_present + self.present_period;
+        if due > now {
+            return due;
+        }
+        let period = self.present_period.as_nanos();
+        let late = (now - due).as_nanos() % period;
+        now + Duration::from_nanos((period - late) as u64)
+    }
+
+    fn push_frame(&mut self) {
+        // in low power mode, draws that leave the screen as it was cost nothing
+        if self.low_power {
+            if self.last_frame.as_ref() == Some(self.chip8.vram()) {
+                return;
+            }
+            self.last_frame = Some(self.chip8.vram().clone());
+        }
+
+        self.display
+            .push_frame(self.chip8.vram(), self.chip8.hires());
+        self.frame_pending = true;
+    }
+
+    // Run a copy of the machine one 60 Hz frame further with the keys held
+    // now and show its screen instead, so a key press shows up a frame
+    // sooner. The copy is thrown away; the real machine is untouched.
+    fn push_frame_ahead(&mut self) {
+        let mut ahead = self.chip8.clone();
+        let _ = ahead.run_frame((self.ips / 60).max(1) as usize);
+        self.display.push_frame(ahead.vram(), ahead.hires());
+        // the real frame is pushed again once run-ahead stops
+        self.drawn_version = None;
+    }
+
+    // Instruction counts and times of the shown machine, if collected.
+    pub fn opcode_stats(&self) -> Option<&OpcodeStats> {
+        self.chip8.opcode_stats()
+    }
+
+    // The fault the program stopped on, if that is how it ended.
+    pub fn fault(&self) -> Option<Fault> {
+        match self.state {
+            RunState::Faulted(fault) => Some(fault),
+            _ => None,
+        }
+    }
+
+    // Statistics about the session, for bug reports and batch tooling.
+    pub fn summary(&self) -> String {
+        let stats = self.chip8.stats();
+        let seconds = self.started.elapsed().as_secs_f64();
+        let unknown: Vec<String> = stats
+            .unknown_opcode_set
+            .iter()
+            .map(|op| format!("{:04x}", op))
+            .collect();
+
+        let mut summary = String::new();
+        summary += &format!("instructions executed: {}\n", stats.instructions);
+        summary += &format!("frames rendered:       {}\n", self.frames_presented);
+        summary += &format!(
+            "average speed:         {:.0} instructions/s\n",
+            stats.instructions as f64 / seconds.max(f64::EPSILON)
+        );
+        summary += &format!(
+            "unknown opcodes:       {} [{}]\n",
+            stats.unknown_opcodes,
+            unknown.join(" ")
+        );
+        summary += &format!("faults:                {}\n", self.faults);
+        summary += &format!("peak stack depth:      {}\n", stats.peak_stack_depth);
+        summary += &format!(
+            "ROM SHA-1:             {}",
+            self.rom_hash.as_deref().unwrap_or("-")
+        );
+        summary
+    }
+
+    // What the machine was doing, for crash reports.
+    pub fn crash_context(&self) -> String {
+        let mut context = String::new();
+        context += &format!(
+            "ROM:          {}\n",
+            self.rom_path
+                .as_ref()
+                .map_or("-".into(), |p| p.display().to_string())
+        );
+        context += &format!(
+            "ROM SHA-1:    {}\n",
+            self.rom_hash.as_deref().unwrap_or("-")
+        );
+        context += &format!("state:        {:?}\n", self.state);
+        context += &format!("speed:        {} instructions/s\n", self.ips);
+        context += &format!("timer rate:   {} Hz\n", self.timer_hz);
+        context += &format!("quirks:       {:?}\n", self.chip8.quirks());
+        context += &format!("cpu:          {}\n", self.chip8.cpu_state());
+        context += &format!("stats:        {:?}\n", self.chip8.stats());
+        context += "\nlast instructions, oldest first:\n";
+        for (pc, op) in self.chip8.trace() {
+            context += &format!("  {:#05x}: {:04x}\n", pc, op);
+        }
+        context
+    }
+
+    fn update_metrics(&mut self, now: Instant) {
+        let seconds = (now - self.metrics_start).as_secs_f64();
+        let instructions = self.chip8.stats().instructions;
+        let ips = (instructions - self.metrics_instructions) as f64 / seconds;
+        let fps = (self.frames_presented - self.metrics_frames) as f64 / seconds;
+        let input_queue = self.display.input_queue_peak();
+
+        self.display.set_metrics(vec![
+            format!("{:.0} IPS", ips),
+            format!("{:.0} FPS", fps),
+            format!("TIMERS {} HZ", self.timer_hz),
+            format!("INPUT QUEUE {}", input_queue),
+        ]);
+        if self.display.metrics_visible() {
+            self.frame_pending = true;
+        }
+        if let Some(profile) = self.chip8.profile_mut() {
+            let hot_spots = profile
+                .hottest(HOT_SPOTS)
+                .iter()
+                .map(|(pc, hits)| format!("{:03X} {:>6}", pc.index(), hits))
+                .collect();
+            self.display
+                .set_profile(Some((profile.heatmap(), hot_spots)));
+            profile.decay();
+        }
+
+        self.metrics_start = now;
+        self.metrics_instructions = instructions;
+        self.metrics_frames = self.frames_presented;
+    }
+
+    // List the bytes that changed since the memory baseline, a range per line
+    // with the old bytes and the new ones, or hide the view without a baseline.
+    fn update_memory_diff(&mut self) {
+        let baseline = match &self.memory_baseline {
+            Some(baseline) => baseline,
+            None => {
+                self.display.set_memory_diff(None);
+                return;
+            }
+        };
+        let ram = self.chip8.ram();
+        let ranges = memory::changed_ranges(baseline, ram);
+        let hex = |bytes: &[u8]| {
+            bytes
+                .iter()
+                .map(|b| format!("{:02X}", b))
+                .collect::<String>()
+        };
+
+        let mut lines = vec![format!("RAM CHANGES {}", ranges.len())];
+        for &(first, last) in ranges.iter().take(MEMORY_DIFF_LINES) {
+            let (old, new) = (&baseline[first..=last], &ram[first..=last]);
+            // long runs are cut short to keep the panel narrow
+            let shown = old.len().min(MEMORY_DIFF_BYTES);
+            lines.push(format!(
+                "{:03X}-{:03X} {}>{}",
+                first,
+                last,
+                hex(&old[..shown]),
+                hex(&new[..shown])
+            ));
+        }
+        if ranges.len() > MEMORY_DIFF_LINES {
+            lines.push("...".to_string());
+        }
+        self.display.set_memory_diff(Some(lines));
+    }
+
+    // While paused, describe the game pixel under the mouse: its position in
+    // the program's coordinates and the value on each plane.
+    fn update_pixel_info(&mut self) {
+        let info = match (self.state, self.display.hovered_pixel()) {
+            (RunState::Paused, Some((col, row))) => {
+                let (bw, bh) = self.chip8.pixel_size();
+                let vram = self.chip8.vram();
+                let value = vram.get(col, row);
+                let planes: String = (0..vram.planes())
+                    .rev()
+                    .map(|plane| if value >> plane & 1 == 1 { '1' } else { '0' })
+                    .collect();
+                Some(format!("X {} Y {} = {}", col / bw, row / bh, planes))
+            }
+            _ => None,
+        };
+        self.display.set_pixel_info(info);
+    }
+
+    // Remember the current palette for this ROM.
+    fn save_palette(&mut self) {
+        let palette = self.display.palette();
+        if let Some(rom_config) = &mut self.rom_config {
+            rom_config.set("palette", &palette.to_string());
+            if let Err(e) = rom_config.save() {
+                log!("Could not save ROM settings: {}", e);
+            }
+        }
+    }
+
+    // Remember the current speed for this ROM, as the instructions per frame
+    // its header would give. Stored this way the ROM library shows and edits it.
+    fn save_speed(&mut self) {
+        let tickrate = (self.ips + 30) / 60;
+        if let Some(rom_config) = &mut self.rom_config {
+            rom_config.set("tickrate", &tickrate.to_string());
+            if let Err(e) = rom_config.save() {
+                log!("Could not save ROM settings: {}", e);
+                return;
+            }
+            self.notify(&format!("SAVED {} INSTRUCTIONS A FRAME", tickrate));
+        }
+    }
+
+    fn set_profiling(&mut self, on: bool) {
+        self.profiling = on;
+        self.chip8.set_profiling(on);
+        if !on {
+            self.display.set_profile(None);
+        }
+    }
+
+    fn set_draw_order(&mut self, on: bool) {
+        self.draw_order = on;
+        self.chip8.set_draw_tagging(on);
+        if !on {
+            self.display.set_draw_tags(None);
+        }
+        self.frame_pending = true;
+    }
+
+    // Remember which debugging views are open for this ROM, so they come
+    // back the next time it is opened.
+    fn save_debug_views(&mut self) {
+        let views: Vec<&str> = [
+            (self.profiling, "profiler"),
+            (self.memory_baseline.is_some(), "memory-diff"),
+            (self.draw_order, "draw-order"),
+        ]
+        .iter()
+        .filter(|(open, _)| *open)
+        .map(|&(_, name)| name)
+        .collect();
+        if let Some(rom_config) = &mut self.rom_config {
+            rom_config.set(DEBUG_VIEWS_KEY, &views.join(","));
+            if let Err(e) = rom_config.save() {
+                log!("Could not save ROM settings: {}", e);
+            }
+        }
+    }
+
+    // Open the debugging views saved for this ROM and close the others. ROMs
+    // without saved views keep whatever is open.
+    fn restore_debug_views(&mut self) {
+        let views: Vec<String> = match self
+            .rom_config
+            .as_ref()
+            .and_then(|c| c.get(DEBUG_VIEWS_KEY))
+        {
+            Some(views) => views.split(',').map(str::to_string).collect(),
+            None => return,
+        };
+        let open = |name: &str| views.iter().any(|v| v == name);
+        self.set_profiling(open("profiler"));
+        self.set_draw_order(open("draw-order"));
+        self.memory_baseline = if open("memory-diff") {
+            Some(self.chip8.ram().to_vec())
+        } else {
+            None
+        };
+        self.update_memory_diff();
+    }
+
+    // Tell the user about something, both in the log and on screen.
+    fn notify(&mut self, message: &str) {
+        log!("{}", message);
+        self.display.show_toast(message);
+    }
+
+    fn is_executing(&self) -> bool {
+        matches!(self.state, RunState::Running | RunState::WaitingForKey)
+    }
+
+    fn advance_timers(&mut self, elapsed: Duration) {
+        let ticks = self.timer_clock.advance(elapsed.min(MAX_CATCH_UP));
+        for _ in 0..ticks {
+            self.chip8.tick_timers();
+            if self.rewind.len() == REWIND_FRAMES {
+                self.rewind.pop_front();
+            }
+            self.rewind.push_back(self.chip8.save_state());
+        }
+        if ticks > 0 {
+            self.display.timer_tick();
+        }
+    }
+
+    // Step back one recorded timer tick for every tick of time passed,
+    // stopping at the oldest one kept.
+    fn rewind(&mut self, elapsed: Duration) {
+        let ticks = self.timer_clock.advance(elapsed.min(MAX_CATCH_UP));
+        for _ in 0..ticks {
+            let state = match self.rewind.pop_back() {
+                Some(state) => state,
+                None => break,
+            };
+            // states come from this same machine, so they always fit
+            let _ = self.chip8.load_state(&state);
+            self.state = RunState::Running;
+            self.cycle_debt = Duration::from_secs(0);
+        }
+    }
+
+    // Follow the sound timer in timer ticks rather than frames, so the bar
+    // empties in step with the beep at any frame rate.
+    fn update_beep_bar(&mut self) {
+        let remaining = self.chip8.sound_timer();
+        // a higher value than before means the program started a new beep
+        if remaining > self.beep_length || remaining == 0 {
+            self.beep_length = remaining;
+        }
+
+        let bar = if self.beep_length >= BEEP_BAR_MIN_TICKS {
+            Some(remaining as f32 / self.beep_length as f32)
+        } else {
+            None
+        };
+        self.display.set_beep_bar(bar);
+    }
+
+    // Run the time up to `now` a timer tick at a time, handing the machine
+    // the keys as they were at the end of each tick, so input lands in the
+    // frame it happened in however long the host loop took to get here.
+    fn run_frames(&mut self, now: Instant, elapsed: Duration) {
+        let elapsed = elapsed.min(MAX_CATCH_UP);
+        let mut done = Duration::from_secs(0);
+        while done < elapsed && self.is_executing() {
+            let slice = (elapsed - done).min(self.timer_clock.until_next_tick());
+            done += slice;
+            let keypad = self.display.take_keypad(now - elapsed + done);
+            self.chip8.set_keypad(keypad);
+            self.advance_timers(slice);
+            self.run_cycles(slice);
+        }
+    }
+
+    fn run_cycles(&mut self, elapsed: Duration) {
+        let period = Duration::from_nanos(1_000_000_000 / self.ips as u64);
+        self.cycle_debt += elapsed.min(MAX_CATCH_UP);
+        while self.cycle_debt >= period && self.is_executing() {
+            self.step();
+            self.cycle_debt -= period;
+        }
+    }
+
+    fn step(&mut self) {
+        self.state = match self.chip8.cycle() {
+            Ok(Status::Running) | Ok(Status::WaitingForVblank) => RunState::Running,
+            Ok(Status::WaitingForKey) => RunState::WaitingForKey,
+            Ok(Status::UnknownOpcode { pc, op }) => {
+                log!("Skipped unknown opcode {:#06x} at {:#05x}", op, pc);
+                RunState::Running
+            }
+            Ok(Status::Halted) => {
+                log!("Program halted");
+                RunState::Halted
+            }
+            Ok(Status::Finished { pc }) if self.halt_when_finished => {
+                log!("Program finished: it jumps to itself at {:#05x}", pc);
+                RunState::Halted
+            }
+            Ok(Status::Finished { .. }) => RunState::Running,
+            Ok(Status::Exited) => {
+                // close its tab, or end the session the way closing the
+                // window does when it is the only one
+                log!("Program exited");
+                self.actions.push_back(if self.tabs.is_empty() {
+                    Action::Quit
+                } else {
+                    Action::CloseTab
+                });
+                RunState::Halted
+            }
+            Err(Fault::Runaway { pc, jump }) if !self.unknown_streak_faults => {
+                self.log_runaway(jump);
+                self.notify(&format!("PAUSED: RAN INTO DATA AT {:03X}", pc.index()));
+                RunState::Paused
+            }
+            Err(fault) => {
+                log!("Program faulted: {}", fault);
+                self.faults += 1;
+                RunState::Faulted(fault)
+            }
+        };
+    }
+
+    // Log the instructions from the jump where the program probably went
+    // astray, or all that are kept without one.
+    fn log_runaway(&self, jump: Option<Addr>) {
+        let trace: Vec<_> = self.chip8.trace().collect();
+        let from = jump
+            .and_then(|jump| trace.iter().rposition(|&&(pc, _)| pc == jump))
+            .unwrap_or(0);
+        log!("Program counter ran into data, the instructions leading there:");
+        for (pc, op) in &trace[from..] {
+            log!("  {:03X}  {:04X}", pc.index(), op);
+        }
+    }
+
+    fn handle_action(&mut self, action: Action) {
+        match action {
+            Action::PasteRom => self.paste_rom(),
+            Action::ReloadRom => self.reload_rom(),
+            Action::Reset => self.reset(),
+            Action::SaveState => self.save_state(),
+            Action::LoadState => self.load_state(),
+            Action::Rewind(held) => {
+                if held && !self.rewinding {
+                    self.notify("REWIND");
+                }
+                self.rewinding = held;
+            }
+            Action::TogglePause => self.toggle_pause(),
+            Action::Step => self.single_step(),
+            Action::ToggleQuirk(n) => {
+                if let Some(setting) = self.chip8.quirks_mut().toggle(n) {
+                    self.notify(&setting);
+                }
+            }
+            Action::TogglePixel(col, row) => {
+                if self.state == RunState::Paused {
+                    let (bw, bh) = self.chip8.pixel_size();
+                    self.chip8.toggle_pixel(col / bw, row / bh);
+                }
+            }
+            Action::ToggleKeypadGuide => {
+                let shown = self.display.keypad_guide_shown();
+                self.display.set_keypad_guide(!shown);
+            }
+            Action::ToggleMetrics => {
+                self.display.toggle_metrics();
+                self.frame_pending = true;
+            }
+            Action::ToggleMagnifier => {
+                self.display.toggle_magnifier();
+                self.frame_pending = true;
+            }
+            Action::Arrow(dx, dy) => {
+                if self.display.palette_editor_open() {
+                    self.display.edit_palette(dy, dx * 8);
+                } else {
+                    self.display.move_magnifier(dx, dy);
+                }
+                self.frame_pending = true;
+            }
+            Action::CaptureBurst => self.start_burst(),
+            Action::CopyScreenshot => self.copy_screenshot(),
+            Action::ToggleProfiler => {
+                self.set_profiling(!self.profiling);
+                self.notify(if self.profiling {
+                    "PROFILER ON"
+                } else {
+                    "PROFILER OFF"
+                });
+                self.save_debug_views();
+            }
+            Action::ToggleMemoryDiff => {
+                if self.memory_baseline.take().is_none() {
+                    self.memory_baseline = Some(self.chip8.ram().to_vec());
+                }
+                self.update_memory_diff();
+                self.save_debug_views();
+            }
+            Action::MarkMemory => {
+                self.memory_baseline = Some(self.chip8.ram().to_vec());
+                self.update_memory_diff();
+                self.notify("MEMORY MARKED");
+            }
+            Action::ToggleBeepBar => {
+                self.beep_bar = !self.beep_bar;
+                if !self.beep_bar {
+                    self.display.set_beep_bar(None);
+                }
+            }
+            Action::ChangeSpeed(faster) => {
+                let ips = if faster {
+                    self.ips * SPEED_STEP_PERCENT / 100
+                } else {
+                    self.ips * 100 / SPEED_STEP_PERCENT
+                };
+                self.ips = ips.clamp(MIN_IPS, MAX_IPS);
+                self.notify(&format!("SPEED {} IPS, CTRL+S TO KEEP", self.ips));
+            }
+            Action::SaveSpeed => self.save_speed(),
+            Action::ToggleDrawOrder => {
+                self.set_draw_order(!self.draw_order);
+                self.save_debug_views();
+            }
+            Action::ToggleCommandPalette => {
+                self.display.toggle_command_palette();
+                self.frame_pending = true;
+            }
+            Action::CyclePalette => {
+                let palette = self.display.palette().next_built_in();
+                self.display.set_palette(palette);
+                self.save_palette();
+                self.frame_pending = true;
+            }
+            Action::TogglePaletteEditor => {
+                if self.display.palette_editor_open() {
+                    self.save_palette();
+                }
+                self.display.toggle_palette_editor();
+                self.frame_pending = true;
+            }
+            Action::OpenRom(path) => self.open_rom(path),
+            Action::NewTab => {
+                self.new_tab();
+                self.notify("NEW TAB, DROP A ROM ON THE WINDOW");
+            }
+            Action::SwitchTab(forward) => self.switch_tab(forward),
+            Action::CloseTab => self.close_tab(),
+            Action::Quit => (),
+        }
+    }
+
+    fn toggle_pause(&mut self) {
+        self.state = match self.state {
+            RunState::Running | RunState::WaitingForKey => RunState::Paused,
+            RunState::Paused => RunState::Running,
+            state => state,
+        };
+    }
+
+    // Execute exactly one instruction while paused and log the resulting state.
+    fn single_step(&mut self) {
+        if self.state != RunState::Paused {
+            return;
+        }
+
+        self.step();
+        if self.step_timers == StepTimers::Proportional {
+            for _ in 0..self.timer_clock.advance_step(self.ips) {
+                self.chip8.tick_timers();
+            }
+        }
+        if self.is_executing() {
+            self.state = RunState::Paused;
+        }
+        log!("{}", self.chip8.cpu_state());
+    }
+
+    // Load a new program, apply the settings embedded in it and start it from scratch.
+    fn restart(&mut self, data: &[u8]) {
+        let (program, header) = romheader::split(data);
+        let header = header.unwrap_or_default();
+        for problem in &header.problems {
+            log!("ROM config: {}", problem);
+        }
+        let rom_hash = sha1::hex_digest(data);
+        let rom_config = RomConfig::load(&paths::data_dir(), &rom_hash);
+        let header = if self.safe_mode {
+            header
+        } else {
+            rom_config.overlay(header)
+        };
+
+        // nothing carries over from the previous ROM, and the command line's
+        // platform wins over the header's
+        *self.chip8.quirks_mut() = self.cli_quirks;
+        if let (Some(platform), None) = (&header.platform, &self.cli_platform) {
+            match Quirks::preset(platform) {
+                Some(mut quirks) => {
+                    for (name, setting) in self.cli_quirk_overrides.iter() {
+                        let _ = quirks.set(name, setting);
+                    }
+                    *self.chip8.quirks_mut() = quirks;
+                }
+                None => log!("Unsupported platform '{}', running as chip8", platform),
+            }
+        }
+        self.display
+            .set_title(header.title.as_deref().unwrap_or(display::DEFAULT_TITLE));
+        let saved_palette = rom_config
+            .get("palette")
+            .filter(|_| !self.safe_mode)
+            .and_then(Palette::parse);
+
+        let tickrate = self.cli_tickrate.or(header.tickrate);
+        self.ips = self
+            .cli_ips
+            .or(tickrate.and_then(|t| t.checked_mul(60)))
+            .unwrap_or(DEFAULT_IPS)
+            .clamp(MIN_IPS, MAX_IPS);
+        self.display.set_palette(
+            self.cli_palette
+                .or(saved_palette)
+                .or(header.palette)
+                .unwrap_or_default(),
+        );
+
+        let platform = self.cli_platform.as_ref().or(header.platform.as_ref());
+        let load_address = self.cli_load_address.unwrap_or_else(|| {
+            platform.map_or(memory::Layout::default().start_pc, |p| {
+                memory::platform_start_pc(p)
+            })
+        });
+        self.chip8.set_load_address(load_address);
+        let dropped = self.load_program(program);
+        verbose!(
+            "Loaded {} bytes at {:#05x} with {:?}, {} instructions per second, ROM {}",
+            program.len() - dropped,
+            load_address,
+            self.chip8.quirks(),
+            self.ips,
+            rom_hash
+        );
+        self.rom_hash = Some(rom_hash);
+        // without settings to save to, the hotkeys that save them do nothing
+        self.rom_config = (!self.safe_mode).then_some(rom_config);
+        self.load_rpl_flags();
+        self.restore_debug_views();
+        self.start_over();
+    }
+
+    // Put `program` in RAM at the load address, returning how many of its
+    // bytes didn't fit.
+    fn load_program(&mut self, program: &[u8]) -> usize {
+        let dropped = self.chip8.load_rom(program);
+        if dropped > 0 {
+            log!(
+                "The last {} bytes of the ROM don't fit in RAM, try a larger --ram-size",
+                dropped
+            );
+        }
+        dropped
+    }
+
+    // Run the loaded program from the top.
+    fn start_over(&mut self) {
+        self.chip8.reset();
+        self.rewind.clear();
+        // restarting replays the same random numbers too
+        if let Some(seed) = self.seed {
+            self.chip8.seed_rng(seed);
+        }
+        self.state = RunState::Running;
+        self.timer_clock = TimerClock::new(self.timer_hz);
+        self.cycle_debt = Duration::from_secs(0);
+    }
+
+    fn open_rom(&mut self, path: String) {
+        match fs::read(&path) {
+            Ok(data) => {
+                self.restart(&data);
+                self.rom_path = Some(PathBuf::from(path));
+            }
+            Err(e) => self.notify(&format!("Could not open {}: {}", path, e)),
+        }
+    }
+
+    // Restart the program as it was loaded, keeping quirks, speed and the
+    // window as they are.
+    fn reset(&mut self) {
+        if self.rom_hash.is_none() {
+            return;
+        }
+        self.start_over();
+        self.notify("RESET");
+    }
+
+    // Where the current ROM's save state lives, next to its settings.
+    fn state_path(&self) -> Option<PathBuf> {
+        let hash = self.rom_hash.as_ref()?;
+        Some(paths::data_dir().join("roms").join(format!("{}.c8s", hash)))
+    }
+
+    // Where the current ROM's RPL user flags are kept between sessions.
+    fn rpl_flags_path(&self) -> Option<PathBuf> {
+        let hash = self.rom_hash.as_ref()?;
+        Some(paths::data_dir().join("roms").join(format!("{}.rpl", hash)))
+    }
+
+    // Restore the flags the ROM saved last time, or clear them.
+    fn load_rpl_flags(&mut self) {
+        let mut flags = [0; RPL_FLAGS];
+        if let Some(bytes) = self.rpl_flags_path().and_then(|path| fs::read(path).ok()) {
+            let len = bytes.len().min(RPL_FLAGS);
+            flags[..len].copy_from_slice(&bytes[..len]);
+        }
+        self.chip8.set_rpl_flags(flags);
+        self.saved_rpl_flags = flags;
+    }
+
+    fn save_rpl_flags(&mut self) {
+        let flags = self.chip8.rpl_flags();
+        self.saved_rpl_flags = flags;
+        let path = match self.rpl_flags_path() {
+            Some(path) => path,
+            None => return,
+        };
+        let saved = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(&path, flags));
+        if let Err(e) = saved {
+            log!("Could not save RPL flags to {}: {}", path.display(), e);
+        }
+    }
+
+    fn save_state(&mut self) {
+        let path = match self.state_path() {
+            Some(path) => path,
+            None => return,
+        };
+        let bytes = self.chip8.save_state().to_bytes();
+        let saved = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(&path, bytes));
+        match saved {
+            Ok(()) => self.notify("STATE SAVED"),
+            Err(e) => self.notify(&format!("Could not save state: {}", e)),
+        }
+    }
+
+    fn load_state(&mut self) {
+        let path = match self.state_path() {
+            Some(path) => path,
+            None => return,
+        };
+        let loaded = fs::read(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| SaveState::parse(&bytes))
+            .and_then(|state| self.chip8.load_state(&state));
+        match loaded {
+            Ok(()) => {
+                self.state = RunState::Running;
+                self.cycle_debt = Duration::from_secs(0);
+                self.rewind.clear();
+                self.frame_pending = true;
+                self.notify("STATE LOADED");
+            }
+            Err(e) => self.notify(&format!("Could not load state: {}", e)),
+        }
+    }
+
+    // Re-read the ROM file and restart it. Everything but the program stays
+    // as it is: quirks and speed as changed with the hotkeys, colors, and the
+    // ROM's saved settings, RPL flags and save state, so they outlive edits
+    // to the program that change its hash.
+    fn reload_rom(&mut self) {
+        let path = match &self.rom_path {
+            Some(path) => path.clone(),
+            None => return,
+        };
+
+        match fs::read(&path) {
+            Ok(data) => {
+                self.load_program(romheader::split(&data).0);
+                self.start_over();
+            }
+            Err(e) => self.notify(&format!("Could not reload {}: {}", path.display(), e)),
+        }
+    }
+
+    // Assemble a hex dump from the clipboard into RAM at 0x200 and restart.
+    fn paste_rom(&mut self) {
+        let text = match self.display.clipboard_text() {
+            Ok(text) => text,
+            Err(e) => {
+                self.notify(&format!("Could not read clipboard: {}", e));
+                return;
+            }
+        };
+
+        if download::is_url(text.trim()) {
+            self.load_rom_url(text.trim());
+            return;
+        }
+        match hexdump::parse(&text) {
+            Ok(data) => self.restart(&data),
+            Err(e) => self.notify(&format!("Could not paste ROM: {}", e)),
+        }
+    }
+}
@@ -0,0 +1,63 @@
+// How the process ends when something goes wrong, so wrapper scripts can
+// tell failures apart by exit code instead of parsing messages.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Failure {
+    // Bad command line, or a headless check that found a problem.
+    General,
+    // The ROM could not be found, read or downloaded.
+    Rom,
+    // The program stopped on a machine fault, like a stack overflow.
+    Fault,
+    // SDL could not open the window, audio or input.
+    Sdl,
+}
+
+impl Failure {
+    pub fn code(self) -> i32 {
+        match self {
+            Failure::General => 1,
+            Failure::Rom => 2,
+            Failure::Fault => 3,
+            Failure::Sdl => 4,
+        }
+    }
+
+    fn kind(self) -> &'static str {
+        match self {
+            Failure::General => "general",
+            Failure::Rom => "rom",
+            Failure::Fault => "fault",
+            Failure::Sdl => "sdl",
+        }
+    }
+}
+
+// Report `message` on stderr, as a JSON object with `json`, and exit with
+// the failure's code.
+pub fn fail(failure: Failure, message: &str, json: bool) -> ! {
+    if json {
+        eprintln!(
+            "{{\"error\":\"{}\",\"code\":{},\"message\":\"{}\"}}",
+            failure.kind(),
+            failure.code(),
+            escape(message)
+        );
+    } else {
+        eprintln!("{}", message);
+    }
+    std::process::exit(failure.code());
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,158 @@
+// Post-processing applied to the game picture before it is shown, as a
+// chain of filters given with --filters, e.g. "phosphor:0.6,scanlines,vignette".
+// Each filter has one strength parameter between 0 and 1.
+
+// How many buffer pixels each hires frame pixel is drawn as, per side, so
+// filters can shade within a pixel.
+pub const SUBPIXELS: usize = 4;
+
+// The game picture the filters work on, in RGBA.
+pub struct Frame {
+    pub width: usize,
+    pub height: usize,
+    // buffer pixels per emulated pixel, per side; twice as many in lores mode
+    pub cell: usize,
+    pub rgba: Vec<u8>,
+}
+
+pub trait Filter {
+    fn apply(&mut self, frame: &mut Frame);
+}
+
+// A filter as named on the command line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FilterSpec {
+    pub name: String,
+    pub amount: f32,
+}
+
+// Filter names with their default strength.
+pub const FILTERS: [(&str, f32); 4] = [
+    ("phosphor", 0.5),
+    ("scanlines", 0.3),
+    ("grid", 0.2),
+    ("vignette", 0.4),
+];
+
+// Parse a comma separated list of name[:amount], applied in order.
+pub fn parse_chain(chain: &str) -> Result<Vec<FilterSpec>, String> {
+    chain
+        .split(',')
+        .map(|item| {
+            let (name, amount) = match item.split_once(':') {
+                Some((name, amount)) => (name, Some(amount)),
+                None => (item, None),
+            };
+            let default = FILTERS
+                .iter()
+                .find(|(known, _)| *known == name)
+                .map(|&(_, amount)| amount)
+                .ok_or_else(|| format!("Unknown filter '{}'", name))?;
+            let amount = match amount {
+                Some(amount) => match amount.parse() {
+                    Ok(n) if (0.0..=1.0).contains(&n) => n,
+                    _ => return Err(format!("Invalid strength '{}' for filter {}", amount, name)),
+                },
+                None => default,
+            };
+            Ok(FilterSpec {
+                name: name.to_string(),
+                amount,
+            })
+        })
+        .collect()
+}
+
+pub fn build(spec: &FilterSpec) -> Box<dyn Filter> {
+    let amount = spec.amount;
+    match spec.name.as_str() {
+        "phosphor" => Box::new(Phosphor {
+            persistence: amount,
+            previous: Vec::new(),
+        }),
+        "scanlines" => Box::new(Scanlines { amount }),
+        "grid" => Box::new(Grid { amount }),
+        "vignette" => Box::new(Vignette { amount }),
+        _ => unreachable!("filter names are checked by parse_chain"),
+    }
+}
+
+// Pixels fade out over a few presents instead of going dark at once, like
+// the slow phosphor of old monitors. Also hides most flicker.
+struct Phosphor {
+    persistence: f32,
+    previous: Vec<u8>,
+}
+
+impl Filter for Phosphor {
+    fn apply(&mut self, frame: &mut Frame) {
+        if self.previous.len() == frame.rgba.len() {
+            for (new, &old) in frame.rgba.iter_mut().zip(self.previous.iter()) {
+                *new = (*new).max((old as f32 * self.persistence) as u8);
+            }
+        }
+        self.previous.clear();
+        self.previous.extend_from_slice(&frame.rgba);
+    }
+}
+
+// Darkens every other buffer row.
+struct Scanlines {
+    amount: f32,
+}
+
+impl Filter for Scanlines {
+    fn apply(&mut self, frame: &mut Frame) {
+        let stride = frame.width * 4;
+        for row in frame.rgba.chunks_mut(stride).skip(1).step_by(2) {
+            darken(row, 1.0 - self.amount);
+        }
+    }
+}
+
+// Darkens the edges of each emulated pixel, so they read as separate dots.
+struct Grid {
+    amount: f32,
+}
+
+impl Filter for Grid {
+    fn apply(&mut self, frame: &mut Frame) {
+        let (width, cell) = (frame.width, frame.cell);
+        for (y, row) in frame.rgba.chunks_mut(width * 4).enumerate() {
+            for (x, pixel) in row.chunks_mut(4).enumerate() {
+                if x % cell == 0 || y % cell == 0 {
+                    darken(pixel, 1.0 - self.amount);
+                }
+            }
+        }
+    }
+}
+
+// Darkens the picture towards its corners.
+struct Vignette {
+    amount: f32,
+}
+
+impl Filter for Vignette {
+    fn apply(&mut self, frame: &mut Frame) {
+        let (half_width, half_height) = (frame.width as f32 / 2.0, frame.height as f32 / 2.0);
+        for (y, row) in frame.rgba.chunks_mut(frame.width * 4).enumerate() {
+            let dy = (y as f32 + 0.5 - half_height) / half_height;
+            for (x, pixel) in row.chunks_mut(4).enumerate() {
+                let dx = (x as f32 + 0.5 - half_width) / half_width;
+                // 0 in the middle, 1 in the corners
+                let distance = (dx * dx + dy * dy) / 2.0;
+                darken(pixel, 1.0 - self.amount * distance);
+            }
+        }
+    }
+}
+
+// Scale the color channels of RGBA pixels, leaving alpha alone.
+fn darken(pixels: &mut [u8], factor: f32) {
+    for pixel in pixels.chunks_mut(4) {
+        for channel in &mut pixel[..3] {
+            *channel = (*channel as f32 * factor) as u8;
+        }
+    }
+}
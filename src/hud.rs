@@ -0,0 +1,158 @@
+use crate::palette;
+
+// A custom overlay drawn over the game every frame, built from the game's
+// memory, for things like live score displays in games that only keep the
+// score internally. It is described in a text file, one shape per line:
+//
+//   # positions and sizes are in lores pixels of the game screen
+//   rect 0 0 64 6 #202020
+//   text 1 1 #ffcc00 SCORE {byte 0x2f0} LIVES {byte 0x2f1}
+//
+// Text may read memory with {byte ADDR} (decimal), {word ADDR} (big-endian
+// 16-bit, decimal) and {hex ADDR} (two hex digits).
+#[derive(Clone, Debug, Default)]
+pub struct Hud {
+    items: Vec<Item>,
+}
+
+#[derive(Clone, Debug)]
+enum Item {
+    Rect(Rect),
+    Text {
+        x: i32,
+        y: i32,
+        color: (u8, u8, u8),
+        parts: Vec<Part>,
+    },
+}
+
+#[derive(Clone, Debug)]
+enum Part {
+    Literal(String),
+    Byte(u16),
+    Word(u16),
+    Hex(u16),
+}
+
+// A filled rectangle in lores pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub color: (u8, u8, u8),
+}
+
+// What to draw this frame, with memory already filled in.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Shape {
+    Rect(Rect),
+    Text {
+        x: i32,
+        y: i32,
+        color: (u8, u8, u8),
+        text: String,
+    },
+}
+
+impl Hud {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut items = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || format!("HUD line {}: can't understand '{}'", n + 1, line);
+            let mut words = line.splitn(5, ' ');
+            let kind = words.next().unwrap_or_default();
+            let x = words
+                .next()
+                .and_then(|w| w.parse().ok())
+                .ok_or_else(invalid)?;
+            let y = words
+                .next()
+                .and_then(|w| w.parse().ok())
+                .ok_or_else(invalid)?;
+            match kind {
+                "rect" => {
+                    let size = words.next().ok_or_else(invalid)?;
+                    let rest = words.next().ok_or_else(invalid)?;
+                    let (height, color) = rest.split_once(' ').ok_or_else(invalid)?;
+                    items.push(Item::Rect(Rect {
+                        x,
+                        y,
+                        width: size.parse().map_err(|_| invalid())?,
+                        height: height.parse().map_err(|_| invalid())?,
+                        color: palette::parse_color(color.trim()).ok_or_else(invalid)?,
+                    }));
+                }
+                "text" => {
+                    let color = words.next().and_then(palette::parse_color);
+                    let text = words.next().unwrap_or_default();
+                    items.push(Item::Text {
+                        x,
+                        y,
+                        color: color.ok_or_else(invalid)?,
+                        parts: parse_parts(text).ok_or_else(invalid)?,
+                    });
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(Self { items })
+    }
+
+    // The shapes to draw, reading memory through `peek`.
+    pub fn shapes(&self, peek: impl Fn(u16) -> u8) -> Vec<Shape> {
+        self.items
+            .iter()
+            .map(|item| match item {
+                Item::Rect(rect) => Shape::Rect(*rect),
+                Item::Text { x, y, color, parts } => Shape::Text {
+                    x: *x,
+                    y: *y,
+                    color: *color,
+                    text: parts
+                        .iter()
+                        .map(|part| match *part {
+                            Part::Literal(ref text) => text.clone(),
+                            Part::Byte(addr) => peek(addr).to_string(),
+                            Part::Word(addr) => (u16::from(peek(addr)) << 8
+                                | u16::from(peek(addr.wrapping_add(1))))
+                            .to_string(),
+                            Part::Hex(addr) => format!("{:02x}", peek(addr)),
+                        })
+                        .collect(),
+                },
+            })
+            .collect()
+    }
+}
+
+// Split text into literals and {kind ADDR} placeholders.
+fn parse_parts(text: &str) -> Option<Vec<Part>> {
+    let mut parts = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        if start > 0 {
+            parts.push(Part::Literal(rest[..start].to_string()));
+        }
+        let end = rest[start..].find('}')? + start;
+        let (kind, addr) = rest[start + 1..end].trim().split_once(' ')?;
+        let addr = addr.trim();
+        let addr = u16::from_str_radix(addr.strip_prefix("0x").unwrap_or(addr), 16).ok()?;
+        parts.push(match kind {
+            "byte" => Part::Byte(addr),
+            "word" => Part::Word(addr),
+            "hex" => Part::Hex(addr),
+            _ => return None,
+        });
+        rest = &rest[end + 1..];
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Literal(rest.to_string()));
+    }
+    Some(parts)
+}
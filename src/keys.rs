@@ -0,0 +1,71 @@
+// Host inputs named independently of the frontend that delivers them, so
+// key bindings mean the same thing whichever library reads the input:
+//
+//   Key0..Key9, KeyA..KeyZ    keyboard keys, by their position on a US layout
+//   Gamepad:South, East, West, North, Up, Down, Left, Right, Start, Back
+//   Touch:Pad0..Touch:PadF    on-screen keypad buttons
+//
+// Bindings are written NAME=HEX, like KeyQ=4, and press that hex key.
+
+const GAMEPAD_BUTTONS: [&str; 10] = [
+    "South", "East", "West", "North", "Up", "Down", "Left", "Right", "Start", "Back",
+];
+
+// Every valid input name.
+pub fn names() -> Vec<String> {
+    let keys = ('0'..='9').chain('A'..='Z').map(|c| format!("Key{}", c));
+    let buttons = GAMEPAD_BUTTONS.iter().map(|b| format!("Gamepad:{}", b));
+    let pads = (0..16).map(|n| format!("Touch:Pad{:X}", n));
+    keys.chain(buttons).chain(pads).collect()
+}
+
+// Parse NAME=HEX into the input name and the hex key it presses.
+pub fn parse_binding(text: &str) -> Result<(String, usize), String> {
+    let (name, key) = text
+        .split_once('=')
+        .ok_or_else(|| format!("Invalid key binding '{}' (expected NAME=HEX)", text))?;
+    let key = match u8::from_str_radix(key, 16) {
+        Ok(key) if key < 16 => key as usize,
+        _ => return Err(format!("Invalid hex key '{}' in '{}'", key, text)),
+    };
+
+    let names = names();
+    if names.iter().any(|n| n == name) {
+        return Ok((name.to_string(), key));
+    }
+    match closest(name, &names) {
+        Some(near) => Err(format!("Unknown input '{}', did you mean {}?", name, near)),
+        None => Err(format!(
+            "Unknown input '{}' (expected KeyQ, Key1, Gamepad:South, Touch:Pad5 and the like)",
+            name
+        )),
+    }
+}
+
+// The name within a couple of typos of `name`, ignoring case, if any.
+fn closest<'a>(name: &str, names: &'a [String]) -> Option<&'a str> {
+    let name = name.to_ascii_lowercase();
+    names
+        .iter()
+        .map(|n| (distance(&name, &n.to_ascii_lowercase()), n))
+        .filter(|&(d, _)| d <= 2)
+        .min_by_key(|&(d, _)| d)
+        .map(|(_, n)| n.as_str())
+}
+
+// Levenshtein distance: single-character insertions, deletions and
+// substitutions needed to turn `a` into `b`.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitute = diagonal + (ca != cb) as usize;
+            diagonal = row[j + 1];
+            row[j + 1] = substitute.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
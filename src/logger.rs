@@ -0,0 +1,98 @@
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+const LOG_NAME: &str = "chip8.log";
+const MAX_LOG_SIZE: u64 = 1024 * 1024;
+const KEPT_LOGS: usize = 3;
+
+static LOG_FILE: Mutex<Option<LogFile>> = Mutex::new(None);
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+// Send log output to a rotating file in `dir` instead of stderr.
+pub fn init_file(dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(LOG_NAME);
+    if fs::metadata(&path).map(|m| m.len()).unwrap_or(0) >= MAX_LOG_SIZE {
+        rotate(&path)?;
+    }
+
+    let file = OpenOptions::new().create(true).append(true).open(&path)?;
+    let size = file.metadata()?.len();
+    *LOG_FILE.lock().unwrap() = Some(LogFile { path, file, size });
+    Ok(())
+}
+
+// Also log the details verbose! is for: actions, run state changes and
+// what each ROM was loaded with.
+pub fn set_verbose(on: bool) {
+    VERBOSE.store(on, Ordering::Relaxed);
+}
+
+pub fn verbose() -> bool {
+    VERBOSE.load(Ordering::Relaxed)
+}
+
+pub fn write(args: fmt::Arguments) {
+    let mut log_file = LOG_FILE.lock().unwrap();
+    let log = match log_file.as_mut() {
+        Some(log) => log,
+        None => {
+            eprintln!("{}", args);
+            return;
+        }
+    };
+
+    let line = format!("{}\n", args);
+    if log.file.write_all(line.as_bytes()).is_err() {
+        eprint!("{}", line);
+        return;
+    }
+
+    log.size += line.len() as u64;
+    if log.size >= MAX_LOG_SIZE && rotate(&log.path).is_ok() {
+        if let Ok(file) = File::create(&log.path) {
+            log.file = file;
+            log.size = 0;
+        }
+    }
+}
+
+// chip8.log -> chip8.log.1 -> chip8.log.2 ..., dropping the oldest.
+fn rotate(path: &Path) -> io::Result<()> {
+    let numbered = |n: usize| PathBuf::from(format!("{}.{}", path.display(), n));
+
+    for n in (1..KEPT_LOGS).rev() {
+        if numbered(n).exists() {
+            fs::rename(numbered(n), numbered(n + 1))?;
+        }
+    }
+    fs::rename(path, numbered(1))
+}
+
+macro_rules! log {
+    ($($arg:tt)*) => {
+        $crate::logger::write(format_args!($($arg)*))
+    };
+}
+
+macro_rules! verbose {
+    ($($arg:tt)*) => {
+        if $crate::logger::verbose() {
+            log!($($arg)*)
+        }
+    };
+}
@@ -1,24 +1,458 @@
-extern crate rand;
+// Release builds on Windows run without a console window; logs go to a file.
+#![cfg_attr(all(windows, not(debug_assertions)), windows_subsystem = "windows")]
+
 extern crate sdl2;
 
-use std::fs;
+#[macro_use]
+mod logger;
 
-mod chip8;
+mod action;
+mod capture;
+mod commands;
+mod config;
+mod crash;
 mod display;
-mod fontset;
+mod download;
+mod emulator;
+mod exit;
+mod filter;
+mod hud;
+mod keys;
+mod pacing;
+mod paths;
+mod romconfig;
+mod sha1;
+mod sharedmem;
+mod stick;
+mod text;
+
+use std::panic::{self, AssertUnwindSafe};
+
+use exit::Failure;
+
+// The machine and the headless tools live in their own crates so they can
+// be used without SDL.
+use chip8_core::{
+    addr, chip8, fontset, framebuffer, input, memory, opstats, quirks, rng, savestate, timers,
+};
+use chip8_tools::{
+    audit, bisect, diff, fuzz, movie, palette, png, preview, render, romheader, statehash, testroms,
+};
+
+// Frames each mutant runs for with --fuzz.
+const FUZZ_FRAMES: u64 = 600;
+// Frames the two builds are compared for with --diff.
+const DIFF_FRAMES: u64 = 3600;
+// Every how many frames the preview command takes a thumbnail.
+const PREVIEW_STRIDE: usize = 30;
+// Frames replayed for each quirk setting with --bisect-quirks.
+const BISECT_FRAMES: u64 = 120;
 
 fn main() {
-    let mut chip8 = chip8::Chip8::new(&fontset::FONT_SET);
-    let path = std::env::args().nth(1);
-    if path.is_none() {
-        panic!("No game defined!");
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("cache") {
+        if let Err(e) = cache_command(&args[1..]) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if args.first().map(String::as_str) == Some("render") {
+        if let Err(e) = render_command(&args[1..]) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if args.first().map(String::as_str) == Some("library") {
+        if let Err(e) = library_command(&args[1..]) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if args.first().map(String::as_str) == Some("preview") {
+        if let Err(e) = preview_command(&args[1..]) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if args.first().map(String::as_str) == Some("bisect-support") {
+        // exit codes are git bisect run's: 0 good, 1 bad, 125 skip
+        match bisect_support_command(&args[1..]) {
+            Ok(true) => return,
+            Ok(false) => std::process::exit(1),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(125);
+            }
+        }
+    }
+    if args.first().map(String::as_str) == Some("quirk-roms") {
+        if let Err(e) = quirk_roms_command(&args[1..]) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // wanted before the rest of the command line is known to be valid
+    let json_errors = args.iter().any(|arg| arg == "--json-errors");
+    let config = match config::Config::from_args(args.into_iter()) {
+        Ok(config) => config,
+        Err(e) => exit::fail(Failure::General, &e, json_errors),
+    };
+
+    if config.log_to_file {
+        if let Err(e) = logger::init_file(&paths::data_dir()) {
+            eprintln!("Could not open log file: {}", e);
+        }
+    }
+    logger::set_verbose(config.verbose);
+    if config.safe_mode {
+        log!("Safe mode: plain CHIP-8, default colors, no filters, HUD or saved ROM settings");
     }
 
-    let data = fs::read(path.unwrap());
-    if data.is_err() {
-        panic!("Game not found!");
+    if let Some(frames) = config.audit_frames {
+        let rom = read_rom(&config);
+        let seed = rng::Rng::from_entropy().next_u64();
+        match audit::check_determinism(&rom, config.quirks, frames, seed) {
+            Ok(()) => log!("{} frames matched (seed {})", frames, seed),
+            Err(e) => exit::fail(
+                Failure::General,
+                &format!("Nondeterminism detected: {}", e),
+                config.json_errors,
+            ),
+        }
+        return;
     }
 
-    chip8.load_rom(&data.unwrap());
-    chip8.start();
+    if let Some(start_frame) = config.bisect_frame {
+        let rom = read_rom(&config);
+        let seed = rng::Rng::from_entropy().next_u64();
+        log!(
+            "Replaying frames {}..{} (seed {})",
+            start_frame,
+            start_frame + BISECT_FRAMES,
+            seed
+        );
+        for effect in bisect::bisect_quirks(&rom, config.quirks, start_frame, BISECT_FRAMES, seed) {
+            let outcome = if effect.changes_outcome {
+                "changes the screen"
+            } else {
+                "no effect"
+            };
+            log!("{:<20} {:<30} {}", effect.name, effect.setting, outcome);
+        }
+        return;
+    }
+
+    if let Some(other) = &config.diff_rom {
+        let a = read_rom(&config);
+        let b = std::fs::read(other).unwrap_or_else(|e| {
+            exit::fail(
+                Failure::Rom,
+                &format!("{}: {}", other, e),
+                config.json_errors,
+            )
+        });
+        let seed = rng::Rng::from_entropy().next_u64();
+        match diff::compare(&a, &b, config.quirks, DIFF_FRAMES, seed) {
+            None => log!("Screens matched for {} frames (seed {})", DIFF_FRAMES, seed),
+            Some(difference) => {
+                let image = &difference.image;
+                let png = png::encode(image.width as u32, image.height as u32, &image.rgb);
+                let out = format!("diff-frame-{}.png", difference.frame);
+                if let Err(e) = std::fs::write(&out, png) {
+                    log!("Could not write {}: {}", out, e);
+                }
+                let message = format!(
+                    "Screens differ at frame {} in {} pixels (seed {}), see {}",
+                    difference.frame, difference.pixels, seed, out
+                );
+                exit::fail(Failure::General, &message, config.json_errors);
+            }
+        }
+        return;
+    }
+
+    if let Some(mutants) = config.fuzz_mutants {
+        let rom = read_rom(&config);
+        let seed = rng::Rng::from_entropy().next_u64();
+        match fuzz::fuzz(&rom, config.quirks, mutants, FUZZ_FRAMES, seed) {
+            Ok(report) => log!(
+                "{} mutants survived (seed {}): {} faulted, {} halted",
+                report.mutants,
+                seed,
+                report.faulted,
+                report.halted
+            ),
+            Err(e) => exit::fail(
+                Failure::General,
+                &format!("Fuzzing failed: {}", e),
+                config.json_errors,
+            ),
+        }
+        return;
+    }
+
+    let mut chip8 = chip8::Chip8::new(&config.font, config.quirks);
+    chip8.set_host_ports(config.host_ports);
+    chip8.set_unknown_opcodes(config.unknown_opcodes);
+    chip8.set_runaway_streak(config.unknown_streak);
+    chip8.set_ram_size(config.ram_size);
+    chip8.set_opcode_stats(config.opcode_stats.is_some());
+    let display = display::Display::new(&config)
+        .unwrap_or_else(|e| exit::fail(Failure::Sdl, &e, config.json_errors));
+    let mut emulator = emulator::Emulator::new(chip8, display, &config);
+    if let Some(path) = &config.shared_memory {
+        match sharedmem::SharedState::create(std::path::Path::new(path)) {
+            Ok(shared) => emulator.set_shared_state(shared),
+            Err(e) => exit::fail(
+                Failure::General,
+                &format!("Could not export state to {}: {}", path, e),
+                config.json_errors,
+            ),
+        }
+    }
+    // app bundles are launched without arguments, the ROM arrives as a dropped file
+    match &config.rom_path {
+        Some(url) if download::is_url(url) => emulator.load_rom_url(url),
+        Some(path) => {
+            if let Err(e) = emulator.load_rom_file(path) {
+                exit::fail(
+                    Failure::Rom,
+                    &format!("{}: {}", path, e),
+                    config.json_errors,
+                );
+            }
+        }
+        None => emulator.wait_for_rom(),
+    }
+    for path in config.tabs.iter() {
+        if let Err(e) = emulator.load_rom_file_in_tab(path) {
+            exit::fail(
+                Failure::Rom,
+                &format!("{}: {}", path, e),
+                config.json_errors,
+            );
+        }
+    }
+    emulator.show_first_tab();
+
+    // the emulator only panics on internal errors, so collect what is needed to reproduce them
+    crash::install_hook();
+    if panic::catch_unwind(AssertUnwindSafe(|| emulator.run())).is_err() {
+        let report = crash::write_report(
+            &paths::data_dir(),
+            &emulator.crash_context(),
+            &format!("{:?}", config),
+        );
+        if let Ok(path) = &report {
+            log!("Crash report written to {}", path.display());
+        }
+        crash::show_message(&report);
+        std::process::exit(101);
+    }
+
+    if config.summary {
+        log!("{}", emulator.summary());
+    }
+    if let (Some(path), Some(stats)) = (&config.opcode_stats, emulator.opcode_stats()) {
+        if let Err(e) = std::fs::write(path, stats.to_csv()) {
+            log!("Could not write opcode stats to {}: {}", path, e);
+        }
+    }
+    if let Some(fault) = emulator.fault() {
+        exit::fail(
+            Failure::Fault,
+            &format!("Program faulted: {}", fault),
+            config.json_errors,
+        );
+    }
+}
+
+// The ROM named on the command line, for the headless modes.
+fn read_rom(config: &config::Config) -> Vec<u8> {
+    let path = config
+        .rom_path
+        .as_deref()
+        .unwrap_or_else(|| exit::fail(Failure::Rom, "No game defined!", config.json_errors));
+    std::fs::read(path).unwrap_or_else(|e| {
+        exit::fail(
+            Failure::Rom,
+            &format!("{}: {}", path, e),
+            config.json_errors,
+        )
+    })
+}
+
+// `chip8-rs cache list|clear`: manage ROMs downloaded from URLs.
+fn cache_command(args: &[String]) -> Result<(), String> {
+    let data_dir = paths::data_dir();
+    match args.first().map(String::as_str) {
+        Some("list") => {
+            let entries = download::list(&data_dir).map_err(|e| e.to_string())?;
+            for entry in entries {
+                println!("{:>6}  {}", entry.size, entry.url);
+                println!("        {}", entry.path.display());
+            }
+            Ok(())
+        }
+        Some("clear") => download::clear(&data_dir).map_err(|e| e.to_string()),
+        _ => Err("Usage: chip8-rs cache list|clear".to_string()),
+    }
+}
+
+// `chip8-rs library list <dir>`: the ROMs in a directory by their saved titles.
+// `chip8-rs library edit <rom> key=value...`: change a ROM's saved title,
+// platform, tickrate or notes.
+fn library_command(args: &[String]) -> Result<(), String> {
+    let usage = || "Usage: chip8-rs library list <dir> | edit <rom> key=value...".to_string();
+    match args {
+        [command, dir] if command == "list" => {
+            let mut paths: Vec<_> = std::fs::read_dir(dir)
+                .map_err(|e| e.to_string())?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| path.is_file())
+                .collect();
+            paths.sort();
+            for path in paths {
+                let data = std::fs::read(&path).map_err(|e| e.to_string())?;
+                let saved =
+                    romconfig::RomConfig::load(&paths::data_dir(), &sha1::hex_digest(&data));
+                let header = saved.overlay(romheader::split(&data).1.unwrap_or_default());
+                let title = header.title.unwrap_or_else(|| {
+                    path.file_stem()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .into_owned()
+                });
+                let platform = header.platform.unwrap_or_else(|| "chip8".to_string());
+                match header.tickrate {
+                    Some(tickrate) => println!("{}  [{}, {} per frame]", title, platform, tickrate),
+                    None => println!("{}  [{}]", title, platform),
+                }
+                if let Some(notes) = saved.get("notes") {
+                    println!("    {}", notes);
+                }
+                println!("    {}", path.display());
+            }
+            Ok(())
+        }
+        [command, rom, edits @ ..] if command == "edit" && !edits.is_empty() => {
+            let data = std::fs::read(rom).map_err(|e| format!("{}: {}", rom, e))?;
+            let mut saved =
+                romconfig::RomConfig::load(&paths::data_dir(), &sha1::hex_digest(&data));
+            for edit in edits {
+                let (key, value) = edit
+                    .split_once('=')
+                    .ok_or_else(|| format!("Expected key=value, got '{}'", edit))?;
+                saved.edit(key.trim(), value.trim())?;
+            }
+            saved.save().map_err(|e| e.to_string())
+        }
+        _ => Err(usage()),
+    }
+}
+
+// `chip8-rs preview <rom> [--demo movie.c8m] --out sheet.png`: run the
+// ROM headlessly like a launcher preview would, driven by a demo movie if
+// given, and save thumbnails of it as a sprite sheet.
+fn preview_command(args: &[String]) -> Result<(), String> {
+    let usage = || "Usage: chip8-rs preview <rom> [--demo <movie.c8m>] --out <png>".to_string();
+    let (rom_path, demo_path, out) = match args {
+        [rom, flag, out] if flag == "--out" => (rom, None, out),
+        [rom, demo_flag, demo, flag, out] if demo_flag == "--demo" && flag == "--out" => {
+            (rom, Some(demo), out)
+        }
+        _ => return Err(usage()),
+    };
+
+    let rom = std::fs::read(rom_path).map_err(|e| format!("{}: {}", rom_path, e))?;
+    let demo = match demo_path {
+        Some(path) => {
+            let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+            Some(movie::Movie::parse(&text)?)
+        }
+        None => None,
+    };
+
+    let mut preview = preview::Preview::new(&rom, demo)?;
+    let mut thumbnails = Vec::new();
+    for frame in 0..preview::FRAMES {
+        let screen = preview.next_frame()?;
+        if frame % PREVIEW_STRIDE == PREVIEW_STRIDE - 1 {
+            thumbnails.push(preview::thumbnail(screen));
+        }
+    }
+    let sheet = capture::sprite_sheet(&thumbnails);
+    let png = png::encode(sheet.width as u32, sheet.height as u32, &sheet.rgb);
+    std::fs::write(out, png).map_err(|e| format!("{}: {}", out, e))
+}
+
+// `chip8-rs bisect-support game.ch8 run.c8m 600 [--expect HASH]`: replay
+// a movie headlessly and print a hash of the state it ends in, and nothing
+// else. With --expect, returns whether the hash matched, for `git bisect run`.
+fn bisect_support_command(args: &[String]) -> Result<bool, String> {
+    let usage = || {
+        "Usage: chip8-rs bisect-support <rom> <movie.c8m> <frames> [--expect <hash>]".to_string()
+    };
+    let (rom_path, movie_path, frames, expected) = match args {
+        [rom, movie, frames] => (rom, movie, frames, None),
+        [rom, movie, frames, flag, hash] if flag == "--expect" => (rom, movie, frames, Some(hash)),
+        _ => return Err(usage()),
+    };
+
+    let frames = frames
+        .parse()
+        .map_err(|_| format!("Invalid frame count '{}'", frames))?;
+    let rom = std::fs::read(rom_path).map_err(|e| format!("{}: {}", rom_path, e))?;
+    let text = std::fs::read_to_string(movie_path).map_err(|e| format!("{}: {}", movie_path, e))?;
+    let movie = movie::Movie::parse(&text)?;
+
+    let hash = format!("{:016x}", statehash::replay_hash(&movie, &rom, frames)?);
+    println!("{}", hash);
+    Ok(expected.is_none_or(|expected| expected.eq_ignore_ascii_case(&hash)))
+}
+
+// `chip8-rs quirk-roms <dir>`: write the quirk test ROMs, to run on other emulators.
+fn quirk_roms_command(args: &[String]) -> Result<(), String> {
+    let dir = args
+        .first()
+        .ok_or_else(|| "Usage: chip8-rs quirk-roms <dir>".to_string())?;
+    std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    for test in testroms::all() {
+        let path = std::path::Path::new(dir).join(format!("{}.ch8", test.name));
+        std::fs::write(&path, &test.rom).map_err(|e| e.to_string())?;
+        println!(
+            "{}  ({}: 1 = yes, 0 = no)",
+            path.display(),
+            test.description
+        );
+    }
+    Ok(())
+}
+
+// `chip8-rs render movie.c8m --out run.mp4`: replay a movie headlessly and encode it.
+fn render_command(args: &[String]) -> Result<(), String> {
+    let usage = || "Usage: chip8-rs render <movie.c8m> --out <video>".to_string();
+    let (movie_path, out) = match args {
+        [movie, flag, out] if flag == "--out" => (movie, out),
+        _ => return Err(usage()),
+    };
+
+    let text = std::fs::read_to_string(movie_path).map_err(|e| e.to_string())?;
+    let movie = movie::Movie::parse(&text)?;
+    // the ROM path is relative to the movie
+    let rom_path = std::path::Path::new(movie_path)
+        .parent()
+        .unwrap_or_else(|| std::path::Path::new(""))
+        .join(&movie.rom);
+    let rom = std::fs::read(&rom_path).map_err(|e| format!("{}: {}", rom_path.display(), e))?;
+
+    let frames = render::render(&movie, &rom, std::path::Path::new(out))?;
+    println!("Rendered {} frames to {}", frames, out);
+    Ok(())
 }
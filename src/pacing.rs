@@ -0,0 +1,81 @@
+use std::time::{Duration, Instant};
+
+// How the run loop waits for the next frame. OS sleeps can overshoot by a
+// millisecond or more (15 ms on Windows without --high-res-timer), so
+// spinning trades CPU time for evenly spaced frames.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pacing {
+    // Sleep the whole wait. Cheapest, least precise.
+    Sleep,
+    // Busy-wait the whole wait. Precise, keeps a core busy.
+    Spin,
+    // Sleep, then busy-wait the last SPIN_MARGIN.
+    Hybrid,
+}
+
+// How much of a hybrid wait is spent spinning.
+const SPIN_MARGIN: Duration = Duration::from_millis(1);
+
+impl Pacing {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "sleep" => Some(Pacing::Sleep),
+            "spin" => Some(Pacing::Spin),
+            "hybrid" => Some(Pacing::Hybrid),
+            _ => None,
+        }
+    }
+
+    // Return at `deadline`, or right away if it has passed.
+    pub fn wait_until(self, deadline: Instant) {
+        let now = Instant::now();
+        if deadline <= now {
+            return;
+        }
+
+        let sleep = match self {
+            Pacing::Sleep => deadline - now,
+            Pacing::Spin => Duration::from_secs(0),
+            Pacing::Hybrid => (deadline - now).saturating_sub(SPIN_MARGIN),
+        };
+        if sleep > Duration::from_secs(0) {
+            std::thread::sleep(sleep);
+        }
+        while Instant::now() < deadline {
+            std::hint::spin_loop();
+        }
+    }
+}
+
+// Raises the OS timer resolution to 1 ms while alive, so sleeps on Windows
+// wake up close to when they were asked to. Elsewhere sleeps are already
+// that precise and this does nothing.
+pub struct HighResTimer(());
+
+impl HighResTimer {
+    pub fn start() -> Self {
+        #[cfg(windows)]
+        unsafe {
+            winmm::timeBeginPeriod(1);
+        }
+        HighResTimer(())
+    }
+}
+
+impl Drop for HighResTimer {
+    fn drop(&mut self) {
+        #[cfg(windows)]
+        unsafe {
+            winmm::timeEndPeriod(1);
+        }
+    }
+}
+
+#[cfg(windows)]
+mod winmm {
+    #[link(name = "winmm")]
+    extern "system" {
+        pub fn timeBeginPeriod(period: u32) -> u32;
+        pub fn timeEndPeriod(period: u32) -> u32;
+    }
+}
@@ -0,0 +1,27 @@
+use std::{env, path::PathBuf};
+
+const APP_DIR: &str = "chip8-rs";
+
+// Per-user directory for logs and other files the emulator writes.
+pub fn data_dir() -> PathBuf {
+    if cfg!(windows) {
+        if let Some(appdata) = env::var_os("APPDATA") {
+            return PathBuf::from(appdata).join(APP_DIR);
+        }
+    } else if cfg!(target_os = "macos") {
+        if let Some(home) = env::var_os("HOME") {
+            return PathBuf::from(home)
+                .join("Library/Application Support")
+                .join(APP_DIR);
+        }
+    } else {
+        if let Some(data_home) = env::var_os("XDG_DATA_HOME") {
+            return PathBuf::from(data_home).join(APP_DIR);
+        }
+        if let Some(home) = env::var_os("HOME") {
+            return PathBuf::from(home).join(".local/share").join(APP_DIR);
+        }
+    }
+
+    PathBuf::from(".").join(APP_DIR)
+}
@@ -0,0 +1,103 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::quirks;
+use crate::romheader::{self, RomHeader};
+
+// The library metadata that can be edited for a ROM: what the launcher list
+// shows and how the game starts.
+pub const LIBRARY_KEYS: [&str; 4] = ["title", "platform", "tickrate", "notes"];
+
+// Settings the user picked for one ROM, kept in the data directory under the
+// ROM's SHA-1 so they follow the game around regardless of its file name.
+// The file holds key=value lines, using the same keys as ROM settings blocks.
+pub struct RomConfig {
+    path: PathBuf,
+    values: BTreeMap<String, String>,
+}
+
+impl RomConfig {
+    // Load the settings for the ROM with hash `rom_hash`. A missing or
+    // unreadable file just means nothing has been saved yet.
+    pub fn load(data_dir: &Path, rom_hash: &str) -> Self {
+        let path = data_dir.join("roms").join(format!("{}.cfg", rom_hash));
+        let values = fs::read_to_string(&path)
+            .map(|text| {
+                text.lines()
+                    .filter_map(|line| line.split_once('='))
+                    .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self { path, values }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(|v| v.as_str())
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) {
+        self.values.insert(key.to_string(), value.to_string());
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.values.remove(key);
+    }
+
+    // Change one of the LIBRARY_KEYS, checking the value the way a ROM
+    // settings block would be read. An empty value forgets the setting.
+    pub fn edit(&mut self, key: &str, value: &str) -> Result<(), String> {
+        if !LIBRARY_KEYS.contains(&key) {
+            return Err(format!(
+                "Unknown setting '{}', expected one of {}",
+                key,
+                LIBRARY_KEYS.join(", ")
+            ));
+        }
+        if value.is_empty() {
+            self.remove(key);
+            return Ok(());
+        }
+        match key {
+            "platform" if !quirks::PLATFORMS.contains(&value) => {
+                return Err(format!("Unknown platform '{}'", value))
+            }
+            "tickrate" if romheader::parse_tickrate(value).is_none() => {
+                return Err(format!("Invalid tickrate '{}'", value))
+            }
+            _ => {}
+        }
+        self.set(key, value);
+        Ok(())
+    }
+
+    // The ROM's own settings block with the user's edits on top.
+    pub fn overlay(&self, mut header: RomHeader) -> RomHeader {
+        if let Some(title) = self.get("title") {
+            header.title = Some(title.to_string());
+        }
+        if let Some(platform) = self.get("platform") {
+            header.platform = Some(platform.to_string());
+        }
+        if let Some(tickrate) = self.get("tickrate").and_then(romheader::parse_tickrate) {
+            header.tickrate = Some(tickrate);
+        }
+        header
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text: String = self
+            .values
+            .iter()
+            .map(|(key, value)| format!("{}={}\n", key, value))
+            .collect();
+        fs::write(&self.path, text)
+    }
+}
@@ -0,0 +1,125 @@
+use std::{
+    fs::{self, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{fence, Ordering},
+};
+
+use memmap2::MmapMut;
+
+use crate::chip8::{Chip8, HEIGHT, WIDTH};
+
+// Live machine state in a memory-mapped file, rewritten every presented
+// frame, for visualizers that want it without going through a socket.
+// All numbers are little-endian:
+//
+//   offset  size
+//        0     4  "C8SM"
+//        4     4  sequence: odd while a frame is being written, even after
+//        8     4  frames written so far
+//       12     2  pc
+//       14     2  I
+//       16     1  sp
+//       17     1  DT
+//       18     1  ST
+//       19     1  hires
+//       20    16  V0..VF
+//       36     2  vram width
+//       38     2  vram height
+//       40  8192  vram, row by row, one byte of plane bits per pixel
+//
+// Readers copy what they need and check the sequence is the same even
+// number before and after. Once the file is mapped, a handshake file next
+// to it (<path>.info) gives the layout version, the file size and the
+// emulator's process id; it is removed when the emulator exits.
+const MAGIC: &[u8; 4] = b"C8SM";
+const VERSION: u32 = 1;
+const VRAM_OFFSET: usize = 40;
+const SIZE: usize = VRAM_OFFSET + WIDTH * HEIGHT;
+
+pub struct SharedState {
+    map: MmapMut,
+    info_path: PathBuf,
+    sequence: u32,
+    frames: u32,
+}
+
+impl SharedState {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.set_len(SIZE as u64)?;
+        // SAFETY: the file is ours for as long as the emulator runs; other
+        // processes are expected to only read it
+        let mut map = unsafe { MmapMut::map_mut(&file)? };
+        map[..4].copy_from_slice(MAGIC);
+
+        let mut info_path = path.as_os_str().to_owned();
+        info_path.push(".info");
+        let info_path = PathBuf::from(info_path);
+        fs::write(
+            &info_path,
+            format!(
+                "chip8-rs shared state {}\nsize {}\npid {}\n",
+                VERSION,
+                SIZE,
+                process::id()
+            ),
+        )?;
+
+        Ok(Self {
+            map,
+            info_path,
+            sequence: 0,
+            frames: 0,
+        })
+    }
+
+    // Write the machine's current state.
+    pub fn update(&mut self, chip8: &Chip8) {
+        self.sequence = self.sequence.wrapping_add(1);
+        self.put(4, &self.sequence.to_le_bytes());
+        fence(Ordering::Release);
+
+        self.frames = self.frames.wrapping_add(1);
+        self.put(8, &self.frames.to_le_bytes());
+        let cpu = chip8.cpu_state();
+        self.put(12, &(cpu.pc.index() as u16).to_le_bytes());
+        self.put(14, &(cpu.ir.index() as u16).to_le_bytes());
+        self.put(
+            16,
+            &[
+                cpu.sp.depth() as u8,
+                cpu.delay_timer,
+                cpu.sound_timer,
+                chip8.hires() as u8,
+            ],
+        );
+        self.put(20, &cpu.registers);
+        let vram = chip8.vram();
+        self.put(36, &(vram.width() as u16).to_le_bytes());
+        self.put(38, &(vram.height() as u16).to_le_bytes());
+        let pixels = vram.pixels();
+        let len = pixels.len().min(SIZE - VRAM_OFFSET);
+        self.put(VRAM_OFFSET, &pixels[..len]);
+
+        fence(Ordering::Release);
+        self.sequence = self.sequence.wrapping_add(1);
+        self.put(4, &self.sequence.to_le_bytes());
+    }
+
+    fn put(&mut self, offset: usize, bytes: &[u8]) {
+        self.map[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+}
+
+impl Drop for SharedState {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.info_path);
+    }
+}
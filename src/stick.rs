@@ -0,0 +1,65 @@
+use std::convert::TryInto;
+
+// Turns a gamepad's left analog stick into hex keypad presses, since most
+// CHIP-8 games steer with a group of keys like 2/4/6/8 or 5/7/8/9.
+#[derive(Clone, Copy, Debug)]
+pub struct StickMapping {
+    // hex keys for up, left, right and down
+    pub keys: [usize; 4],
+    // how far the stick has to move before anything is pressed, 0.0 to 1.0
+    pub dead_zone: f32,
+    // whether a stick held diagonally presses two keys
+    pub diagonals: bool,
+}
+
+// A diagonal presses both keys once the smaller axis is past this fraction
+// of the stick's deflection, which gives eight equal sectors.
+const DIAGONAL_THRESHOLD: f32 = 0.383; // sin(22.5°)
+
+impl Default for StickMapping {
+    fn default() -> Self {
+        Self {
+            keys: [0x2, 0x4, 0x6, 0x8],
+            dead_zone: 0.25,
+            diagonals: false,
+        }
+    }
+}
+
+impl StickMapping {
+    // Four hex digits for up, left, right and down, like "2468".
+    pub fn parse_keys(value: &str) -> Option<[usize; 4]> {
+        let digits: Vec<usize> = value
+            .chars()
+            .map(|c| c.to_digit(16).map(|d| d as usize))
+            .collect::<Option<_>>()?;
+        digits.try_into().ok()
+    }
+
+    // Keys held by the stick at `x`, `y`, each from -1.0 to 1.0 with y
+    // pointing down.
+    pub fn keys_for(&self, x: f32, y: f32) -> [bool; 16] {
+        let mut keys = [false; 16];
+        let deflection = (x * x + y * y).sqrt();
+        if deflection < self.dead_zone {
+            return keys;
+        }
+
+        let [up, left, right, down] = self.keys;
+        let horizontal = if x < 0.0 { left } else { right };
+        let vertical = if y < 0.0 { up } else { down };
+        if self.diagonals {
+            if x.abs() >= deflection * DIAGONAL_THRESHOLD {
+                keys[horizontal] = true;
+            }
+            if y.abs() >= deflection * DIAGONAL_THRESHOLD {
+                keys[vertical] = true;
+            }
+        } else if x.abs() >= y.abs() {
+            keys[horizontal] = true;
+        } else {
+            keys[vertical] = true;
+        }
+        keys
+    }
+}